@@ -0,0 +1,76 @@
+// Requires administrator rights: enumerates the MFT of a real volume.
+// Set RIVET_BENCH_DRIVE to benchmark a drive other than C:, by letter, mount folder
+// or GUID path.
+
+#[cfg(windows)]
+use {
+    criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput},
+    rivet::mft_enumerator::MftEnumerator,
+    rivet::mft_indexer::Indexer,
+    rivet::volume_manager::{indexable_volumes, VolumeInfo},
+    tokio_util::sync::CancellationToken,
+};
+
+#[cfg(windows)]
+fn bench_drive() -> VolumeInfo {
+    let key = std::env::var("RIVET_BENCH_DRIVE").unwrap_or_else(|_| "C".to_string());
+    indexable_volumes()
+        .into_iter()
+        .find(|volume| volume.matches(&key))
+        .expect("benchmark volume is not mounted")
+}
+
+#[cfg(windows)]
+fn enumerate(volume: &VolumeInfo, buffer_size: usize) -> usize {
+    let enumerator = MftEnumerator::new(volume, buffer_size).expect("failed to open volume");
+    enumerator.iter().filter(|entry| entry.is_ok()).count()
+}
+
+#[cfg(windows)]
+fn mft_enumeration(c: &mut Criterion) {
+    let volume = bench_drive();
+    let record_count = enumerate(&volume, 128 * 1024) as u64;
+
+    let mut group = c.benchmark_group("mft_enumeration");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(record_count));
+    for kb in [64, 128, 256, 512, 1024] {
+        group.bench_with_input(BenchmarkId::new("buffer_kb", kb), &kb, |b, &kb| {
+            b.iter(|| enumerate(&volume, kb * 1024));
+        });
+    }
+    group.finish();
+}
+
+// Sizes are only fetched once per record, so every iteration starts from a fresh index
+#[cfg(windows)]
+fn size_fetch(c: &mut Criterion) {
+    let volume = bench_drive();
+    let token = CancellationToken::new();
+
+    let mut group = c.benchmark_group("size_fetch");
+    group.sample_size(10);
+    for threads in [1, 4, 8, 16] {
+        group.bench_with_input(BenchmarkId::new("threads", threads), &threads, |b, &threads| {
+            b.iter_batched(
+                || {
+                    let indexer = Indexer::new();
+                    indexer.index_volume(&volume, 128 * 1024, &token).expect("failed to index volume");
+                    indexer
+                },
+                |indexer| indexer.fetch_sizes(volume.id, threads, &token),
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    group.finish();
+}
+
+#[cfg(windows)]
+criterion_group!(benches, mft_enumeration, size_fetch);
+#[cfg(windows)]
+criterion_main!(benches);
+
+// There's no MFT to read elsewhere
+#[cfg(not(windows))]
+fn main() {}
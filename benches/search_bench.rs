@@ -0,0 +1,250 @@
+// Runs against a synthetic in-memory index, so unlike mft_bench it needs no volume
+// access or administrator rights.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+#[cfg(windows)]
+use rivet::gui::{sort_ids, SortColumn};
+use rivet::mft_indexer::{record_key, FileRecord, Indexer, SearchQuery};
+use rivet::volume::VolumeId;
+
+const INDEX_SIZES: [usize; 3] = [10_000, 100_000, 500_000];
+const FOLDER_WORDS: &[&str] = &[
+    "Users", "Documents", "Projects", "Windows", "System32", "Program Files", "AppData",
+    "Local", "Temp", "Downloads", "Pictures", "src", "target", "node_modules", "Backup",
+];
+const FILE_WORDS: &[&str] = &[
+    "report", "invoice", "config", "main", "index", "setup", "readme", "photo", "draft",
+    "notes", "summary", "budget", "module", "library", "schedule",
+];
+// Every other file is a .txt, so "txt" matches about half of the records
+const OTHER_EXTENSIONS: &[&str] = &["rs", "log", "png", "dll", "exe", "json"];
+// One folder per this many records
+const FILES_PER_FOLDER: usize = 20;
+
+/// Builds an index of `size` records shaped like a Windows volume: a tree of folders
+/// with files named `<word>_<word>_<n>.<ext>`. Seeded so every run sees the same data.
+fn synthetic_index(size: usize) -> Indexer {
+    let mut rng = StdRng::seed_from_u64(0x5EED);
+    let indexer = Indexer::new();
+    let volume = VolumeId::from_letter('C');
+    let root = record_key(volume, 5);
+    let mut folders = vec![root];
+    indexer.insert_record(FileRecord {
+        id: root,
+        parent_id: root,
+        sequence: 5,
+        name: "C:".to_string(),
+        wide_name: None,
+        size: 0,
+        modified: 0,
+        created: 0,
+        accessed: 0,
+        is_dir: true,
+        is_reparse_point: false,
+        attributes: 0,
+        hash: None,
+        links: Vec::new(),
+    });
+
+    for n in 1..size as u64 {
+        let id = record_key(volume, 100 + n as u128);
+        let parent_id = *folders.choose(&mut rng).unwrap();
+        let modified = rng.gen_range(130_000_000_000_000_000..134_000_000_000_000_000i64);
+        let record = if (n as usize).is_multiple_of(FILES_PER_FOLDER) {
+            folders.push(id);
+            FileRecord {
+                id,
+                parent_id,
+                sequence: 1,
+                name: format!("{} {}", FOLDER_WORDS.choose(&mut rng).unwrap(), n),
+                wide_name: None,
+                size: 0,
+                modified,
+                created: 0,
+                accessed: 0,
+                is_dir: true,
+                is_reparse_point: false,
+                attributes: 0,
+                hash: None,
+                links: Vec::new(),
+            }
+        } else {
+            let ext = if n % 2 == 0 { "txt" } else { *OTHER_EXTENSIONS.choose(&mut rng).unwrap() };
+            FileRecord {
+                id,
+                parent_id,
+                sequence: 1,
+                name: format!(
+                    "{}_{}_{}.{}",
+                    FILE_WORDS.choose(&mut rng).unwrap(),
+                    FILE_WORDS.choose(&mut rng).unwrap(),
+                    n,
+                    ext
+                ),
+                wide_name: None,
+                size: rng.gen_range(0..1 << 30),
+                modified,
+                created: 0,
+                accessed: 0,
+                is_dir: false,
+                is_reparse_point: false,
+                attributes: 0,
+                hash: None,
+                links: Vec::new(),
+            }
+        };
+        indexer.insert_record(record);
+    }
+    indexer
+}
+
+const QUERIES: [(&str, &str); 4] = [
+    ("short", "bud"),
+    ("long", "budget_schedule"),
+    ("no_match", "zzqx"),
+    ("half_match", "txt"),
+];
+
+// The name index is never built, so `search` takes the full `records.iter()` scan
+fn search(c: &mut Criterion) {
+    bench_search(c, "search", false);
+}
+
+// Same queries answered through the trigram index
+fn search_indexed(c: &mut Criterion) {
+    bench_search(c, "search_indexed", true);
+}
+
+fn bench_search(c: &mut Criterion, name: &str, name_index: bool) {
+    let mut group = c.benchmark_group(name);
+    for size in INDEX_SIZES {
+        let indexer = synthetic_index(size);
+        if name_index {
+            indexer.build_name_index();
+        }
+        group.throughput(Throughput::Elements(size as u64));
+        for (kind, query) in QUERIES {
+            for case_sensitive in [false, true] {
+                let kind = if case_sensitive { format!("{}_case", kind) } else { kind.to_string() };
+                group.bench_with_input(BenchmarkId::new(kind, size), &query, |b, query| {
+                    b.iter(|| indexer.search(&SearchQuery::new(query, case_sensitive), usize::MAX));
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+// Sorting lives in the GUI, which only builds on Windows
+#[cfg(windows)]
+fn sort(c: &mut Criterion) {
+    let columns = [
+        SortColumn::Relevance,
+        SortColumn::Name,
+        SortColumn::Folder,
+        SortColumn::Path,
+        SortColumn::Size,
+        SortColumn::RecursiveSize,
+        SortColumn::Modified,
+    ];
+
+    let mut group = c.benchmark_group("sort");
+    group.sample_size(10);
+    for size in INDEX_SIZES {
+        let indexer = synthetic_index(size);
+        let mut ids: Vec<u128> = indexer.records.iter().map(|r| *r.key()).collect();
+        ids.shuffle(&mut StdRng::seed_from_u64(size as u64));
+        group.throughput(Throughput::Elements(size as u64));
+        for column in columns {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", column), size), &column, |b, &column| {
+                b.iter_batched_ref(
+                    || ids.clone(),
+                    |ids| sort_ids(&indexer, ids, column, true, None, "report", false),
+                    criterion::BatchSize::LargeInput,
+                );
+            });
+        }
+    }
+    group.finish();
+}
+
+// Save and load through a temp file, compressed and not. Uncompressed files can't be
+// loaded, so only compressed loads are timed. Sizes and the ratio are printed once.
+fn persistence(c: &mut Criterion) {
+    let dir = std::env::temp_dir();
+    let mut group = c.benchmark_group("persistence");
+    group.sample_size(10);
+    for size in INDEX_SIZES {
+        let indexer = synthetic_index(size);
+        let compressed = dir.join(format!("rivet_bench_{}.rivet.zst", size));
+        let raw = dir.join(format!("rivet_bench_{}.rivet", size));
+        indexer.save(&compressed, true).expect("failed to save index");
+        indexer.save(&raw, false).expect("failed to save index");
+        let compressed_bytes = std::fs::metadata(&compressed).unwrap().len();
+        let raw_bytes = std::fs::metadata(&raw).unwrap().len();
+        println!(
+            "{} records: {} bytes raw, {} bytes compressed ({:.1}x)",
+            size,
+            raw_bytes,
+            compressed_bytes,
+            raw_bytes as f64 / compressed_bytes as f64
+        );
+
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_function(BenchmarkId::new("save_compressed", size), |b| {
+            b.iter(|| indexer.save(&compressed, true).unwrap());
+        });
+        group.bench_function(BenchmarkId::new("save_raw", size), |b| {
+            b.iter(|| indexer.save(&raw, false).unwrap());
+        });
+        group.bench_function(BenchmarkId::new("load_compressed", size), |b| {
+            b.iter(|| Indexer::load(&compressed).unwrap());
+        });
+        let _ = std::fs::remove_file(&compressed);
+        let _ = std::fs::remove_file(&raw);
+    }
+    group.finish();
+}
+
+// Inserting a whole index one record at a time against `batch_insert`, which goes
+// parallel for batches this size
+fn batch_insert(c: &mut Criterion) {
+    let size = INDEX_SIZES[INDEX_SIZES.len() - 1];
+    let records = synthetic_index(size).snapshot();
+    let mut group = c.benchmark_group("batch_insert");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(size as u64));
+    group.bench_function(BenchmarkId::new("sequential", size), |b| {
+        b.iter_batched(
+            || records.clone(),
+            |records| {
+                let indexer = Indexer::new();
+                for record in records {
+                    indexer.insert_record(record);
+                }
+                indexer
+            },
+            criterion::BatchSize::LargeInput,
+        );
+    });
+    group.bench_function(BenchmarkId::new("parallel", size), |b| {
+        b.iter_batched(
+            || records.clone(),
+            |records| {
+                let indexer = Indexer::new();
+                indexer.batch_insert(records);
+                indexer
+            },
+            criterion::BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+#[cfg(windows)]
+criterion_group!(benches, search, search_indexed, sort, persistence, batch_insert);
+#[cfg(not(windows))]
+criterion_group!(benches, search, search_indexed, persistence, batch_insert);
+criterion_main!(benches);
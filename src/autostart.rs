@@ -0,0 +1,34 @@
+use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegDeleteValueW, RegOpenKeyExW, RegSetValueExW, HKEY, HKEY_CURRENT_USER, KEY_SET_VALUE, REG_SZ,
+};
+use windows::core::HSTRING;
+
+const RUN_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+const VALUE_NAME: &str = "Rivet";
+
+/// Adds or removes Rivet from the current user's Run key.
+pub fn set_start_with_windows(enabled: bool) -> anyhow::Result<()> {
+    let command = format!("\"{}\"", std::env::current_exe()?.display());
+
+    let mut key = HKEY::default();
+    unsafe {
+        RegOpenKeyExW(HKEY_CURRENT_USER, &HSTRING::from(RUN_KEY), 0, KEY_SET_VALUE, &mut key).ok()?;
+    }
+
+    let result = if enabled {
+        // REG_SZ data is the NUL-terminated UTF-16 string as raw bytes
+        let bytes: Vec<u8> = command
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .flat_map(|unit| unit.to_le_bytes())
+            .collect();
+        unsafe { RegSetValueExW(key, &HSTRING::from(VALUE_NAME), 0, REG_SZ, Some(&bytes)).ok() }
+    } else {
+        let err = unsafe { RegDeleteValueW(key, &HSTRING::from(VALUE_NAME)) };
+        if err == ERROR_FILE_NOT_FOUND { Ok(()) } else { err.ok() }
+    };
+
+    unsafe { let _ = RegCloseKey(key); }
+    Ok(result?)
+}
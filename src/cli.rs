@@ -0,0 +1,98 @@
+use crate::config::Config;
+use crate::gui::format_filetime;
+use crate::mft_indexer::{estimate_record_count, Indexer, SearchQuery};
+use crate::volume_manager::indexable_volumes;
+use clap::Parser;
+use std::io::Write;
+use std::path::PathBuf;
+use tokio_util::sync::CancellationToken;
+use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+// Sortable and unambiguous, unlike the configurable GUI date format
+const LONG_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Parser, Debug)]
+#[command(name = "rivet", about = "Instant file search for Windows volumes")]
+pub struct Args {
+    /// Search without opening the window and print matching paths. Takes the same
+    /// syntax as the search box, e.g. `report ext:pdf dm:>=2024-01-01`.
+    #[arg(long, value_name = "QUERY")]
+    pub search: Option<String>,
+    /// Match names and paths case-sensitively
+    #[arg(long, requires = "search")]
+    pub case_sensitive: bool,
+    /// Only print files with this extension (repeatable)
+    #[arg(long, value_name = "EXT")]
+    pub ext: Vec<String>,
+    /// Stop after this many matches (defaults to the configured result limit)
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Print tab-separated path, size in bytes and modification time
+    #[arg(long)]
+    pub long: bool,
+    /// After indexing, save the index to this file
+    #[arg(long, value_name = "PATH")]
+    pub save_index: Option<PathBuf>,
+    /// Save the index without compression, for debugging. Such files can't be loaded.
+    #[arg(long, requires = "save_index")]
+    pub no_compress: bool,
+    /// Serve the HTTP search API on this loopback port, even if it is off in settings
+    #[arg(long, value_name = "PORT")]
+    pub http_port: Option<u16>,
+}
+
+/// Indexes the configured volumes, prints matches for `args.search` to stdout and
+/// returns whether anything matched. Diagnostics go to stderr so output can be piped.
+pub fn run_search(args: &Args) -> bool {
+    // Release builds use the GUI subsystem, so borrow the launching terminal's console.
+    // Redirected output works regardless since the handles are inherited.
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+
+    let Some(query) = &args.search else {
+        return false;
+    };
+    let config = Config::load();
+    let limit = args.limit.unwrap_or(config.result_limit);
+    let volumes: Vec<_> = indexable_volumes().into_iter()
+        .filter(|volume| config.volumes.iter().any(|key| volume.matches(key)))
+        .collect();
+    let indexer = estimate_record_count(&volumes).map_or_else(Indexer::new, Indexer::with_capacity);
+    let token = CancellationToken::new();
+
+    for volume in &volumes {
+        // Failures are logged by index_volume; search whatever did get indexed
+        let _ = indexer.index_volume(volume, config.mft_buffer_size(), &token);
+    }
+
+    if let Some(path) = &args.save_index {
+        if let Err(e) = indexer.save(path, !args.no_compress) {
+            tracing::error!(path = %path.display(), error = %e, "Failed to save index");
+        }
+    }
+
+    let mut search = SearchQuery::from_config(query, args.case_sensitive, &config);
+    search.extensions = args.ext.iter().map(|ext| ext.trim_start_matches('.').to_string()).collect();
+    let matches = indexer.search(&search, limit).ids;
+
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    for id in &matches {
+        let path = indexer.get_full_path(*id).path;
+        let result = if args.long {
+            indexer.fetch_size(*id);
+            let (size, modified) = indexer.records.get(id).map(|r| (r.size, r.modified)).unwrap_or_default();
+            writeln!(out, "{}\t{}\t{}", path, size, format_filetime(modified, LONG_DATE_FORMAT))
+        } else {
+            writeln!(out, "{}", path)
+        };
+        // The reader went away (e.g. `| head`), nothing left to do
+        if result.is_err() {
+            break;
+        }
+    }
+    let _ = out.flush();
+
+    !matches.is_empty()
+}
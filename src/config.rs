@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Lowest `monitor_poll_ms` honoured, so a typo can't turn the monitor into a busy loop
+pub const MIN_MONITOR_POLL_MS: u64 = 50;
+// `mft_buffer_kb` is kept within these, so the buffer always fits a whole USN record
+// and a typo can't allocate gigabytes
+const MIN_MFT_BUFFER_KB: usize = 4;
+const MAX_MFT_BUFFER_KB: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Volumes to index and monitor, by drive letter (`D`), mount folder (`C:\Data`) or
+    // GUID path (`\\?\Volume{...}\`), see `VolumeInfo::matches`
+    pub volumes: Vec<String>,
+    // Also index drives that are plugged in while Rivet is running
+    pub index_new_volumes: bool,
+    // Volumes unchecked in settings, which stay unindexed even when plugged in while running
+    pub ignored_volumes: Vec<String>,
+    // Folders (matched as path prefixes) or `*`/`?` name patterns hidden from results
+    pub excluded: Vec<String>,
+    pub result_limit: usize,
+    // chrono format string used for the Date Modified column
+    pub date_format: String,
+    // Include hidden/system files and NTFS metafiles in results
+    pub show_hidden: bool,
+    // Order digit runs in names and paths by value, so file2 sorts before file10
+    pub natural_sort: bool,
+    // Fetch every file size right after enumeration instead of leaving them at 0
+    pub fetch_sizes_eagerly: bool,
+    // Run indexing and the size fetch in background mode, so Windows throttles their disk I/O
+    pub low_priority_indexing: bool,
+    pub start_with_windows: bool,
+    // Hide to the tray when the window is closed, so the index stays current for the hotkey
+    pub close_to_tray: bool,
+    // Global shortcut that brings up the search window, e.g. "Alt+Space"; empty disables it
+    pub hotkey: String,
+    // Content search skips files larger than this, in KB
+    pub content_search_max_kb: u64,
+    // Extensions (without the dot) content search reads without sniffing for binary data
+    pub text_extensions: Vec<String>,
+    // Serve search results over HTTP on 127.0.0.1:`http_port`
+    pub http_api: bool,
+    pub http_port: u16,
+    // Size of the output buffer handed to FSCTL_ENUM_USN_DATA, in KB
+    pub mft_buffer_kb: usize,
+    // Worker threads for the size fetch pass; 0 uses one per core
+    pub size_fetch_threads: usize,
+    // How often the change monitor checks for file changes, in milliseconds. It checks
+    // more often while files are changing and less often while they aren't.
+    pub monitor_poll_ms: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            volumes: vec!["C".to_string()],
+            index_new_volumes: true,
+            ignored_volumes: Vec::new(),
+            excluded: Vec::new(),
+            result_limit: 10_000,
+            date_format: "%Y-%m-%d %H:%M".to_string(),
+            natural_sort: true,
+            show_hidden: false,
+            fetch_sizes_eagerly: true,
+            low_priority_indexing: false,
+            start_with_windows: false,
+            close_to_tray: true,
+            hotkey: "Alt+Space".to_string(),
+            content_search_max_kb: 1024,
+            text_extensions: [
+                "txt", "md", "log", "csv", "json", "xml", "yaml", "yml", "toml", "ini", "cfg",
+                "html", "css", "js", "ts", "rs", "py", "c", "h", "cpp", "cs", "java", "go", "ps1", "bat",
+            ].map(String::from).to_vec(),
+            http_api: false,
+            http_port: 7797,
+            mft_buffer_kb: 128,
+            size_fetch_threads: 0,
+            monitor_poll_ms: 500,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Rivet").join("config.json"))
+    }
+
+    /// Reads the config file, falling back to defaults if it is missing or unreadable.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path().ok_or_else(|| anyhow::anyhow!("APPDATA is not set"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn mft_buffer_size(&self) -> usize {
+        self.mft_buffer_kb.clamp(MIN_MFT_BUFFER_KB, MAX_MFT_BUFFER_KB) * 1024
+    }
+
+    pub fn monitor_poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.monitor_poll_ms.max(MIN_MONITOR_POLL_MS))
+    }
+
+    /// Whether a result should be hidden by the `excluded` list. Entries containing `*`
+    /// or `?` are matched against the file name, anything else is treated as a folder.
+    pub fn is_excluded(&self, full_path: &str, name: &str) -> bool {
+        is_excluded(&self.excluded, full_path, name)
+    }
+}
+
+/// Whether any of the `excluded` entries hides this file, see `Config::is_excluded`.
+pub fn is_excluded(excluded: &[String], full_path: &str, name: &str) -> bool {
+    excluded.iter().any(|pattern| {
+        if pattern.contains(['*', '?']) {
+            wildcard_match(&pattern.to_lowercase(), &name.to_lowercase())
+        } else {
+            let folder = pattern.trim_end_matches('\\').to_lowercase();
+            let path = full_path.to_lowercase();
+            path == folder || path.starts_with(&format!("{}\\", folder))
+        }
+    })
+}
+
+/// Matches `text` against a pattern where `*` is any run of characters and `?` is one.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it is currently absorbing up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,117 @@
+use crate::config::Config;
+use crate::mft_indexer::Indexer;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+// Bytes read to decide whether a file without a known text extension is binary
+const SNIFF_LEN: usize = 512;
+// Files are only read once a search has gone this long without being replaced, so
+// typing a query doesn't read everything again for each keystroke
+const START_DELAY: Duration = Duration::from_millis(300);
+
+/// How many times `needle` (already lowercased) occurs in the file, ignoring case.
+/// None for files over `max_bytes`, binary files and anything unreadable. Text that
+/// isn't valid UTF-8 (Latin-1, a stray bad byte) is still searched, with the invalid
+/// bytes replaced.
+fn count_matches(path: &Path, needle: &str, max_bytes: u64, known_text: bool) -> Option<usize> {
+    let mut file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() > max_bytes {
+        return None;
+    }
+    if !known_text {
+        let mut head = [0u8; SNIFF_LEN];
+        let read = file.read(&mut head).ok()?;
+        if head[..read].contains(&0) {
+            return None;
+        }
+        file.seek(SeekFrom::Start(0)).ok()?;
+    }
+    let mut content = Vec::new();
+    file.read_to_end(&mut content).ok()?;
+    let count = String::from_utf8_lossy(&content).to_lowercase().matches(needle).count();
+    (count > 0).then_some(count)
+}
+
+/// Searches the contents of a set of files on a background thread, reading them in
+/// parallel on the rayon pool. Poll `finished` each frame; dropping it stops the search,
+/// and one dropped within `START_DELAY` never reads anything.
+pub struct ContentSearch {
+    pub total: usize,
+    done: Arc<AtomicUsize>,
+    token: CancellationToken,
+    rx: Receiver<HashMap<u128, usize>>,
+}
+
+impl ContentSearch {
+    /// Starts looking for `query` in the files among `ids`, skipping folders and files
+    /// over the configured size limit.
+    pub fn start(indexer: Arc<Indexer>, ids: Vec<u128>, query: &str, config: &Config) -> Self {
+        let (tx, rx) = channel();
+        let done = Arc::new(AtomicUsize::new(0));
+        let token = CancellationToken::new();
+        let total = ids.len();
+
+        let needle = query.to_lowercase();
+        let max_bytes = config.content_search_max_kb * 1024;
+        let text_extensions = config.text_extensions.clone();
+        let thread_done = Arc::clone(&done);
+        let thread_token = token.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(START_DELAY);
+            if thread_token.is_cancelled() {
+                return;
+            }
+            let matches = ids.par_iter()
+                .filter_map(|&id| {
+                    thread_done.fetch_add(1, Ordering::Relaxed);
+                    if thread_token.is_cancelled() {
+                        return None;
+                    }
+                    let (is_dir, name) = indexer.records.get(&id).map(|r| (r.is_dir, r.name.clone()))?;
+                    if is_dir {
+                        return None;
+                    }
+                    let known_text = name.rsplit_once('.')
+                        .is_some_and(|(_, ext)| text_extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+                    let full_path = indexer.get_full_path(id);
+                    if !full_path.complete {
+                        return None;
+                    }
+                    let path = full_path.native();
+                    count_matches(Path::new(&path), &needle, max_bytes, known_text).map(|count| (id, count))
+                })
+                .collect();
+            if !thread_token.is_cancelled() {
+                let _ = tx.send(matches);
+            }
+        });
+
+        Self { total, done, token, rx }
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.done.load(Ordering::Relaxed) as f32 / self.total as f32
+    }
+
+    /// Matching ids and their match counts once every file has been read.
+    pub fn finished(&self) -> Option<HashMap<u128, usize>> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for ContentSearch {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
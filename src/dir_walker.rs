@@ -0,0 +1,337 @@
+use crate::mft_enumerator::{MftEntry, VolumeEnumerator};
+use crate::ntfs::{USN_REASON_DATA_OVERWRITE, USN_REASON_FILE_CREATE, USN_REASON_FILE_DELETE, USN_REASON_RENAME_NEW_NAME};
+use crate::usn_monitor::{ChangeEvent, ChangeSource, JournalLost};
+use crate::volume_manager::VolumeInfo;
+use sha2::{Digest, Sha256};
+use std::fs::{self, ReadDir};
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::os::windows::fs::MetadataExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{debug, warn};
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, ReadDirectoryChangesW, FILE_ACTION_ADDED, FILE_ACTION_MODIFIED, FILE_ACTION_REMOVED,
+    FILE_ACTION_RENAMED_NEW_NAME, FILE_ACTION_RENAMED_OLD_NAME, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT,
+    FILE_FLAG_BACKUP_SEMANTICS, FILE_LIST_DIRECTORY, FILE_NOTIFY_CHANGE_ATTRIBUTES, FILE_NOTIFY_CHANGE_DIR_NAME,
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SIZE, FILE_NOTIFY_INFORMATION,
+    FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows::Win32::System::IO::CancelSynchronousIo;
+use windows::core::HSTRING;
+
+// Notification bytes read per ReadDirectoryChangesW call
+const NOTIFY_BUFFER_SIZE: usize = 64 * 1024;
+// How often `drop` retries cancelling the watch thread's read until it has exited
+const CANCEL_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A stable file ID for a volume without one of its own (FAT32, exFAT, network shares),
+/// derived from the path. Paths compare case-insensitively like the volumes do. The top
+/// bit is set so the ID is treated as a full 128-bit one, see `fid_to_mft_index`.
+pub fn path_file_id(path: &str) -> u128 {
+    let hash = Sha256::digest(path.to_lowercase().as_bytes());
+    let id = u128::from_le_bytes(hash[..16].try_into().unwrap());
+    id | (1 << 127)
+}
+
+fn entry_from_metadata(path: &Path, parent_fid: u128, metadata: &fs::Metadata) -> MftEntry {
+    let attributes = metadata.file_attributes();
+    let file_name = path.file_name().unwrap_or_default();
+    MftEntry {
+        fid: path_file_id(&path.to_string_lossy()),
+        parent_fid,
+        name: file_name.to_string_lossy().into_owned(),
+        wide_name: file_name.to_str().is_none().then(|| file_name.encode_wide().collect()),
+        modified: metadata.last_write_time() as i64,
+        created: metadata.creation_time() as i64,
+        accessed: metadata.last_access_time() as i64,
+        size: if metadata.is_dir() { 0 } else { metadata.file_size() },
+        is_dir: (attributes & FILE_ATTRIBUTE_DIRECTORY.0) != 0,
+        is_reparse_point: (attributes & FILE_ATTRIBUTE_REPARSE_POINT.0) != 0,
+        attributes,
+    }
+}
+
+/// Indexes a volume by walking its folders, for file systems without an MFT. Much
+/// slower than `MftEnumerator`, but sizes come for free. Links are listed but not
+/// followed, and folders that can't be read are skipped.
+pub struct DirWalker {
+    // Folders still to read, with their IDs
+    pending: Vec<(PathBuf, u128)>,
+    current: Option<(ReadDir, u128)>,
+}
+
+impl DirWalker {
+    /// Walks the volume mounted at `root`, see `VolumeInfo::root`.
+    pub fn new(root: &str) -> Self {
+        Self::from_folder(PathBuf::from(root), path_file_id(root))
+    }
+
+    /// Walks everything beneath `folder` (but not `folder` itself).
+    pub fn from_folder(folder: PathBuf, fid: u128) -> Self {
+        Self { pending: vec![(folder, fid)], current: None }
+    }
+
+    /// The ID `DirWalker::new` gives the root folder at `root`.
+    pub fn root_fid(root: &str) -> u128 {
+        path_file_id(root)
+    }
+}
+
+impl Iterator for DirWalker {
+    type Item = anyhow::Result<MftEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((read_dir, parent_fid)) = &mut self.current {
+                match read_dir.next() {
+                    Some(Ok(entry)) => {
+                        // From the directory listing itself, so this costs no extra call
+                        let Ok(metadata) = entry.metadata() else {
+                            continue;
+                        };
+                        let path = entry.path();
+                        let entry = entry_from_metadata(&path, *parent_fid, &metadata);
+                        if entry.is_dir && !entry.is_reparse_point {
+                            self.pending.push((path, entry.fid));
+                        }
+                        return Some(Ok(entry));
+                    }
+                    Some(Err(e)) => {
+                        debug!(error = %e, "Skipping unreadable folder entry");
+                        continue;
+                    }
+                    None => self.current = None,
+                }
+            }
+
+            let (folder, fid) = self.pending.pop()?;
+            match fs::read_dir(&folder) {
+                Ok(read_dir) => self.current = Some((read_dir, fid)),
+                Err(e) => debug!(folder = %folder.display(), error = %e, "Skipping unreadable folder"),
+            }
+        }
+    }
+}
+
+impl VolumeEnumerator for DirWalker {
+    fn entries(&mut self) -> impl Iterator<Item = anyhow::Result<MftEntry>> + '_ {
+        self
+    }
+}
+
+/// Watches a volume without a USN journal with ReadDirectoryChangesW, turning its
+/// notifications into the same events the journal would give. The blocking reads run
+/// on a thread of their own, which owns the folder handle; dropping the source stops
+/// the thread and waits for it.
+pub struct DirChangeSource {
+    // Where the volume is mounted, with a trailing backslash
+    root: String,
+    rx: Receiver<Notifications>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+enum Notifications {
+    // The action and volume-relative path of each change
+    Changes(Vec<(u32, OsString)>),
+    // More changes than the buffer holds; which ones is unknown
+    Overflowed,
+}
+
+impl DirChangeSource {
+    pub fn open(volume: &VolumeInfo) -> anyhow::Result<Self> {
+        let root = volume.root().to_string();
+        let handle = unsafe {
+            CreateFileW(
+                &HSTRING::from(root.as_str()),
+                FILE_LIST_DIRECTORY.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                // Needed to open a directory
+                FILE_FLAG_BACKUP_SEMANTICS,
+                None,
+            )?
+        };
+
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_root = root.clone();
+        let thread_stop = stop.clone();
+        // HANDLE isn't Send; from here on only the thread uses it, and closes it
+        let raw_handle = handle.0 as isize;
+        let thread = std::thread::spawn(move || {
+            let handle = HANDLE(raw_handle as *mut std::ffi::c_void);
+            watch_folder(handle, &thread_root, &tx, &thread_stop);
+            unsafe {
+                let _ = CloseHandle(handle);
+            }
+        });
+
+        Ok(Self { root, rx, stop, thread: Some(thread) })
+    }
+
+    /// Events for one notification, empty if the file is already gone again.
+    fn events_for(&self, action: u32, exact_relative: &OsStr) -> Vec<ChangeEvent> {
+        let root = &self.root;
+        // The exact path finds the file; IDs and names come from the lossy one
+        let path = PathBuf::from(root).join(exact_relative);
+        let relative = exact_relative.to_string_lossy();
+        let relative: &str = &relative;
+        let parent_fid = match relative.rsplit_once('\\') {
+            Some((folder, _)) => path_file_id(&format!("{}{}", root, folder)),
+            None => path_file_id(root),
+        };
+        let fid = path_file_id(&path.to_string_lossy());
+        let name = relative.rsplit('\\').next().unwrap_or(relative).to_string();
+
+        if action == FILE_ACTION_REMOVED.0 || action == FILE_ACTION_RENAMED_OLD_NAME.0 {
+            // Records are keyed by path, so a rename is a delete and a create
+            return vec![ChangeEvent {
+                fid,
+                parent_fid,
+                reason: USN_REASON_FILE_DELETE,
+                name,
+                wide_name: None,
+                modified: 0,
+                attributes: 0,
+            }];
+        }
+        let reason = match action {
+            a if a == FILE_ACTION_ADDED.0 => USN_REASON_FILE_CREATE,
+            a if a == FILE_ACTION_RENAMED_NEW_NAME.0 => USN_REASON_RENAME_NEW_NAME,
+            a if a == FILE_ACTION_MODIFIED.0 => USN_REASON_DATA_OVERWRITE,
+            _ => return Vec::new(),
+        };
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Vec::new();
+        };
+        let entry = entry_from_metadata(&path, parent_fid, &metadata);
+        let mut events = vec![event_from_entry(entry, reason)];
+        // A folder renamed into place brings everything beneath it under new paths
+        if reason == USN_REASON_RENAME_NEW_NAME && metadata.is_dir() {
+            events.extend(
+                DirWalker::from_folder(path, fid)
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| event_from_entry(entry, USN_REASON_FILE_CREATE)),
+            );
+        }
+        events
+    }
+}
+
+/// Reads notifications for the folder behind `handle` until `stop` is set, a read
+/// fails or the buffer overflows.
+fn watch_folder(handle: HANDLE, root: &str, tx: &Sender<Notifications>, stop: &AtomicBool) {
+    // ReadDirectoryChangesW needs a DWORD-aligned buffer
+    let mut buffer = vec![0u32; NOTIFY_BUFFER_SIZE / 4];
+    while !stop.load(Ordering::Relaxed) {
+        let mut bytes_returned = 0u32;
+        let result = unsafe {
+            ReadDirectoryChangesW(
+                handle,
+                buffer.as_mut_ptr() as _,
+                NOTIFY_BUFFER_SIZE as u32,
+                true,
+                FILE_NOTIFY_CHANGE_FILE_NAME
+                    | FILE_NOTIFY_CHANGE_DIR_NAME
+                    | FILE_NOTIFY_CHANGE_ATTRIBUTES
+                    | FILE_NOTIFY_CHANGE_SIZE
+                    | FILE_NOTIFY_CHANGE_LAST_WRITE,
+                Some(&mut bytes_returned),
+                None,
+                None,
+            )
+        };
+        if let Err(e) = result {
+            debug!(%root, error = %e, "Folder watch stopped");
+            return;
+        }
+        if bytes_returned == 0 {
+            warn!(%root, "Folder change notifications overflowed");
+            let _ = tx.send(Notifications::Overflowed);
+            return;
+        }
+        let changes = parse_notifications(&buffer, bytes_returned as usize);
+        if tx.send(Notifications::Changes(changes)).is_err() {
+            return;
+        }
+    }
+}
+
+fn event_from_entry(entry: MftEntry, reason: u32) -> ChangeEvent {
+    ChangeEvent {
+        fid: entry.fid,
+        parent_fid: entry.parent_fid,
+        reason,
+        name: entry.name,
+        wide_name: entry.wide_name,
+        modified: entry.modified,
+        attributes: entry.attributes,
+    }
+}
+
+/// The action and volume-relative path of each FILE_NOTIFY_INFORMATION in `buffer`.
+fn parse_notifications(buffer: &[u32], len: usize) -> Vec<(u32, OsString)> {
+    let bytes = buffer.as_ptr() as *const u8;
+    let mut notifications = Vec::new();
+    let mut offset = 0;
+    while offset < len {
+        let info = unsafe { &*(bytes.add(offset) as *const FILE_NOTIFY_INFORMATION) };
+        let name = unsafe {
+            std::slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2)
+        };
+        notifications.push((info.Action.0, OsString::from_wide(name)));
+        if info.NextEntryOffset == 0 {
+            break;
+        }
+        offset += info.NextEntryOffset as usize;
+    }
+    notifications
+}
+
+impl ChangeSource for DirChangeSource {
+    fn poll(&mut self) -> anyhow::Result<Vec<ChangeEvent>> {
+        let mut events = Vec::new();
+        loop {
+            match self.rx.try_recv() {
+                Ok(Notifications::Changes(changes)) => {
+                    for (action, relative) in changes {
+                        events.extend(self.events_for(action, &relative));
+                    }
+                }
+                // Only walking the volume again finds what changed
+                Ok(Notifications::Overflowed) => {
+                    return Err(JournalLost("folder change notifications overflowed".to_string()).into());
+                }
+                Err(TryRecvError::Empty) => return Ok(events),
+                Err(TryRecvError::Disconnected) => {
+                    anyhow::bail!("Folder watch for volume {} stopped", self.root)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DirChangeSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let Some(thread) = self.thread.take() else { return };
+        // A cancel that lands before the thread starts its next read is lost, so keep
+        // cancelling until the thread sees `stop` and exits
+        while !thread.is_finished() {
+            unsafe {
+                let _ = CancelSynchronousIo(HANDLE(thread.as_raw_handle()));
+            }
+            std::thread::sleep(CANCEL_RETRY_INTERVAL);
+        }
+        let _ = thread.join();
+    }
+}
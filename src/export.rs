@@ -0,0 +1,166 @@
+use crate::mft_indexer::Indexer;
+use serde::Serialize;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// Picks the format from the file extension, defaulting to CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportRow {
+    name: String,
+    path: String,
+    size: u64,
+    modified: String,
+    is_dir: bool,
+}
+
+/// ISO-8601 UTC timestamp for a FILETIME, empty when unknown.
+fn filetime_to_iso(filetime: i64) -> String {
+    let unix_secs = (filetime / 10_000_000) - 11_644_473_600;
+    if filetime == 0 || unix_secs < 0 {
+        return String::new();
+    }
+    chrono::DateTime::from_timestamp(unix_secs, 0)
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+        .unwrap_or_default()
+}
+
+/// Quotes a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_rows(
+    indexer: &Indexer,
+    ids: &[u128],
+    path: &Path,
+    format: ExportFormat,
+    done: &AtomicUsize,
+) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(std::fs::File::create(path)?);
+    match format {
+        ExportFormat::Csv => writeln!(out, "name,path,size,modified,is_dir")?,
+        ExportFormat::Json => write!(out, "[")?,
+    }
+
+    let mut written = 0;
+    for (i, &id) in ids.iter().enumerate() {
+        // Records can vanish mid-export if their volume is removed
+        let Some((name, size, modified, is_dir)) = indexer.records.get(&id)
+            .map(|r| (r.name.clone(), r.size, r.modified, r.is_dir))
+        else {
+            continue;
+        };
+        let row = ExportRow {
+            name,
+            path: indexer.get_full_path(id).path,
+            size,
+            modified: filetime_to_iso(modified),
+            is_dir,
+        };
+        match format {
+            ExportFormat::Csv => writeln!(
+                out,
+                "{},{},{},{},{}",
+                csv_field(&row.name),
+                csv_field(&row.path),
+                row.size,
+                row.modified,
+                row.is_dir
+            )?,
+            ExportFormat::Json => {
+                if written > 0 {
+                    write!(out, ",")?;
+                }
+                serde_json::to_writer(&mut out, &row)?;
+            }
+        }
+        written += 1;
+        done.store(i + 1, Ordering::Relaxed);
+    }
+
+    if format == ExportFormat::Json {
+        write!(out, "]")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Writes results to a file on a background thread. Poll `finished` each frame.
+pub struct ExportJob {
+    pub path: PathBuf,
+    pub total: usize,
+    done: Arc<AtomicUsize>,
+    rx: Receiver<anyhow::Result<()>>,
+}
+
+impl ExportJob {
+    /// Starts exporting `ids` in their current order; the format follows `path`'s extension.
+    pub fn start(indexer: Arc<Indexer>, ids: Vec<u128>, path: PathBuf) -> Self {
+        let (tx, rx) = channel();
+        let done = Arc::new(AtomicUsize::new(0));
+        let total = ids.len();
+
+        let thread_done = Arc::clone(&done);
+        let thread_path = path.clone();
+        std::thread::spawn(move || {
+            let format = ExportFormat::from_path(&thread_path);
+            let _ = tx.send(write_rows(&indexer, &ids, &thread_path, format, &thread_done));
+        });
+
+        Self { path, total, done, rx }
+    }
+
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.done.load(Ordering::Relaxed) as f32 / self.total as f32
+    }
+
+    /// The outcome once the export has finished.
+    pub fn finished(&self) -> Option<anyhow::Result<()>> {
+        self.rx.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filetimes_convert_to_utc_dates() {
+        assert_eq!(filetime_to_iso(116_444_736_000_000_000), "1970-01-01T00:00:00Z");
+        assert_eq!(filetime_to_iso(132_223_104_000_000_000), "2020-01-01T00:00:00Z");
+        assert_eq!(filetime_to_iso(133_485_408_000_000_000), "2024-01-01T00:00:00Z");
+        // 2024-02-29 13:45:30.5
+        assert_eq!(filetime_to_iso(133_536_879_305_000_000), "2024-02-29T13:45:30Z");
+    }
+
+    #[test]
+    fn unknown_and_pre_1970_filetimes_are_empty() {
+        assert_eq!(filetime_to_iso(0), "");
+        assert_eq!(filetime_to_iso(116_444_735_990_000_000), "");
+    }
+}
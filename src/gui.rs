@@ -1,67 +1,763 @@
 use eframe::egui;
 use egui_extras::TableBuilder;
-use crate::mft_indexer::Indexer;
-use std::sync::Arc;
+use crate::autostart::set_start_with_windows;
+use crate::config::Config;
+use crate::content_search::ContentSearch;
+use crate::export::ExportJob;
+use crate::mft_indexer::{
+    attribute_letters, key_volume, FileRecord, FullPath, IndexPhase, IndexProgress, Indexer, SearchQuery,
+    ATTRIBUTE_FLAGS,
+};
+use crate::preview::{format_attributes, Preview, PreviewContent, PreviewLoader};
+use crate::settings_window::{SettingsAction, SettingsWindow};
+use crate::hotkey::HotkeyListener;
+use crate::logging;
+use crate::single_instance::{ActivationListener, SingleInstance};
+use crate::tray::{main_window_handle, restore_window, Tray, TrayCommand};
+use crate::volume_manager::{disk_info, DiskInfo, VolumeId, VolumeInfo, VolumeManager};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::Receiver;
 use tokio_util::sync::CancellationToken;
 use windows::Win32::UI::Shell::ShellExecuteW;
 use windows::Win32::UI::WindowsAndMessaging::SW_SHOW;
 use windows::core::HSTRING;
 use windows::Win32::Foundation::HWND;
 
-#[derive(PartialEq, Clone, Copy)]
-enum SortColumn {
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SortColumn {
+    Relevance,
     Name,
+    Folder,
     Path,
     Size,
+    // Like Size, but folders count the total size of everything beneath them
+    RecursiveSize,
     Modified,
+    Created,
+    Accessed,
+    Extension,
 }
 
-fn format_filetime(filetime: i64) -> String {
+/// A result table column other than Name, which is always shown and comes first.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+enum ResultColumn {
+    Folder,
+    Path,
+    Size,
+    Modified,
+    Created,
+    Accessed,
+    Extension,
+    Attributes,
+}
+
+impl ResultColumn {
+    // In the order the column menu lists them
+    const ALL: [ResultColumn; 8] = [
+        ResultColumn::Folder,
+        ResultColumn::Path,
+        ResultColumn::Size,
+        ResultColumn::Modified,
+        ResultColumn::Created,
+        ResultColumn::Accessed,
+        ResultColumn::Extension,
+        ResultColumn::Attributes,
+    ];
+    const DEFAULT: [ResultColumn; 3] = [ResultColumn::Path, ResultColumn::Size, ResultColumn::Modified];
+
+    /// Header text, also the key its width is saved under.
+    fn title(self) -> &'static str {
+        match self {
+            ResultColumn::Folder => "Folder",
+            ResultColumn::Path => "Path",
+            ResultColumn::Size => "Size",
+            ResultColumn::Modified => "Date Modified",
+            ResultColumn::Created => "Date Created",
+            ResultColumn::Accessed => "Date Accessed",
+            ResultColumn::Extension => "Extension",
+            ResultColumn::Attributes => "Attributes",
+        }
+    }
+
+    /// What clicking the header sorts by; None for columns that can't be sorted.
+    fn sort_column(self) -> Option<SortColumn> {
+        match self {
+            ResultColumn::Folder => Some(SortColumn::Folder),
+            ResultColumn::Path => Some(SortColumn::Path),
+            ResultColumn::Size => Some(SortColumn::Size),
+            ResultColumn::Modified => Some(SortColumn::Modified),
+            ResultColumn::Created => Some(SortColumn::Created),
+            ResultColumn::Accessed => Some(SortColumn::Accessed),
+            ResultColumn::Extension => Some(SortColumn::Extension),
+            ResultColumn::Attributes => None,
+        }
+    }
+
+    fn sorts_by(self, sort: SortColumn) -> bool {
+        match sort {
+            SortColumn::RecursiveSize => self == ResultColumn::Size,
+            sort => self.sort_column() == Some(sort),
+        }
+    }
+
+    /// Initial and minimum width, and whether long text is cut off.
+    fn layout(self) -> (f32, f32, bool) {
+        match self {
+            ResultColumn::Folder => (300.0, 100.0, true),
+            ResultColumn::Path => (400.0, 100.0, true),
+            ResultColumn::Size => (100.0, 50.0, false),
+            ResultColumn::Modified | ResultColumn::Created | ResultColumn::Accessed => (150.0, 100.0, false),
+            ResultColumn::Extension => (70.0, 40.0, false),
+            ResultColumn::Attributes => (60.0, 40.0, false),
+        }
+    }
+}
+
+/// How the search box text is matched against names.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SearchMode {
+    // Parsed with the `query` syntax: words, wildcards, filters, OR and NOT
+    #[default]
+    Normal,
+    // Names containing the typed letters in order, see `SearchQuery::fuzzy`
+    Fuzzy,
+}
+
+/// A built-in list of files, offered next to the search box. Text in the search box
+/// narrows it like any other search.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum QuickView {
+    Largest,
+    RecentDay,
+    RecentWeek,
+}
+
+impl QuickView {
+    const ALL: [QuickView; 3] = [QuickView::Largest, QuickView::RecentDay, QuickView::RecentWeek];
+
+    fn label(self) -> &'static str {
+        match self {
+            QuickView::Largest => "Largest files",
+            QuickView::RecentDay => "Modified in the last 24 hours",
+            QuickView::RecentWeek => "Modified in the last 7 days",
+        }
+    }
+}
+
+/// A `QuickView` being gathered on a background thread.
+struct ViewScan {
+    view: QuickView,
+    // What the results are highlighted with once they arrive
+    query: SearchQuery,
+    rx: Receiver<Vec<u128>>,
+}
+
+/// How the date columns show their dates.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum DateFormat {
+    // The configured `date_format`, e.g. "2024-01-15 14:32"
+    #[default]
+    Absolute,
+    // "2 hours ago", "yesterday", ...
+    Relative,
+}
+
+/// The icon shown before a result's name, and what it stands for.
+fn record_icon(record: &FileRecord) -> (&'static str, &'static str) {
+    if record.is_reparse_point {
+        ("🔗", "Link: a symlink, junction or other reparse point, not the real file or folder")
+    } else if record.is_dir {
+        ("📁", "Folder")
+    } else {
+        ("📄", "File")
+    }
+}
+
+/// The extension shown in the Extension column, without the dot; empty for folders.
+fn name_extension(name: &str, is_dir: bool) -> &str {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !is_dir && !stem.is_empty() => ext,
+        _ => "",
+    }
+}
+
+pub(crate) fn format_filetime(filetime: i64, format: &str) -> String {
     if filetime == 0 { return "---".to_string(); }
     let unix_secs = (filetime / 10_000_000) - 11_644_473_600;
     if unix_secs < 0 { return "---".to_string(); }
     
     let dt = chrono::DateTime::from_timestamp(unix_secs, 0);
     match dt {
-        Some(dt) => dt.format("%Y-%m-%d %H:%M").to_string(),
+        Some(dt) => dt.format(format).to_string(),
         None => "---".to_string(),
     }
 }
 
-fn format_size(bytes: u64) -> String {
+/// `format_filetime`, or with `DateFormat::Relative` the time since `filetime` in words.
+/// Dates more than a year ago, or in the future, are always shown in full.
+fn format_date(filetime: i64, format: &str, mode: DateFormat) -> String {
+    if mode == DateFormat::Absolute || filetime == 0 {
+        return format_filetime(filetime, format);
+    }
+    let unix_secs = (filetime / 10_000_000) - 11_644_473_600;
+    let Some(date) = chrono::DateTime::from_timestamp(unix_secs, 0) else {
+        return format_filetime(filetime, format);
+    };
+    let age = chrono::Utc::now() - date;
+    if age < chrono::Duration::zero() || age > chrono::Duration::days(365) {
+        return format_filetime(filetime, format);
+    }
+    let plural = |n: i64, unit: &str| format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" });
+    match age.num_days() {
+        0 if age.num_minutes() == 0 => "just now".to_string(),
+        0 if age.num_hours() == 0 => plural(age.num_minutes(), "minute"),
+        0 => plural(age.num_hours(), "hour"),
+        1 => "yesterday".to_string(),
+        days @ 2..=6 => plural(days, "day"),
+        7..=13 => "last week".to_string(),
+        days @ 14..=29 => plural(days / 7, "week"),
+        30..=59 => "last month".to_string(),
+        days => plural(days / 30, "month"),
+    }
+}
+
+/// The directory part of a full path, keeping the backslash for drive roots (`C:\`).
+fn parent_folder(path: &str) -> &str {
+    match path.rfind('\\') {
+        Some(i) if path[..i].ends_with(':') => &path[..=i],
+        Some(i) => &path[..i],
+        None => path,
+    }
+}
+
+/// `name` with the parts `query` matched picked out in the highlight color.
+fn highlighted_name(ui: &egui::Ui, name: &str, spans: &[Range<usize>]) -> egui::text::LayoutJob {
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let plain = egui::TextFormat::simple(font.clone(), ui.visuals().text_color());
+    let highlighted = egui::TextFormat::simple(font, ui.visuals().warn_fg_color);
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut end = 0;
+    for span in spans {
+        // Spans are cached, so the name may have been renamed since
+        if span.start < end || name.get(span.clone()).is_none() {
+            continue;
+        }
+        job.append(&name[end..span.start], 0.0, plain.clone());
+        job.append(&name[span.clone()], 0.0, highlighted.clone());
+        end = span.end;
+    }
+    job.append(&name[end..], 0.0, plain);
+    job
+}
+
+/// `count` with thousands separators, e.g. "1,934,112".
+fn format_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+pub fn format_size(bytes: u64) -> String {
     if bytes == 0 { return "0 KB".to_string(); }
     const KB: u64 = 1024;
     const MB: u64 = 1024 * KB;
     const GB: u64 = 1024 * MB;
+    const TB: u64 = 1024 * GB;
 
-    if bytes >= GB { format!("{:.2} GB", bytes as f64 / GB as f64) }
+    if bytes >= TB { format!("{:.2} TB", bytes as f64 / TB as f64) }
+    else if bytes >= GB { format!("{:.2} GB", bytes as f64 / GB as f64) }
     else if bytes >= MB { format!("{:.1} MB", bytes as f64 / MB as f64) }
     else if bytes >= KB { format!("{} KB", bytes / KB) }
     else { format!("{} B", bytes) }
 }
 
+/// `format_size` followed by the exact byte count, e.g. "1.15 GB (1,234,567,890 bytes)".
+/// Sizes under a KB are exact already and shown as is.
+fn format_size_precise(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    format!("{} ({} bytes)", format_size(bytes), format_count(bytes))
+}
+
+/// Compares strings treating each run of ASCII digits as one number, so "file2" sorts
+/// before "file10", and ignoring ASCII case. Equal numbers with more leading zeros sort
+/// after fewer; names differing only in case fall back to byte order. Digit runs are
+/// compared as text, so they can be any length.
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let run_a = a[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let run_b = b[j..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let digits_a = &a[i..i + run_a];
+            let digits_b = &b[j..j + run_b];
+            let trimmed_a = &digits_a[digits_a.iter().take_while(|&&c| c == b'0').count()..];
+            let trimmed_b = &digits_b[digits_b.iter().take_while(|&&c| c == b'0').count()..];
+            // Without leading zeros, a longer run is a bigger number
+            let order = trimmed_a.len().cmp(&trimmed_b.len())
+                .then_with(|| trimmed_a.cmp(trimmed_b))
+                .then_with(|| run_a.cmp(&run_b));
+            if order != Ordering::Equal {
+                return order;
+            }
+            i += run_a;
+            j += run_b;
+        } else {
+            // Digits are ASCII, so byte-wise comparison of the rest keeps UTF-8 order
+            match a[i].to_ascii_lowercase().cmp(&b[j].to_ascii_lowercase()) {
+                Ordering::Equal => {
+                    i += 1;
+                    j += 1;
+                }
+                order => return order,
+            }
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then_with(|| a.cmp(b))
+}
+
+/// Plain text order for when natural sorting is off: ignores ASCII case like
+/// `natural_cmp`, with byte order breaking ties.
+fn caseless_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    a.bytes().map(|c| c.to_ascii_lowercase())
+        .cmp(b.bytes().map(|c| c.to_ascii_lowercase()))
+        .then_with(|| a.cmp(b))
+}
+
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+const FILETIME_TICKS_PER_DAY: i64 = 86_400 * 10_000_000;
+
+fn filetime_now() -> i64 {
+    let since_epoch = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    FILETIME_UNIX_EPOCH + (since_epoch.as_nanos() / 100) as i64
+}
+
+/// Scores how well `name` matches the lowercased `query`. The match kind dominates
+/// (exact name, exact stem, prefix, word start, anywhere); within a kind, shorter names,
+/// earlier matches and recently modified files rank higher.
+fn relevance_score(name: &str, query: &str, modified: i64, now: i64) -> u32 {
+    let name = name.to_lowercase();
+    let Some(pos) = name.find(query) else {
+        return 0;
+    };
+    let stem = name.rsplit_once('.').map_or(name.as_str(), |(stem, _)| stem);
+    let at_word_start = name[..pos].ends_with(|c: char| !c.is_alphanumeric());
+
+    let mut score: u32 = if name == query {
+        1000
+    } else if stem == query {
+        800
+    } else if pos == 0 {
+        600
+    } else if at_word_start {
+        400
+    } else {
+        200
+    };
+
+    // Penalties and bonus stay under the 200 gap between match kinds
+    let extra_chars = (name.len() - query.len()).min(60) as u32;
+    score -= extra_chars + (pos.min(30) as u32);
+    let age_days = (now - modified) / FILETIME_TICKS_PER_DAY;
+    if modified > 0 && age_days < 30 {
+        score += 50 - (age_days.max(0) as u32 * 50 / 30);
+    }
+    score
+}
+
+const MAX_SEARCH_HISTORY: usize = 50;
+const LOG_PANEL_LINES: usize = 500;
+// How often the status bar's free space figures are refreshed
+const DISK_INFO_REFRESH: std::time::Duration = std::time::Duration::from_secs(30);
+// Bars in the largest folders chart
+const LARGEST_DIRS_SHOWN: usize = 20;
+const LOG_PANEL_REFRESH: std::time::Duration = std::time::Duration::from_secs(1);
+// Background work asks for a redraw this soon, so bursts of changes share one frame
+const REPAINT_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+// Result counts above this are shown as "9999+" so the badge stays small
+const RESULT_BADGE_MAX: usize = 9999;
+// Most common extensions offered as chips under the results
+const EXTENSION_CHIPS_SHOWN: usize = 40;
+// How often the current query is run again while the index is changing
+const LIVE_SEARCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// How often relative dates ("2 minutes ago") are redrawn while nothing else repaints
+const RELATIVE_DATE_REFRESH: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// UI state persisted across sessions through eframe storage. Window geometry is
+/// persisted by eframe itself.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct AppState {
+    search_history: Vec<String>,
+    show_preview: bool,
+    // Shown after Name, in this order
+    columns: Vec<ResultColumn>,
+    // Column header -> width; egui's own table state is lost when columns are toggled
+    column_widths: HashMap<String, f32>,
+    date_format: DateFormat,
+    search_mode: SearchMode,
+    group_by_folder: bool,
+    sort_column: SortColumn,
+    sort_ascending: bool,
+    secondary_sort: Option<(SortColumn, bool)>,
+    case_sensitive: bool,
+    // Path of the folder searches were limited to, found in the index again on the next start
+    search_folder: Option<String>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            search_history: Vec::new(),
+            show_preview: false,
+            columns: ResultColumn::DEFAULT.to_vec(),
+            column_widths: HashMap::new(),
+            date_format: DateFormat::Absolute,
+            search_mode: SearchMode::Normal,
+            group_by_folder: false,
+            sort_column: SortColumn::Name,
+            sort_ascending: true,
+            secondary_sort: None,
+            case_sensitive: false,
+            search_folder: None,
+        }
+    }
+}
+
+/// What a result's cells are drawn from, gathered once per row.
+struct ResultRow<'a> {
+    id: u128,
+    record: &'a FileRecord,
+    full_path: String,
+    // `full_path` for opening, see `FullPath::native`
+    native_path: &'a OsStr,
+    path_complete: bool,
+    // The file's other hard links, see `Indexer::link_paths`
+    link_paths: &'a [FullPath],
+    date_format: &'a str,
+}
+
+/// Combined size of the files in the result list. Folders are only counted, as their
+/// own size is always 0.
+#[derive(Default)]
+struct ResultsSize {
+    bytes: u64,
+    files: usize,
+    folders: usize,
+}
+
 pub struct RivetApp {
     indexer: Arc<Indexer>,
+    config: Arc<RwLock<Config>>,
+    volume_manager: Arc<Mutex<VolumeManager>>,
+    settings_window: Option<SettingsWindow>,
+    hwnd: Option<isize>,
+    tray: Option<Tray>,
+    hotkey: Option<HotkeyListener>,
+    hotkey_error: Option<String>,
+    // Relays "show yourself" requests from instances launched while we're running
+    activation: Option<ActivationListener>,
+    // Focus the search box and select its text on the next frame
+    focus_search: bool,
+    // Whether the search box was non-empty at the last search, to switch between
+    // relevance and alphabetical order as it fills and empties
+    query_active: bool,
+    // Set when the user really exits, so the close request isn't turned into hide-to-tray
+    exiting: bool,
+    progress_rx: Receiver<IndexProgress>,
+    enumeration_errors: BTreeMap<VolumeId, u64>,
+    // Visible rows last handed to `Indexer::ensure_sizes`, so each set is only fetched once
+    ensured_sizes: Vec<u128>,
+    // Feeds the thread fetching sizes of visible rows, started with the first request
+    size_worker: Option<std::sync::mpsc::Sender<Vec<u128>>>,
+    // Unplugged volumes -> how many of their records are hidden until they're back
+    offline: BTreeMap<VolumeId, u64>,
     search_query: String,
-    results: Vec<u64>, 
+    results: Vec<u128>, 
+    // Query that produced `results`, lowercased unless matching case, used to refine
+    // instead of rescanning
+    last_query: String,
+    // The parsed form of `last_query`, to highlight what it matched in each name
+    highlight: Option<SearchQuery>,
+    results_truncated: bool,
+    // Total of `results`, summed when the list changes and as sizes come in
+    results_size: ResultsSize,
+    // Show results in collapsible sections per containing folder instead of the table
+    group_by_folder: bool,
+    // Folder path -> its results in the current sort order, rebuilt when `results` changes
+    folder_groups: Option<Vec<(String, Vec<u128>)>>,
+    // Extensions chosen in the chip bar; the table only shows results with one of them
+    active_extension_filter: HashSet<String>,
+    // Extension -> number of results, most common first, rebuilt when `results` changes
+    extension_counts: Option<Vec<(String, usize)>>,
+    // `results` narrowed by `active_extension_filter`, rebuilt when either changes
+    filtered_results: Option<Vec<u128>>,
+    // Where `highlight` matched each result's name, filled in as rows are drawn so the
+    // name isn't matched again every frame
+    highlight_spans: HashMap<u128, Vec<Range<usize>>>,
+    // Paths of each result's other hard links, resolved as rows are first drawn
+    link_paths: HashMap<u128, Vec<FullPath>>,
+    // `Indexer::generation` when `results` were last searched, and when that was
+    results_generation: u64,
+    last_live_search: Option<std::time::Instant>,
+    // Last title sent to the window, so it's only updated when the count changes
+    window_title: String,
+    // Why the search box text isn't a valid query, shown in the status bar
+    query_error: Option<String>,
     cancel_token: CancellationToken,
     sort_column: SortColumn,
     sort_ascending: bool,
+    // Column and direction that order rows the sort column ranks equal, set by Shift+click
+    secondary_sort: Option<(SortColumn, bool)>,
+    search_history: Vec<String>,
+    // Position in `search_history` while recalling with Up/Down; None once the query is edited
+    history_cursor: Option<usize>,
+    selected_id: Option<u128>,
+    show_preview: bool,
+    // Result columns after Name, in display order; reordered by dragging their headers
+    columns: Vec<ResultColumn>,
+    column_widths: HashMap<String, f32>,
+    date_format: DateFormat,
+    search_mode: SearchMode,
+    // Results whose name also matches this, under `exclude_mode`, are left out while
+    // the exclusion box is shown
+    show_exclude: bool,
+    // Only results beneath this folder, by id and path; see `SearchQuery::within`
+    search_folder: Option<(u128, String)>,
+    // The saved `search_folder` path until the index has that folder, see `restore_search_folder`
+    pending_search_folder: Option<String>,
+    exclude_query: String,
+    exclude_mode: SearchMode,
+    // FILE_ATTRIBUTE_* flags every result must have; 0 means no filter
+    attribute_filter: u32,
+    // Match names and paths case-sensitively, in the search and exclusion boxes
+    case_sensitive: bool,
+    // Keep only name matches whose contents contain `content_query`
+    content_mode: bool,
+    content_query: String,
+    content_search: Option<ContentSearch>,
+    // Result -> occurrences of `content_query`, for the badge in the Name column
+    content_matches: HashMap<u128, usize>,
+    // Groups of identical files, delivered once the background hashing pass is done
+    duplicate_scan: Option<Receiver<HashMap<[u8; 32], Vec<u128>>>>,
+    view_scan: Option<ViewScan>,
+    // Folders being measured on request, each sending its total once done
+    folder_size_jobs: HashMap<u128, Receiver<u64>>,
+    // Files found to have an identical copy, badged in the Name column
+    duplicates: HashSet<u128>,
+    // Path and recursive size of the biggest folders, filled when the chart is opened
+    show_dir_chart: bool,
+    largest_dirs: Vec<(String, u64)>,
+    preview_loader: PreviewLoader,
+    preview: Option<Preview>,
+    preview_texture: Option<egui::TextureHandle>,
+    preview_requested_for: Option<u128>,
+    export: Option<ExportJob>,
+    // Outcome of the last export, shown in the status bar
+    export_status: Option<String>,
+    // Indexed volumes with their label and free space, None where that couldn't be read.
+    // Gathered on a background thread, so frames never wait on the volume manager's lock
+    // or on the drives themselves.
+    volumes: Vec<(VolumeInfo, Option<DiskInfo>)>,
+    volumes_rx: Option<Receiver<Vec<(VolumeInfo, Option<DiskInfo>)>>>,
+    last_disk_info_update: Option<std::time::Instant>,
+    show_logs: bool,
+    // Tail of the log file and when it was last read, refreshed while the panel is open
+    log_lines: Vec<String>,
+    logs_read_at: Option<std::time::Instant>,
+    show_error_log: bool,
+    // Time of the newest error dismissed from the banner; newer ones show it again
+    errors_seen_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 impl RivetApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>, indexer: Arc<Indexer>, cancel_token: CancellationToken) -> Self {
-        Self {
+    pub fn new(
+        cc: &eframe::CreationContext<'_>,
+        indexer: Arc<Indexer>,
+        config: Arc<RwLock<Config>>,
+        volume_manager: Arc<Mutex<VolumeManager>>,
+        progress_rx: Receiver<IndexProgress>,
+        icon: Option<Arc<egui::IconData>>,
+        cancel_token: CancellationToken,
+    ) -> Self {
+        // Missing or corrupt state deserializes to None and falls back to defaults
+        let state: AppState = cc.storage
+            .and_then(|storage| eframe::get_value(storage, eframe::APP_KEY))
+            .unwrap_or_default();
+
+        let ctx = cc.egui_ctx.clone();
+        indexer.set_repaint_signal(move || ctx.request_repaint_after(REPAINT_DELAY));
+
+        let hwnd = main_window_handle(cc);
+        let tray = match (hwnd, icon) {
+            (Some(hwnd), Some(icon)) => Tray::new(&cc.egui_ctx, hwnd, &icon)
+                .map_err(|e| tracing::error!(error = %e, "Failed to create tray icon"))
+                .ok(),
+            _ => None,
+        };
+
+        let mut app = Self {
             indexer,
+            config,
+            volume_manager,
+            settings_window: None,
+            hwnd,
+            tray,
+            hotkey: None,
+            hotkey_error: None,
+            activation: None,
+            focus_search: false,
+            query_active: false,
+            exiting: false,
+            progress_rx,
+            enumeration_errors: BTreeMap::new(),
+            ensured_sizes: Vec::new(),
+            size_worker: None,
+            offline: BTreeMap::new(),
             search_query: String::new(),
             results: Vec::new(),
+            last_query: String::new(),
+            highlight: None,
+            results_truncated: false,
+            results_size: ResultsSize::default(),
+            group_by_folder: state.group_by_folder,
+            folder_groups: None,
+            active_extension_filter: HashSet::new(),
+            extension_counts: None,
+            filtered_results: None,
+            highlight_spans: HashMap::new(),
+            link_paths: HashMap::new(),
+            results_generation: 0,
+            last_live_search: None,
+            window_title: "Rivet".to_string(),
+            query_error: None,
             cancel_token,
-            sort_column: SortColumn::Name,
-            sort_ascending: true,
+            sort_column: state.sort_column,
+            sort_ascending: state.sort_ascending,
+            secondary_sort: state.secondary_sort,
+            search_history: state.search_history,
+            history_cursor: None,
+            selected_id: None,
+            show_preview: state.show_preview,
+            columns: state.columns,
+            column_widths: state.column_widths,
+            date_format: state.date_format,
+            search_mode: state.search_mode,
+            attribute_filter: 0,
+            case_sensitive: state.case_sensitive,
+            content_mode: false,
+            content_query: String::new(),
+            show_exclude: false,
+            search_folder: None,
+            pending_search_folder: state.search_folder,
+            exclude_query: String::new(),
+            exclude_mode: SearchMode::Normal,
+            content_search: None,
+            content_matches: HashMap::new(),
+            duplicate_scan: None,
+            view_scan: None,
+            folder_size_jobs: HashMap::new(),
+            duplicates: HashSet::new(),
+            show_dir_chart: false,
+            largest_dirs: Vec::new(),
+            preview_loader: PreviewLoader::new(),
+            preview: None,
+            preview_texture: None,
+            preview_requested_for: None,
+            export: None,
+            export_status: None,
+            volumes: Vec::new(),
+            volumes_rx: None,
+            last_disk_info_update: None,
+            show_logs: false,
+            log_lines: Vec::new(),
+            logs_read_at: None,
+            show_error_log: false,
+            errors_seen_at: None,
+        };
+        app.register_hotkey(&cc.egui_ctx);
+        app.restore_search_folder();
+        app
+    }
+
+    fn register_hotkey(&mut self, ctx: &egui::Context) {
+        // Drop the old listener first so re-registering the same combination succeeds
+        self.hotkey = None;
+        self.hotkey_error = None;
+        let combination = self.config.read().unwrap().hotkey.clone();
+        let Some(hwnd) = self.hwnd else { return };
+        if combination.is_empty() {
+            return;
+        }
+
+        let restore_ctx = ctx.clone();
+        match HotkeyListener::start(&combination, ctx, move || restore_window(hwnd, &restore_ctx)) {
+            Ok(listener) => self.hotkey = Some(listener),
+            Err(e) => self.hotkey_error = Some(e.to_string()),
+        }
+    }
+
+    /// Brings the window forward whenever another copy of Rivet is launched.
+    pub fn listen_for_activation(&mut self, ctx: &egui::Context, instance: SingleInstance) {
+        let hwnd = self.hwnd;
+        let restore_ctx = ctx.clone();
+        self.activation = Some(instance.listen(ctx, move || {
+            if let Some(hwnd) = hwnd {
+                restore_window(hwnd, &restore_ctx);
+            }
+        }));
+    }
+
+    fn push_history(&mut self) {
+        let query = self.search_query.trim();
+        if query.is_empty() || self.search_history.last().map(|s| s.as_str()) == Some(query) {
+            return;
+        }
+        self.search_history.push(query.to_string());
+        if self.search_history.len() > MAX_SEARCH_HISTORY {
+            let excess = self.search_history.len() - MAX_SEARCH_HISTORY;
+            self.search_history.drain(..excess);
         }
     }
 
-    fn open_file(&self, path: &str) {
+    /// Steps through history like a shell: Up goes to older entries, Down to newer ones,
+    /// and stepping past the newest entry restores an empty query.
+    fn recall_history(&mut self, older: bool) -> bool {
+        if self.search_history.is_empty() {
+            return false;
+        }
+        let last = self.search_history.len() - 1;
+        let next = match (self.history_cursor, older) {
+            (None, true) => Some(last),
+            (None, false) => return false,
+            (Some(i), true) => Some(i.saturating_sub(1)),
+            (Some(i), false) if i < last => Some(i + 1),
+            (Some(_), false) => None,
+        };
+        self.history_cursor = next;
+        self.search_query = next.map(|i| self.search_history[i].clone()).unwrap_or_default();
+        true
+    }
+
+    /// Opens `path`, which should be ready for Win32 file APIs, see `FullPath::native`.
+    fn open_file(&self, path: &OsStr) {
         unsafe {
             ShellExecuteW(
                 HWND::default(),
@@ -74,210 +770,2011 @@ impl RivetApp {
         }
     }
 
-    fn open_folder(&self, path: &str) {
+    fn open_folder(&self, path: &OsStr) {
         unsafe {
             // /select, <path> highlights the file in Explorer
-            let cmd = format!("/select,\"{}\"", path);
+            let mut cmd = OsString::from("/select,\"");
+            cmd.push(path);
+            cmd.push("\"");
             ShellExecuteW(
                 HWND::default(),
                 &HSTRING::from("open"),
                 &HSTRING::from("explorer.exe"),
-                &HSTRING::from(cmd),
+                &HSTRING::from(cmd.as_os_str()),
                 None,
                 SW_SHOW,
             );
         }
     }
 
-    fn perform_search(&mut self) {
+    fn search_box(&mut self, ui: &mut egui::Ui) {
+        let response = ui.add(
+            egui::TextEdit::singleline(&mut self.search_query)
+                .hint_text("Search files...")
+                .desired_width(f32::INFINITY)
+                .lock_focus(true)
+        );
+        self.result_count_badge(ui, response.rect);
+        if self.focus_search {
+            self.focus_search = false;
+            response.request_focus();
+            if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), response.id) {
+                let start = egui::text::CCursor::new(0);
+                let end = egui::text::CCursor::new(self.search_query.chars().count());
+                state.cursor.set_char_range(Some(egui::text::CCursorRange::two(start, end)));
+                state.store(ui.ctx(), response.id);
+            }
+        }
+
+        if response.changed() {
+            self.history_cursor = None;
+            self.perform_search();
+        }
+
+        // Only recall history while the box is empty or still showing a recalled entry,
+        // so arrow keys never clobber a query the user is typing
+        if response.has_focus() && (self.search_query.is_empty() || self.history_cursor.is_some()) {
+            let (up, down) = ui.input(|i| (i.key_pressed(egui::Key::ArrowUp), i.key_pressed(egui::Key::ArrowDown)));
+            if (up || down) && self.recall_history(up) {
+                if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), response.id) {
+                    let end = egui::text::CCursor::new(self.search_query.chars().count());
+                    state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end)));
+                    state.store(ui.ctx(), response.id);
+                }
+                self.perform_search();
+            }
+        }
+
+        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+            self.push_history();
+            self.history_cursor = None;
+            response.request_focus();
+        }
+    }
+
+    /// Short form of the result count, e.g. "42", "500+" when the search stopped at the
+    /// result limit, or None while the search box is empty.
+    fn result_count_text(&self) -> Option<String> {
         if self.search_query.is_empty() {
-            self.results.clear();
+            return None;
+        }
+        let count = self.results.len();
+        Some(if count > RESULT_BADGE_MAX {
+            format!("{}+", RESULT_BADGE_MAX)
+        } else if self.results_truncated {
+            format!("{}+", count)
+        } else {
+            count.to_string()
+        })
+    }
+
+    /// Draws the result count as a rounded label at the right end of the search box.
+    fn result_count_badge(&self, ui: &mut egui::Ui, search_rect: egui::Rect) {
+        let Some(text) = self.result_count_text() else {
             return;
+        };
+        let text = egui::RichText::new(text).small().strong();
+        let font = egui::TextStyle::Small.resolve(ui.style());
+        let text_size = ui.painter().layout_no_wrap(text.text().to_string(), font, egui::Color32::PLACEHOLDER).size();
+        let size = text_size + egui::vec2(12.0, 4.0);
+        let rect = egui::Rect::from_min_size(
+            egui::pos2(search_rect.right() - size.x - 4.0, search_rect.center().y - size.y / 2.0),
+            size,
+        );
+        ui.painter().rect_filled(rect, size.y / 2.0, ui.visuals().widgets.inactive.bg_fill);
+        ui.put(rect, egui::Label::new(text).selectable(false));
+    }
+
+    /// Puts the result count in the window title while there's a query.
+    fn update_window_title(&mut self, ctx: &egui::Context) {
+        let title = match self.result_count_text() {
+            Some(count) => format!("Rivet — {} results", count),
+            None => "Rivet".to_string(),
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    fn drain_progress(&mut self) {
+        // Collected first, since handling one needs `self` mutably
+        let received: Vec<IndexProgress> = self.progress_rx.try_iter().collect();
+        let mut sizes_fetched = false;
+        for progress in received {
+            match progress {
+                IndexProgress::Started { volume } => {
+                    self.offline.remove(&volume);
+                    self.enumeration_errors.remove(&volume);
+                }
+                IndexProgress::RecordInserted { .. } => {}
+                IndexProgress::Complete { .. } => self.restore_search_folder(),
+                IndexProgress::RecordFailed { volume, errors } => {
+                    self.enumeration_errors.insert(volume, errors);
+                }
+                IndexProgress::SizesFetched { done, total, .. } => {
+                    sizes_fetched = true;
+                    // Size filters and folder totals depend on what just came in
+                    if done == total {
+                        self.results_changed();
+                    }
+                }
+                IndexProgress::Offline { volume, records } => {
+                    self.offline.insert(volume, records);
+                    self.results.retain(|&id| key_volume(id) != volume);
+                    self.results_changed();
+                    if self.selected_id.is_some_and(|id| key_volume(id) == volume) {
+                        self.selected_id = None;
+                    }
+                }
+                IndexProgress::Removed { volume } => {
+                    self.enumeration_errors.remove(&volume);
+                    self.offline.remove(&volume);
+                    self.results.retain(|&id| key_volume(id) != volume);
+                    self.results_changed();
+                    if self.selected_id.is_some_and(|id| key_volume(id) == volume) {
+                        self.selected_id = None;
+                    }
+                }
+            }
         }
+        // Once per batch, however many sizes came in since the last frame
+        if sizes_fetched {
+            self.sum_results_size();
+        }
+    }
 
-        let query = self.search_query.to_lowercase();
-        let mut matches = Vec::new();
+    fn handle_tray(&mut self, ctx: &egui::Context) {
+        let commands = self.tray.as_ref().map(|tray| tray.poll()).unwrap_or_default();
+        for command in commands {
+            match command {
+                TrayCommand::Hide => self.hide_to_tray(ctx),
+                // The tray already restored the window
+                TrayCommand::Show => {}
+                TrayCommand::RebuildIndex => self.volume_manager.lock().unwrap().rebuild_all(),
+                TrayCommand::Exit => self.exit(ctx),
+            }
+        }
 
-        for entry in self.indexer.records.iter() {
-            if entry.name.to_lowercase().contains(&query) {
-                matches.push(*entry.key());
+        let activated = self.activation.as_ref().is_some_and(|activation| activation.activated());
+        if activated || self.hotkey.as_ref().is_some_and(|hotkey| hotkey.pressed()) {
+            self.focus_search = true;
+            if let Some(tray) = &self.tray {
+                tray.set_visible(true);
             }
-            if matches.len() > 10000 { break; }
         }
 
-        self.results = matches;
-        self.sort_results();
+        let close_to_tray = self.tray.is_some() && self.config.read().unwrap().close_to_tray;
+        if close_to_tray && !self.exiting && ctx.input(|i| i.viewport().close_requested()) {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.hide_to_tray(ctx);
+        }
     }
 
-    fn sort_results(&mut self) {
-        let indexer = &self.indexer;
-        let ascending = self.sort_ascending;
-        
-        match self.sort_column {
-            SortColumn::Name => {
-                self.results.sort_by(|a, b| {
-                    let name_a = indexer.records.get(a).map(|r| r.name.clone()).unwrap_or_default();
-                    let name_b = indexer.records.get(b).map(|r| r.name.clone()).unwrap_or_default();
-                    if ascending { name_a.cmp(&name_b) } else { name_b.cmp(&name_a) }
-                });
-            },
-            SortColumn::Path => {
-                self.results.sort_by(|a, b| {
-                    let path_a = indexer.get_full_path(*a, 'C');
-                    let path_b = indexer.get_full_path(*b, 'C');
-                    if ascending { path_a.cmp(&path_b) } else { path_b.cmp(&path_a) }
-                });
-            },
-            SortColumn::Modified => {
-                self.results.sort_by(|a, b| {
-                    let mod_a = indexer.records.get(a).map(|r| r.modified).unwrap_or(0);
-                    let mod_b = indexer.records.get(b).map(|r| r.modified).unwrap_or(0);
-                    if ascending { mod_a.cmp(&mod_b) } else { mod_b.cmp(&mod_a) }
-                });
-            },
-            SortColumn::Size => {
-                self.results.sort_by(|a, b| {
-                    let size_a = indexer.records.get(a).map(|r| r.size).unwrap_or(0);
-                    let size_b = indexer.records.get(b).map(|r| r.size).unwrap_or(0);
-                    if ascending { size_a.cmp(&size_b) } else { size_b.cmp(&size_a) }
-                });
-            },
+    fn hide_to_tray(&mut self, ctx: &egui::Context) {
+        if let Some(tray) = &self.tray {
+            tray.set_visible(false);
         }
+        ctx.send_viewport_cmd(egui::ViewportCommand::Visible(false));
     }
-}
 
-impl eframe::App for RivetApp {
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+    fn exit(&mut self, ctx: &egui::Context) {
+        self.exiting = true;
         self.cancel_token.cancel();
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
     }
 
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            ui.add_space(8.0);
-            ui.horizontal(|ui| {
-                ui.label(egui::RichText::new("🔍").size(20.0));
-                let response = ui.add(
-                    egui::TextEdit::singleline(&mut self.search_query)
-                        .hint_text("Search files...")
-                        .desired_width(f32::INFINITY)
-                        .lock_focus(true)
-                );
-                if response.changed() {
-                    self.perform_search();
+    fn apply_settings(&mut self, ctx: &egui::Context, config: Config) {
+        let hotkey_changed = self.config.read().unwrap().hotkey != config.hotkey;
+        if self.config.read().unwrap().start_with_windows != config.start_with_windows {
+            if let Err(e) = set_start_with_windows(config.start_with_windows) {
+                tracing::error!(error = %e, "Failed to update startup entry");
+            }
+        }
+        if let Err(e) = config.save() {
+            tracing::error!(error = %e, "Failed to save settings");
+        }
+        *self.config.write().unwrap() = config;
+        if hotkey_changed {
+            self.register_hotkey(ctx);
+        }
+
+        self.volume_manager.lock().unwrap().sync();
+        self.refresh_volumes_now();
+        // Limits and exclusions may have changed, so the current results can't be refined
+        self.last_query.clear();
+        self.perform_search();
+    }
+
+    fn update_preview(&mut self, ctx: &egui::Context) {
+        if let Some(preview) = self.preview_loader.poll() {
+            if Some(preview.id) == self.selected_id {
+                self.preview_texture = match &preview.content {
+                    PreviewContent::Image(image) => Some(ctx.load_texture("preview", image.clone(), Default::default())),
+                    _ => None,
+                };
+                self.preview = Some(preview);
+            }
+        }
+
+        if !self.show_preview || self.selected_id == self.preview_requested_for {
+            return;
+        }
+        self.preview_requested_for = self.selected_id;
+        self.preview = None;
+        self.preview_texture = None;
+        match self.selected_id {
+            Some(id) => {
+                let full_path = self.indexer.get_full_path(id);
+                if full_path.complete {
+                    self.preview_loader.request(id, full_path.native(), ctx);
+                } else {
+                    self.preview_loader.cancel();
                 }
-            });
-            ui.add_space(8.0);
-        });
+            }
+            None => self.preview_loader.cancel(),
+        }
+    }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            let table = TableBuilder::new(ui)
-                .striped(true)
-                .resizable(true)
-                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
-                .column(egui_extras::Column::initial(250.0).resizable(true).at_least(100.0).clip(true)) // Name
-                .column(egui_extras::Column::initial(400.0).resizable(true).at_least(100.0).clip(true)) // Path
-                .column(egui_extras::Column::initial(100.0).resizable(true).at_least(50.0)) // Size
-                .column(egui_extras::Column::initial(150.0).resizable(true).at_least(100.0)) // Date Modified
-                .header(20.0, |mut header| {
-                    header.col(|ui| {
-                        let text = if self.sort_column == SortColumn::Name {
-                            format!("Name {}", if self.sort_ascending { "🔼" } else { "🔽" })
-                        } else { "Name".to_string() };
-                        if ui.button(text).clicked() {
-                            if self.sort_column == SortColumn::Name { self.sort_ascending = !self.sort_ascending; }
-                            else { self.sort_column = SortColumn::Name; self.sort_ascending = true; }
-                            self.sort_results();
-                        }
-                    });
-                    header.col(|ui| {
-                        let text = if self.sort_column == SortColumn::Path {
-                            format!("Path {}", if self.sort_ascending { "🔼" } else { "🔽" })
-                        } else { "Path".to_string() };
-                        if ui.button(text).clicked() {
-                            if self.sort_column == SortColumn::Path { self.sort_ascending = !self.sort_ascending; }
-                            else { self.sort_column = SortColumn::Path; self.sort_ascending = true; }
-                            self.sort_results();
-                        }
-                    });
-                    header.col(|ui| {
-                        let text = if self.sort_column == SortColumn::Size {
-                            format!("Size {}", if self.sort_ascending { "🔼" } else { "🔽" })
-                        } else { "Size".to_string() };
-                        if ui.button(text).clicked() {
-                            if self.sort_column == SortColumn::Size { self.sort_ascending = !self.sort_ascending; }
-                            else { self.sort_column = SortColumn::Size; self.sort_ascending = true; }
-                            self.sort_results();
-                        }
-                    });
-                    header.col(|ui| {
-                        let text = if self.sort_column == SortColumn::Modified {
-                            format!("Date Modified {}", if self.sort_ascending { "🔼" } else { "🔽" })
-                        } else { "Date Modified".to_string() };
-                        if ui.button(text).clicked() {
-                            if self.sort_column == SortColumn::Modified { self.sort_ascending = !self.sort_ascending; }
-                            else { self.sort_column = SortColumn::Modified; self.sort_ascending = true; }
-                            self.sort_results();
-                        }
-                    });
-                });
+    fn preview_panel(&self, ui: &mut egui::Ui) {
+        let Some(id) = self.selected_id else {
+            ui.label(egui::RichText::new("Select a result to preview").weak());
+            return;
+        };
+        let Some(record) = self.indexer.records.get(&id).map(|r| r.clone()) else {
+            ui.label(egui::RichText::new("File no longer in index").weak());
+            return;
+        };
+        let preview = self.preview.as_ref().filter(|p| p.id == id);
 
-            table.body(|body| {
-                body.rows(22.0, self.results.len(), |mut row| {
-                    let row_index = row.index();
-                    let id = self.results[row_index];
-                    let full_path = self.indexer.get_full_path(id, 'C');
-                    if let Some(record) = self.indexer.records.get(&id) {
-                        row.col(|ui| {
-                            ui.horizontal(|ui| {
-                                if ui.button("🚀").on_hover_text("Open/Run File").clicked() {
-                                    self.open_file(&full_path);
-                                }
-                                ui.label(if record.is_dir { "📁" } else { "📄" });
-                                ui.add(egui::Label::new(&record.name).truncate());
-                            });
-                        });
-                        row.col(|ui| {
-                            ui.horizontal(|ui| {
-                                if ui.button("📂").on_hover_text("Open in Explorer").clicked() {
-                                    self.open_folder(&full_path);
-                                }
-                                ui.add(egui::Label::new(egui::RichText::new(&full_path).color(ui.visuals().weak_text_color())).truncate());
-                            });
-                        });
-                        row.col(|ui| {
-                            if record.is_dir {
-                                ui.label("");
-                            } else {
-                                ui.label(format_size(record.size));
-                            }
-                        });
-                        row.col(|ui| {
-                            ui.label(format_filetime(record.modified));
-                        });
-                    }
-                });
-            });
+        ui.heading(&record.name);
+        egui::Grid::new("preview_metadata").num_columns(2).show(ui, |ui| {
+            ui.label("Path");
+            ui.add(egui::Label::new(self.indexer.get_full_path(id).path).wrap());
+            ui.end_row();
+            if !record.is_dir {
+                ui.label("Size");
+                ui.label(format_size_precise(record.size));
+                ui.end_row();
+            }
+            ui.label("Modified");
+            ui.label(format_filetime(record.modified, &self.config.read().unwrap().date_format));
+            ui.end_row();
+            ui.label("Attributes");
+            ui.label(preview.and_then(|p| p.attributes).map(format_attributes).unwrap_or_else(|| "---".to_string()));
+            ui.end_row();
+            if record.is_reparse_point {
+                // Cloud placeholders and dedup files are reparse points with no target
+                ui.label("Links to");
+                let target = preview.and_then(|p| p.link_target.clone()).unwrap_or_else(|| "---".to_string());
+                ui.add(egui::Label::new(target).wrap());
+                ui.end_row();
+            }
         });
+        ui.separator();
 
-        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.label(format!("{} files indexed", self.indexer.records.len()));
-                ui.separator();
-                ui.label(format!("{} results", self.results.len()));
-                if self.indexer.records.len() == 0 {
-                    ui.separator();
-                    ui.spinner();
-                    ui.label("Indexing C:\\...");
+        match preview.map(|p| &p.content) {
+            None => { ui.spinner(); }
+            Some(PreviewContent::Image(_)) => {
+                if let Some(texture) = &self.preview_texture {
+                    ui.add(egui::Image::new(egui::load::SizedTexture::from_handle(texture)).max_size(ui.available_size()));
                 }
-                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    ui.label(egui::RichText::new("Rivet Alpha").text_style(egui::TextStyle::Small).weak());
+            }
+            Some(PreviewContent::Text { text, truncated }) => {
+                if *truncated {
+                    ui.label(egui::RichText::new("Showing the start of the file").weak());
+                }
+                egui::ScrollArea::both().auto_shrink([false, false]).show(ui, |ui| {
+                    ui.add(egui::Label::new(egui::RichText::new(text).monospace()).extend());
                 });
-            });
+            }
+            Some(PreviewContent::Binary) => {
+                ui.label(egui::RichText::new("Binary file, no preview available").weak());
+            }
+            Some(PreviewContent::Unsupported) => {
+                ui.label(egui::RichText::new("No preview available").weak());
+            }
+            Some(PreviewContent::Error(e)) => {
+                ui.colored_label(ui.visuals().error_fg_color, e);
+            }
+        }
+    }
+
+    fn export_results(&mut self) {
+        if self.export.is_some() || self.results.is_empty() {
+            return;
+        }
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export results")
+            .set_file_name("results.csv")
+            .add_filter("CSV", &["csv"])
+            .add_filter("JSON", &["json"])
+            .save_file()
+        else {
+            return;
+        };
+        self.export_status = None;
+        self.export = Some(ExportJob::start(Arc::clone(&self.indexer), self.results.clone(), path));
+    }
+
+    fn poll_export(&mut self) {
+        let Some(result) = self.export.as_ref().and_then(|job| job.finished()) else {
+            return;
+        };
+        let job = self.export.take().unwrap();
+        self.export_status = Some(match result {
+            Ok(()) => format!("Exported {} results to {}", job.total, job.path.display()),
+            Err(e) => {
+                tracing::error!(path = %job.path.display(), error = %e, "Export failed");
+                format!("Export failed: {}", e)
+            }
         });
+    }
 
-        // Request a repaint to keep UI updated if background indexing is happening
-        if self.results.is_empty() && !self.search_query.is_empty() {
-             ctx.request_repaint();
+    /// Takes in the volumes gathered by the last refresh and starts the next one once
+    /// `DISK_INFO_REFRESH` has passed, or straight away after `refresh_volumes_now`.
+    fn refresh_disk_info(&mut self) {
+        if let Some(rx) = &self.volumes_rx {
+            match rx.try_recv() {
+                Ok(volumes) => self.volumes = volumes,
+                Err(std::sync::mpsc::TryRecvError::Empty) => return,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {}
+            }
+            self.volumes_rx = None;
         }
+        if self.last_disk_info_update.is_some_and(|at| at.elapsed() < DISK_INFO_REFRESH) {
+            return;
+        }
+        self.last_disk_info_update = Some(std::time::Instant::now());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let volume_manager = Arc::clone(&self.volume_manager);
+        let indexer = Arc::clone(&self.indexer);
+        std::thread::spawn(move || {
+            let volumes = volume_manager.lock().unwrap().volumes();
+            let volumes = volumes.into_iter()
+                .map(|volume| {
+                    let info = disk_info(&volume);
+                    (volume, info)
+                })
+                .collect();
+            let _ = tx.send(volumes);
+            indexer.request_repaint();
+        });
+        self.volumes_rx = Some(rx);
+    }
+
+    /// Gathers the volumes again on the next frame, after the set of indexed ones changed.
+    fn refresh_volumes_now(&mut self) {
+        self.last_disk_info_update = None;
+    }
+
+    /// One "C: (Windows) Free: 45.2 GB / 238 GB" entry per indexed volume.
+    fn disk_info_labels(&self, ui: &mut egui::Ui) {
+        for (volume, info) in &self.volumes {
+            ui.separator();
+            match info {
+                Some(info) if info.label.is_empty() => {
+                    ui.label(format!("{} Free: {} / {}", volume, format_size(info.free_bytes), format_size(info.total_bytes)));
+                }
+                Some(info) => {
+                    ui.label(format!(
+                        "{} ({}) Free: {} / {}",
+                        volume, info.label, format_size(info.free_bytes), format_size(info.total_bytes)
+                    ));
+                }
+                None => {
+                    ui.label(format!("{} N/A", volume));
+                }
+            }
+        }
+    }
+
+    fn log_panel(&mut self, ui: &mut egui::Ui) {
+        if self.logs_read_at.is_none_or(|at| at.elapsed() >= LOG_PANEL_REFRESH) {
+            self.log_lines = logging::tail(LOG_PANEL_LINES);
+            self.logs_read_at = Some(std::time::Instant::now());
+        }
+        ui.horizontal(|ui| {
+            ui.strong("Logs");
+            if let Some(dir) = logging::log_dir() {
+                if ui.small_button("📂").on_hover_text("Open log folder").clicked() {
+                    self.open_file(dir.as_os_str());
+                }
+            }
+        });
+        egui::ScrollArea::both()
+            .stick_to_bottom(true)
+            .auto_shrink([false, false])
+            .show(ui, |ui| {
+                for line in &self.log_lines {
+                    ui.label(egui::RichText::new(line).monospace().size(11.0));
+                }
+            });
+    }
+
+    /// The search box's text as a query, with the filters set around it applied.
+    fn build_query(&self, config: &Config) -> SearchQuery {
+        let mut search = SearchQuery::from_config(&self.search_query, self.case_sensitive, config);
+        search.attributes = self.attribute_filter;
+        search.within = self.search_folder.as_ref().map(|(id, _)| *id);
+        if self.search_mode == SearchMode::Fuzzy {
+            search = search.fuzzy();
+        }
+        search
+    }
+
+    fn perform_search(&mut self) {
+        // The results are about to answer the search box instead
+        self.view_scan = None;
+        if self.search_query.is_empty() {
+            self.results.clear();
+            self.results_changed();
+            self.last_query.clear();
+            self.highlight = None;
+            self.query_error = None;
+            self.content_search = None;
+            self.content_matches.clear();
+            if self.query_active && self.sort_column == SortColumn::Relevance {
+                self.sort_column = SortColumn::Name;
+                self.sort_ascending = true;
+            }
+            self.query_active = false;
+            return;
+        }
+        if !self.query_active {
+            self.query_active = true;
+            self.sort_column = SortColumn::Relevance;
+            self.sort_ascending = false;
+        }
+
+        let config = self.config.read().unwrap();
+        let search = self.build_query(&config);
+        // Kept as typed when matching case, so narrowing compares the same way
+        let query = if self.case_sensitive { self.search_query.clone() } else { self.search_query.to_lowercase() };
+
+        // A query containing the previous one can only match a subset of its results,
+        // so narrow the existing (already sorted) list instead of rescanning the index.
+        // Not possible if the previous scan stopped at the result cap, and wildcards,
+        // filters and OR/NOT don't narrow that way. Content matches always need a fresh search.
+        self.query_error = search.error().map(str::to_string);
+        let refinable = !self.content_mode && search.is_plain() && SearchQuery::new(&self.last_query, self.case_sensitive).is_plain();
+        if refinable && !self.last_query.is_empty() && !self.results_truncated && query.contains(&self.last_query) {
+            drop(config);
+            let indexer = &self.indexer;
+            self.results.retain(|id| indexer.records.get(id).is_some_and(|r| search.matches(&r)));
+            self.results_changed();
+            self.last_query = query;
+            self.highlight = Some(search);
+            // Scores depend on the query, so only relevance order needs redoing
+            if self.sort_column == SortColumn::Relevance {
+                self.sort_results();
+            }
+            return;
+        }
+
+        self.results_generation = self.indexer.generation();
+        // The exclusion box can drop any number of matches, so the limit only applies
+        // once it has
+        let limit = config.result_limit;
+        let excluding = self.show_exclude && !self.exclude_query.trim().is_empty();
+        let found = self.indexer.search(&search, if excluding { usize::MAX } else { limit });
+        drop(config);
+
+        self.results = found.ids;
+        self.apply_exclusion();
+        self.results_truncated = found.truncated || self.results.len() > limit;
+        self.results.truncate(limit);
+        self.results_changed();
+        self.last_query = query;
+        self.highlight = Some(search);
+        self.sort_results();
+        self.start_content_search();
+    }
+
+    /// Runs the current query again once the index has changed since it last ran,
+    /// at most every `LIVE_SEARCH_INTERVAL`, so new and deleted files show up without
+    /// touching the search box. The selection is kept if its file is still there.
+    fn refresh_stale_results(&mut self) {
+        // Content searches read files and would restart on every change
+        if !self.query_active || self.content_mode || self.indexer.generation() == self.results_generation {
+            return;
+        }
+        if self.last_live_search.is_some_and(|at| at.elapsed() < LIVE_SEARCH_INTERVAL) {
+            return;
+        }
+        self.last_live_search = Some(std::time::Instant::now());
+
+        // A repeated query would only narrow the old list, missing new files
+        self.last_query.clear();
+        self.perform_search();
+        if self.selected_id.is_some_and(|id| !self.indexer.records.contains_key(&id)) {
+            self.selected_id = None;
+        }
+    }
+
+    /// In content mode, hands the name matches to a background search for
+    /// `content_query`; `poll_content_search` puts the files that contain it back.
+    fn start_content_search(&mut self) {
+        self.content_search = None;
+        self.content_matches.clear();
+        if !self.content_mode || self.content_query.is_empty() || self.results.is_empty() {
+            return;
+        }
+        let candidates = std::mem::take(&mut self.results);
+        self.results_changed();
+        let config = self.config.read().unwrap();
+        self.content_search = Some(ContentSearch::start(Arc::clone(&self.indexer), candidates, &self.content_query, &config));
+    }
+
+    fn poll_content_search(&mut self) {
+        let Some(matches) = self.content_search.as_ref().and_then(|search| search.finished()) else {
+            return;
+        };
+        self.content_search = None;
+        self.results = matches.keys().copied().collect();
+        self.results_changed();
+        self.content_matches = matches;
+        self.sort_results();
+    }
+
+    /// Hashes candidate files on a background thread and then shows every duplicate.
+    fn find_duplicates(&mut self) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let indexer = Arc::clone(&self.indexer);
+        let token = self.cancel_token.child_token();
+        std::thread::spawn(move || {
+            indexer.compute_hashes(&token);
+            let _ = tx.send(indexer.find_duplicates());
+        });
+        self.duplicate_scan = Some(rx);
+    }
+
+    /// Measures `folder` on a background thread, see `Indexer::compute_folder_size`.
+    fn measure_folder(&mut self, folder: u128) {
+        if self.folder_size_jobs.contains_key(&folder) {
+            return;
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let indexer = Arc::clone(&self.indexer);
+        std::thread::spawn(move || {
+            let _ = tx.send(indexer.compute_folder_size(folder));
+            indexer.request_repaint();
+        });
+        self.folder_size_jobs.insert(folder, rx);
+    }
+
+    fn poll_folder_sizes(&mut self) {
+        let running = self.folder_size_jobs.len();
+        self.folder_size_jobs.retain(|_, rx| matches!(rx.try_recv(), Err(std::sync::mpsc::TryRecvError::Empty)));
+        // A measured folder may belong elsewhere in a list sorted by recursive size
+        if self.folder_size_jobs.len() != running && self.sort_column == SortColumn::RecursiveSize {
+            self.sort_results();
+        }
+    }
+
+    /// Gathers `view` on a background thread, narrowed by whatever is in the search box.
+    fn start_view(&mut self, view: QuickView) {
+        let config = self.config.read().unwrap();
+        let query = self.build_query(&config);
+        let count = config.result_limit;
+        drop(config);
+        let since = match view {
+            QuickView::Largest => 0,
+            QuickView::RecentDay => filetime_now() - FILETIME_TICKS_PER_DAY,
+            QuickView::RecentWeek => filetime_now() - 7 * FILETIME_TICKS_PER_DAY,
+        };
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        let indexer = Arc::clone(&self.indexer);
+        let thread_query = query.clone();
+        std::thread::spawn(move || {
+            let ids = match view {
+                QuickView::Largest => indexer.largest_files(&thread_query, count),
+                QuickView::RecentDay | QuickView::RecentWeek => indexer.recent_files(&thread_query, since, count),
+            };
+            let _ = tx.send(ids);
+            indexer.request_repaint();
+        });
+        self.view_scan = Some(ViewScan { view, query, rx });
+    }
+
+    fn poll_view_scan(&mut self) {
+        let Some(ids) = self.view_scan.as_ref().and_then(|scan| scan.rx.try_recv().ok()) else {
+            return;
+        };
+        let scan = self.view_scan.take().unwrap();
+        self.results = ids;
+        self.results_changed();
+        self.results_truncated = false;
+        // Live re-searches would swap the view for the plain query's results, and
+        // a longer query has to search afresh rather than narrow the view
+        self.query_active = false;
+        self.last_query.clear();
+        self.highlight = (!self.search_query.is_empty()).then_some(scan.query);
+        self.content_search = None;
+        self.content_matches.clear();
+        (self.sort_column, self.sort_ascending) = match scan.view {
+            QuickView::Largest => (SortColumn::Size, false),
+            QuickView::RecentDay | QuickView::RecentWeek => (SortColumn::Modified, false),
+        };
+        self.sort_results();
+    }
+
+    /// The menu of `QuickView`s next to the search box.
+    fn views_menu(&mut self, ui: &mut egui::Ui) {
+        let mut chosen = None;
+        ui.add_enabled_ui(self.view_scan.is_none(), |ui| {
+            ui.menu_button("⚡", |ui| {
+                for view in QuickView::ALL {
+                    if ui.button(view.label()).clicked() {
+                        chosen = Some(view);
+                        ui.close_menu();
+                    }
+                }
+            }).response.on_hover_text("Built-in views, narrowed by the search text if there is any");
+        });
+        if let Some(view) = chosen {
+            self.start_view(view);
+        }
+    }
+
+    fn poll_duplicate_scan(&mut self) {
+        let Some(groups) = self.duplicate_scan.as_ref().and_then(|rx| rx.try_recv().ok()) else {
+            return;
+        };
+        self.duplicate_scan = None;
+
+        // Biggest files first, with each set of identical files kept together
+        let indexer = &self.indexer;
+        let mut groups: Vec<Vec<u128>> = groups.into_values().collect();
+        groups.sort_by_cached_key(|ids| std::cmp::Reverse(indexer.records.get(&ids[0]).map_or(0, |r| r.size)));
+        self.results = groups.into_iter().flatten().collect();
+        self.results_changed();
+        self.duplicates = self.results.iter().copied().collect();
+        self.results_truncated = false;
+
+        // The list no longer answers the search box
+        self.search_query.clear();
+        self.last_query.clear();
+        self.highlight = None;
+        self.query_active = false;
+        self.content_search = None;
+        self.content_matches.clear();
+    }
+
+    /// The "Content" toggle and, while it's on, the box for the text to look for.
+    fn content_search_box(&mut self, ui: &mut egui::Ui) {
+        let mut changed = ui.checkbox(&mut self.content_mode, "Content")
+            .on_hover_text("Only show text files containing the given text")
+            .changed();
+        if self.content_mode {
+            changed |= ui.add(
+                egui::TextEdit::singleline(&mut self.content_query)
+                    .hint_text("Text in file...")
+                    .desired_width(180.0)
+            ).changed();
+        }
+        if changed {
+            // The current results were filtered for another mode or text
+            self.last_query.clear();
+            self.perform_search();
+        }
+    }
+
+    /// Drops the results whose name also matches the exclusion box. Runs after the main
+    /// query, so it composes with its filters; narrowing a query keeps it applied.
+    fn apply_exclusion(&mut self) {
+        let text = self.exclude_query.trim();
+        if !self.show_exclude || text.is_empty() {
+            return;
+        }
+        let mut exclude = SearchQuery::new(text, self.case_sensitive);
+        // Hidden files are only in the results if they're wanted, so they can be excluded too
+        exclude.include_hidden = true;
+        if self.exclude_mode == SearchMode::Fuzzy {
+            exclude = exclude.fuzzy();
+        }
+        if exclude.error().is_some() {
+            return;
+        }
+        let indexer = &self.indexer;
+        self.results.retain(|id| !indexer.records.get(id).is_some_and(|r| exclude.matches(&r)));
+    }
+
+    /// Asks for a folder and limits the search to what's beneath it.
+    fn pick_search_folder(&mut self) {
+        let Some(path) = rfd::FileDialog::new().set_title("Search in folder").pick_folder() else {
+            return;
+        };
+        let path = path.to_string_lossy().into_owned();
+        match self.indexer.find_folder(&path) {
+            Some(id) => self.set_search_folder(Some((id, path))),
+            None => self.indexer.log_error(format!("Can't search in {}: it isn't an indexed folder", path)),
+        }
+    }
+
+    fn set_search_folder(&mut self, folder: Option<(u128, String)>) {
+        self.search_folder = folder;
+        self.pending_search_folder = None;
+        // Widening the scope can bring back results, so this can't just narrow
+        self.last_query.clear();
+        self.perform_search();
+    }
+
+    /// Limits searches to the folder saved last session, once it's in the index. Tried at
+    /// startup for an index loaded from disk, then after each volume finishes indexing.
+    fn restore_search_folder(&mut self) {
+        let Some(path) = &self.pending_search_folder else {
+            return;
+        };
+        if let Some(id) = self.indexer.find_folder(path) {
+            let path = path.clone();
+            self.set_search_folder(Some((id, path)));
+        }
+    }
+
+    /// The folder the search is limited to, as a chip above the results that removes
+    /// the limit when clicked.
+    fn search_folder_chip(&mut self, ui: &mut egui::Ui) {
+        let Some((_, path)) = &self.search_folder else {
+            return;
+        };
+        let mut remove = false;
+        ui.horizontal(|ui| {
+            ui.label(egui::RichText::new("In folder:").small());
+            remove = ui.selectable_label(true, egui::RichText::new(format!("📂 {}  ✖", path)).small())
+                .on_hover_text("Search everywhere again")
+                .clicked();
+        });
+        if remove {
+            self.set_search_folder(None);
+        }
+    }
+
+    /// The "Exclude:" row under the search bar, with a red border to set it apart
+    /// from the query that selects results.
+    fn exclude_box(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Exclude:");
+            let error_color = ui.visuals().error_fg_color;
+            egui::Frame::new()
+                .stroke(egui::Stroke::new(1.0, error_color))
+                .inner_margin(1.0)
+                .show(ui, |ui| {
+                    changed |= ui.add(
+                        egui::TextEdit::singleline(&mut self.exclude_query)
+                            .hint_text("Names to leave out, e.g. *.log")
+                            .desired_width(320.0)
+                    ).changed();
+                });
+            changed |= ui.selectable_value(&mut self.exclude_mode, SearchMode::Normal, "Exact").changed();
+            changed |= ui.selectable_value(&mut self.exclude_mode, SearchMode::Fuzzy, "~ Fuzzy").changed();
+        });
+        if changed {
+            // Results left out before may match now, so they can't just be narrowed
+            self.last_query.clear();
+            self.perform_search();
+        }
+    }
+
+    /// A clickable column header that sorts by `column`, flipping direction when it is
+    /// already the sort column. Shift+click makes it the secondary sort column instead.
+    /// Right-click offers the optional columns.
+    fn sort_header(&mut self, ui: &mut egui::Ui, column: SortColumn, label: &str) {
+        // The Size header also stands for its recursive variant
+        let recursive = column == SortColumn::Size && self.sort_column == SortColumn::RecursiveSize;
+        let (column, label) = if recursive { (SortColumn::RecursiveSize, "Size (recursive)") } else { (column, label) };
+        let arrow = |ascending: bool| if ascending { "🔼" } else { "🔽" };
+        let text = match self.secondary_sort {
+            _ if self.sort_column == column => format!("{} {}", label, arrow(self.sort_ascending)),
+            Some((secondary, ascending)) if secondary == column => format!("{} {}²", label, arrow(ascending)),
+            _ => label.to_string(),
+        };
+        let response = ui.button(text).on_hover_text("Shift+click to sort by this column second");
+        if response.clicked() {
+            if ui.input(|i| i.modifiers.shift) {
+                if self.sort_column != column {
+                    self.secondary_sort = match self.secondary_sort {
+                        Some((secondary, ascending)) if secondary == column => Some((column, !ascending)),
+                        _ => Some((column, true)),
+                    };
+                }
+            } else if self.sort_column == column {
+                self.sort_ascending = !self.sort_ascending;
+            } else {
+                self.sort_column = column;
+                self.sort_ascending = true;
+                if self.secondary_sort.is_some_and(|(secondary, _)| secondary == column) {
+                    self.secondary_sort = None;
+                }
+            }
+            self.sort_results();
+        }
+        response.context_menu(|ui| {
+            if matches!(column, SortColumn::Size | SortColumn::RecursiveSize) {
+                let mut include_contents = recursive;
+                if ui.checkbox(&mut include_contents, "Sort folders by size of contents").changed() {
+                    self.sort_column = if include_contents { SortColumn::RecursiveSize } else { SortColumn::Size };
+                    self.sort_results();
+                    ui.close_menu();
+                }
+                ui.separator();
+            }
+            self.column_menu(ui);
+        });
+    }
+
+    /// Refreshes the list behind the largest folders chart.
+    fn load_largest_dirs(&mut self) {
+        let dirs = self.indexer.largest_dirs(LARGEST_DIRS_SHOWN);
+        let ids: Vec<u128> = dirs.iter().map(|&(id, _)| id).collect();
+        let paths = self.indexer.get_full_paths(&ids);
+        self.largest_dirs = paths.into_iter().map(|p| p.path).zip(dirs.into_iter().map(|(_, size)| size)).collect();
+    }
+
+    fn dir_chart_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_dir_chart;
+        egui::Window::new("Largest folders").open(&mut open).default_width(520.0).show(ctx, |ui| {
+            if self.largest_dirs.is_empty() {
+                ui.label(egui::RichText::new("Folder sizes are computed once file sizes have been fetched").weak());
+                return;
+            }
+            let max = self.largest_dirs[0].1.max(1) as f32;
+            egui::Grid::new("largest_dirs").num_columns(2).striped(true).show(ui, |ui| {
+                for (path, size) in &self.largest_dirs {
+                    ui.add(egui::Label::new(path).truncate());
+                    ui.add(egui::ProgressBar::new(*size as f32 / max).desired_width(200.0).text(format_size(*size)));
+                    ui.end_row();
+                }
+            });
+        });
+        self.show_dir_chart = open;
+    }
+
+    /// Strip under the search bar with the newest error until it's dismissed, since the
+    /// windowed build has no console to print it to.
+    fn error_banner(&mut self, ctx: &egui::Context) {
+        let latest = self.indexer.error_log.lock().unwrap().back().cloned();
+        let Some((at, message)) = latest.filter(|(at, _)| self.errors_seen_at < Some(*at)) else {
+            return;
+        };
+        egui::TopBottomPanel::top("error_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", message));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("✖").on_hover_text("Dismiss").clicked() {
+                        self.errors_seen_at = Some(at);
+                    }
+                    if ui.small_button("Details").clicked() {
+                        self.errors_seen_at = Some(at);
+                        self.show_error_log = true;
+                    }
+                });
+            });
+        });
+    }
+
+    /// Indexing and monitoring failures collected by the indexer, oldest first.
+    fn error_log_modal(&mut self, ctx: &egui::Context) {
+        let modal = egui::Modal::new(egui::Id::new("error_log")).show(ctx, |ui| {
+            ui.set_width(640.0);
+            ui.heading("Errors");
+            let entries: Vec<String> = self.indexer.error_log.lock().unwrap().iter()
+                .map(|(at, message)| format!("{}  {}", at.format("%Y-%m-%d %H:%M:%S"), message))
+                .collect();
+            egui::ScrollArea::vertical()
+                .max_height(360.0)
+                .stick_to_bottom(true)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    for entry in &entries {
+                        ui.label(egui::RichText::new(entry).monospace().size(11.0));
+                    }
+                });
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.button("Copy log").clicked() {
+                    ui.ctx().copy_text(entries.join("\n"));
+                }
+                if ui.button("Clear").clicked() {
+                    self.indexer.error_log.lock().unwrap().clear();
+                    self.show_error_log = false;
+                }
+                if ui.button("Close").clicked() {
+                    self.show_error_log = false;
+                }
+            });
+        });
+        if modal.should_close() {
+            self.show_error_log = false;
+        }
+    }
+
+    /// Buttons choosing the `SearchMode`, laid out right to left like the toolbar.
+    fn search_mode_buttons(&mut self, ui: &mut egui::Ui) {
+        let fuzzy = ui.selectable_value(&mut self.search_mode, SearchMode::Fuzzy, "~ Fuzzy")
+            .on_hover_text("Match names containing the typed letters in order, e.g. mftidx for mft_indexer.rs");
+        let normal = ui.selectable_value(&mut self.search_mode, SearchMode::Normal, "Exact")
+            .on_hover_text("Match words, wildcards and filters like ext: and size:");
+        if fuzzy.changed() || normal.changed() {
+            // The previous results were matched the other way, so they can't be narrowed
+            self.last_query.clear();
+            self.perform_search();
+        }
+    }
+
+    /// Toggle for matching case, which fuzzy mode decides by itself from the typed text.
+    fn case_toggle(&mut self, ui: &mut egui::Ui) {
+        let enabled = self.search_mode == SearchMode::Normal;
+        let toggle = ui.add_enabled(enabled, egui::SelectableLabel::new(self.case_sensitive, "Aa"))
+            .on_hover_text("Match case");
+        if toggle.clicked() {
+            self.case_sensitive = !self.case_sensitive;
+            // Results matched the other way can't be narrowed
+            self.last_query.clear();
+            self.perform_search();
+        }
+    }
+
+    /// Small button switching the date columns between full and relative dates.
+    fn date_format_toggle(&mut self, ui: &mut egui::Ui) {
+        let (text, hover) = match self.date_format {
+            DateFormat::Absolute => ("🕑", "Show how long ago"),
+            DateFormat::Relative => ("📅", "Show full dates"),
+        };
+        if ui.small_button(text).on_hover_text(hover).clicked() {
+            self.date_format = match self.date_format {
+                DateFormat::Absolute => DateFormat::Relative,
+                DateFormat::Relative => DateFormat::Absolute,
+            };
+        }
+    }
+
+    /// The column's width as the user last left it, or `default`.
+    fn column_width(&self, label: &str, default: f32) -> f32 {
+        self.column_widths.get(label).copied().unwrap_or(default)
+    }
+
+    /// Toggles for the optional result columns, offered on right-click of any header.
+    /// Shown columns keep their place; newly shown ones go at the end.
+    fn column_menu(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled(false, egui::Checkbox::new(&mut true, "Name column"));
+        for column in ResultColumn::ALL {
+            let mut shown = self.columns.contains(&column);
+            if ui.checkbox(&mut shown, format!("{} column", column.title())).changed() {
+                if shown {
+                    self.columns.push(column);
+                } else {
+                    self.hide_column(column);
+                }
+                ui.close_menu();
+            }
+        }
+        ui.label(egui::RichText::new("Drag headers to reorder").weak().small());
+    }
+
+    /// Removes `column` from the table, sorting by Name instead if it was sorted by.
+    fn hide_column(&mut self, column: ResultColumn) {
+        self.columns.retain(|&c| c != column);
+        if self.secondary_sort.is_some_and(|(secondary, _)| column.sorts_by(secondary)) {
+            self.secondary_sort = None;
+        }
+        if column.sorts_by(self.sort_column) {
+            self.sort_column = SortColumn::Name;
+            self.sort_ascending = true;
+            self.sort_results();
+        }
+    }
+
+    /// Moves `column` to where `target` is, shifting the columns in between.
+    fn move_column(&mut self, column: ResultColumn, target: ResultColumn) {
+        let (Some(from), Some(to)) = (
+            self.columns.iter().position(|&c| c == column),
+            self.columns.iter().position(|&c| c == target),
+        ) else {
+            return;
+        };
+        let column = self.columns.remove(from);
+        self.columns.insert(to, column);
+    }
+
+    /// Header contents for `column`: a sort button, or a plain label if it can't be sorted.
+    fn column_header(&mut self, ui: &mut egui::Ui, column: ResultColumn) {
+        match column.sort_column() {
+            Some(sort) => {
+                self.sort_header(ui, sort, column.title());
+                if column == ResultColumn::Modified {
+                    self.date_format_toggle(ui);
+                }
+            }
+            None => {
+                ui.add(egui::Label::new(column.title()).sense(egui::Sense::click()))
+                    .context_menu(|ui| self.column_menu(ui));
+            }
+        }
+    }
+
+    /// One result's cell in `column`. Rows whose size or dates aren't known yet are
+    /// added to `unsized_rows` to be fetched first.
+    fn result_cell(&self, ui: &mut egui::Ui, column: ResultColumn, row: &ResultRow, unsized_rows: &mut Vec<u128>) {
+        let record = row.record;
+        match column {
+            ResultColumn::Folder => {
+                ui.add(egui::Label::new(egui::RichText::new(parent_folder(&row.full_path)).color(ui.visuals().weak_text_color())).truncate());
+            }
+            ResultColumn::Path => {
+                ui.horizontal(|ui| {
+                    // An incomplete path would open the wrong folder, if any
+                    if ui.add_enabled(row.path_complete, egui::Button::new("📂")).on_hover_text("Open in Explorer").clicked() {
+                        self.open_folder(row.native_path);
+                    }
+                    let mut text = egui::RichText::new(&row.full_path).color(ui.visuals().weak_text_color());
+                    if !row.path_complete {
+                        text = text.italics();
+                    }
+                    let location = if record.is_dir {
+                        row.full_path.clone()
+                    } else {
+                        format!("Located in: {}", parent_folder(&row.full_path))
+                    };
+                    let mut label = ui.add(egui::Label::new(text).truncate().sense(egui::Sense::hover()))
+                        .on_hover_text(location);
+                    if !row.path_complete {
+                        label = label.on_hover_text("Some parent folders couldn't be found in the index");
+                    }
+                    if !row.link_paths.is_empty() {
+                        let paths: Vec<&str> = row.link_paths.iter().map(|p| p.path.as_str()).collect();
+                        label.on_hover_text(format!("Also linked as:\n{}", paths.join("\n")));
+                    }
+                });
+            }
+            ResultColumn::Size => {
+                if self.folder_size_jobs.contains_key(&row.id) {
+                    ui.spinner();
+                } else if record.is_dir {
+                    // Only known once the size pass has run, or the folder was measured
+                    let size = self.indexer.dir_sizes.get(&row.id).map(|size| *size);
+                    let mut text = egui::RichText::new(size.map(format_size).unwrap_or_default());
+                    if self.indexer.stale_dir_sizes.contains(&row.id) {
+                        text = text.weak();
+                        ui.label(text).on_hover_text("Files beneath it changed since; right-click to measure again");
+                    } else {
+                        ui.label(text);
+                    }
+                } else {
+                    if record.size == 0 && !unsized_rows.contains(&row.id) {
+                        unsized_rows.push(row.id);
+                    }
+                    ui.label(format_size(record.size));
+                }
+            }
+            ResultColumn::Modified | ResultColumn::Created | ResultColumn::Accessed => {
+                let filetime = match column {
+                    ResultColumn::Modified => record.modified,
+                    ResultColumn::Created => record.created,
+                    _ => record.accessed,
+                };
+                // Creation and access dates are fetched along with sizes
+                if column != ResultColumn::Modified && record.created == 0 && !unsized_rows.contains(&row.id) {
+                    unsized_rows.push(row.id);
+                }
+                ui.label(format_date(filetime, row.date_format, self.date_format));
+            }
+            ResultColumn::Extension => {
+                if record.is_dir {
+                    ui.label(egui::RichText::new("Folder").weak());
+                } else {
+                    ui.label(name_extension(&record.name, record.is_dir));
+                }
+            }
+            ResultColumn::Attributes => {
+                ui.label(egui::RichText::new(attribute_letters(record.attributes)).monospace());
+            }
+        }
+    }
+
+    /// Toolbar menu restricting results to files with the chosen attributes.
+    fn attribute_filter_menu(&mut self, ui: &mut egui::Ui) {
+        let label = if self.attribute_filter == 0 {
+            "🏷".to_string()
+        } else {
+            format!("🏷 {}", attribute_letters(self.attribute_filter))
+        };
+        let mut changed = false;
+        ui.menu_button(label, |ui| {
+            for (flag, letter, name) in ATTRIBUTE_FLAGS {
+                let mut set = self.attribute_filter & flag != 0;
+                if ui.checkbox(&mut set, format!("{} ({})", name, letter)).changed() {
+                    self.attribute_filter ^= flag;
+                    changed = true;
+                }
+            }
+            ui.separator();
+            if ui.add_enabled(self.attribute_filter != 0, egui::Button::new("Clear")).clicked() {
+                self.attribute_filter = 0;
+                changed = true;
+                ui.close_menu();
+            }
+        }).response.on_hover_text("Only show files with these attributes");
+
+        if changed {
+            // The previous results were filtered differently, so they can't be refined
+            self.last_query.clear();
+            self.perform_search();
+        }
+    }
+
+    /// Gets sizes for the visible files that don't have one yet: a running size pass
+    /// takes them next, otherwise a worker thread fetches them. Scrolling on before it
+    /// gets to a set replaces the set, so it only ever works on what's on screen.
+    fn fetch_visible_sizes(&mut self, ids: Vec<u128>) {
+        let fetching = self.indexer.indexing_states().iter().any(|(_, state)| state.phase == IndexPhase::FetchingMetadata);
+        if fetching {
+            self.indexer.request_sizes(ids);
+            return;
+        }
+        if ids.is_empty() || ids == self.ensured_sizes {
+            return;
+        }
+        self.ensured_sizes = ids.clone();
+        let worker = self.size_worker.get_or_insert_with(|| {
+            let (tx, rx) = std::sync::mpsc::channel::<Vec<u128>>();
+            let indexer = Arc::clone(&self.indexer);
+            std::thread::spawn(move || {
+                while let Ok(mut ids) = rx.recv() {
+                    // Only the newest set is still on screen
+                    while let Ok(newer) = rx.try_recv() {
+                        ids = newer;
+                    }
+                    indexer.ensure_sizes(&ids);
+                }
+            });
+            tx
+        });
+        let _ = worker.send(ids);
+    }
+
+    /// A progress bar per volume still being indexed, or "Ready" once all of them are.
+    fn indexing_progress(&self, ui: &mut egui::Ui) {
+        let states = self.indexer.indexing_states();
+        if states.is_empty() {
+            return;
+        }
+        if states.iter().all(|(_, state)| state.phase == IndexPhase::Ready) {
+            ui.separator();
+            ui.label(format!("Ready — {} files", format_count(self.indexer.records.len() as u64)));
+            return;
+        }
+
+        let busy = states.iter().any(|(_, state)| matches!(state.phase, IndexPhase::Enumerating | IndexPhase::FetchingMetadata));
+        if busy {
+            ui.separator();
+            let paused = self.indexer.is_paused();
+            let (icon, hint) = if paused { ("▶", "Resume indexing") } else { ("⏸", "Pause indexing and size fetching") };
+            if ui.button(icon).on_hover_text(hint).clicked() {
+                self.indexer.set_paused(!paused);
+            }
+            if paused {
+                ui.colored_label(ui.visuals().warn_fg_color, "Indexing paused");
+            }
+        }
+
+        for (id, state) in states {
+            let volume = match self.volumes.iter().find(|(volume, _)| volume.id == id) {
+                Some((volume, _)) => volume.to_string(),
+                None => id.to_string(),
+            };
+            let phase = match state.phase {
+                IndexPhase::Enumerating if self.indexer.is_catching_up(id) => "Catching up: reindexing",
+                IndexPhase::Enumerating => "Indexing",
+                IndexPhase::FetchingMetadata => "Fetching sizes of",
+                IndexPhase::Ready => continue,
+                IndexPhase::Error => {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().error_fg_color, format!("⚠ Indexing {} failed", volume))
+                        .on_hover_text("See the error log for why");
+                    continue;
+                }
+            };
+            let bar = match state.fraction() {
+                Some(fraction) => egui::ProgressBar::new(fraction)
+                    .text(format!("{} {}... {:.0}%", phase, volume, fraction * 100.0)),
+                // Walked volumes have no record count to go by
+                None => egui::ProgressBar::new(0.0)
+                    .animate(true)
+                    .text(format!("{} {}... {} records", phase, volume, format_count(state.processed))),
+            };
+            ui.separator();
+            ui.add(bar.desired_width(220.0)).on_hover_text(format!(
+                "{} of {} done, {}s so far",
+                format_count(state.processed),
+                if state.total > 0 { format!("~{}", format_count(state.total)) } else { "unknown".to_string() },
+                state.started.elapsed().as_secs(),
+            ));
+        }
+    }
+
+    /// Drops what was derived from `results`, to be rebuilt from the new list.
+    fn results_changed(&mut self) {
+        self.sum_results_size();
+        self.folder_groups = None;
+        self.extension_counts = None;
+        self.filtered_results = None;
+        self.highlight_spans.clear();
+        self.link_paths.clear();
+    }
+
+    /// Row of "ext (count)" chips under the table. Clicking chips shows only results
+    /// with those extensions; the filter is applied when drawing, not searching.
+    fn extension_chips(&mut self, ui: &mut egui::Ui) {
+        let counts = self.extension_counts.take().unwrap_or_else(|| {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for id in &self.results {
+                if let Some(record) = self.indexer.records.get(id) {
+                    *counts.entry(name_extension(&record.name, record.is_dir).to_lowercase()).or_default() += 1;
+                }
+            }
+            let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+            counts.sort_by(|(ext_a, count_a), (ext_b, count_b)| count_b.cmp(count_a).then_with(|| ext_a.cmp(ext_b)));
+            counts
+        });
+        let mut changed = false;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let all = egui::RichText::new("All").small();
+                if ui.selectable_label(self.active_extension_filter.is_empty(), all).clicked() {
+                    self.active_extension_filter.clear();
+                    changed = true;
+                }
+                for (ext, count) in counts.iter().take(EXTENSION_CHIPS_SHOWN) {
+                    let label = if ext.is_empty() { "(none)" } else { ext.as_str() };
+                    let text = egui::RichText::new(format!("{} ({})", label, count)).small();
+                    if ui.selectable_label(self.active_extension_filter.contains(ext), text).clicked() {
+                        if !self.active_extension_filter.remove(ext) {
+                            self.active_extension_filter.insert(ext.clone());
+                        }
+                        changed = true;
+                    }
+                }
+            });
+        });
+        self.extension_counts = Some(counts);
+        if changed {
+            self.filtered_results = None;
+        }
+    }
+
+    /// `results` with an extension chosen in the chip bar, or None when none is chosen.
+    fn filter_by_extension(&mut self) -> Option<Vec<u128>> {
+        if self.active_extension_filter.is_empty() {
+            return None;
+        }
+        let filtered = self.filtered_results.take().unwrap_or_else(|| {
+            self.results.iter()
+                .copied()
+                .filter(|id| self.indexer.records.get(id).is_some_and(|r| {
+                    self.active_extension_filter.contains(&name_extension(&r.name, r.is_dir).to_lowercase())
+                }))
+                .collect()
+        });
+        Some(filtered)
+    }
+
+    /// Buckets `results` by containing folder. Folders come in the order of their
+    /// first result and keep their results in sort order.
+    fn group_results(&self) -> Vec<(String, Vec<u128>)> {
+        let paths = self.indexer.get_full_paths(&self.results);
+        let mut groups: Vec<(String, Vec<u128>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (full_path, &id) in paths.iter().zip(&self.results) {
+            let folder = parent_folder(&full_path.path);
+            let position = *positions.entry(folder.to_string()).or_insert_with(|| {
+                groups.push((folder.to_string(), Vec::new()));
+                groups.len() - 1
+            });
+            groups[position].1.push(id);
+        }
+        groups
+    }
+
+    /// The "group by folder" view: a collapsible section per folder listing its results.
+    fn grouped_results(&mut self, ui: &mut egui::Ui, date_format: &str) {
+        // The table headers aren't shown here, so offer the sort order above the groups
+        ui.horizontal(|ui| {
+            ui.label("Sort by:");
+            if self.query_active {
+                self.sort_header(ui, SortColumn::Relevance, "Relevance");
+            }
+            self.sort_header(ui, SortColumn::Name, "Name");
+            self.sort_header(ui, SortColumn::Size, "Size");
+            self.sort_header(ui, SortColumn::Modified, "Date Modified");
+            self.date_format_toggle(ui);
+        });
+        let groups = self.folder_groups.take().unwrap_or_else(|| self.group_results());
+        let mut clicked_id = None;
+        let mut open_path = None;
+        egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+            for (folder, ids) in &groups {
+                egui::CollapsingHeader::new(format!("📁 {} ({})", folder, ids.len()))
+                    .id_salt(folder)
+                    .show(ui, |ui| {
+                        for &id in ids {
+                            let Some(record) = self.indexer.records.get(&id) else { continue };
+                            ui.horizontal(|ui| {
+                                let (icon, kind) = record_icon(&record);
+                                let selected = self.selected_id == Some(id);
+                                let mut response = ui.selectable_label(selected, format!("{} {}", icon, record.name));
+                                if record.is_reparse_point {
+                                    response = response.on_hover_text(kind);
+                                }
+                                if response.clicked() {
+                                    clicked_id = Some(id);
+                                }
+                                if response.double_clicked() {
+                                    open_path = Some(id);
+                                }
+                                let size = if record.is_dir {
+                                    self.indexer.dir_sizes.get(&id).map(|size| format_size(*size)).unwrap_or_default()
+                                } else {
+                                    format_size(record.size)
+                                };
+                                let details = format!("{}   {}", size, format_date(record.modified, date_format, self.date_format));
+                                ui.label(egui::RichText::new(details).color(ui.visuals().weak_text_color()));
+                            });
+                        }
+                    });
+            }
+        });
+        self.folder_groups = Some(groups);
+        if clicked_id.is_some() {
+            self.selected_id = clicked_id;
+        }
+        if let Some(id) = open_path {
+            let full_path = self.indexer.get_full_path(id);
+            if full_path.complete {
+                self.open_file(&full_path.native());
+            }
+        }
+    }
+
+    /// Whether a volume is still being read, so the index lacks some of its files.
+    fn is_indexing(&self) -> bool {
+        self.indexer.indexing_states().iter().any(|(_, state)| state.phase == IndexPhase::Enumerating)
+    }
+
+    /// Totals the sizes of `results`, when the list changes or more sizes are known.
+    fn sum_results_size(&mut self) {
+        let mut total = ResultsSize::default();
+        for id in &self.results {
+            let Some(record) = self.indexer.records.get(id) else { continue };
+            if record.is_dir {
+                total.folders += 1;
+            } else {
+                total.files += 1;
+                total.bytes += record.size;
+            }
+        }
+        self.results_size = total;
+    }
+
+    /// Shows the combined size of the current results and of the selected row.
+    fn results_size_label(&self, ui: &mut egui::Ui) {
+        if self.results.is_empty() {
+            return;
+        }
+        let total = &self.results_size;
+        ui.separator();
+        let count = |n: usize, noun: &str| format!("{} {}{}", format_count(n as u64), noun, if n == 1 { "" } else { "s" });
+        // e.g. "3 folders, 128 files, 4.2 GB"
+        let mut parts = Vec::new();
+        if total.folders > 0 {
+            parts.push(count(total.folders, "folder"));
+        }
+        parts.push(count(total.files, "file"));
+        parts.push(format_size(total.bytes));
+        ui.label(parts.join(", ")).on_hover_text("Folders aren't included in the total size");
+
+        let Some(id) = self.selected_id else {
+            return;
+        };
+        let size = match self.indexer.records.get(&id).map(|r| (r.is_dir, r.size)) {
+            Some((true, _)) => self.indexer.dir_sizes.get(&id).map(|size| *size),
+            Some((false, size)) => Some(size),
+            None => None,
+        };
+        ui.separator();
+        match size {
+            Some(size) => ui.label(format!("1 selected ({})", format_size(size))),
+            None => ui.label("1 selected"),
+        };
+    }
+
+    fn sort_results(&mut self) {
+        let natural = self.config.read().unwrap().natural_sort;
+        // Fuzzy scores rank better than anything the relevance heuristics can tell
+        if self.search_mode == SearchMode::Fuzzy && self.sort_column == SortColumn::Relevance {
+            let search = SearchQuery::new(&self.last_query, self.case_sensitive).fuzzy();
+            sort_fuzzy(&self.indexer, &mut self.results, &search, self.sort_ascending);
+        } else {
+            // Rank by the text names must contain, not filters and operators
+            let ranked_text = SearchQuery::new(&self.last_query, self.case_sensitive).required_text();
+            sort_ids(&self.indexer, &mut self.results, self.sort_column, self.sort_ascending, self.secondary_sort, &ranked_text, natural);
+        }
+        self.folder_groups = None;
+        self.filtered_results = None;
+    }
+}
+
+/// Relevance order for a fuzzy `query`: by match score, best first unless `ascending`.
+fn sort_fuzzy(indexer: &Indexer, ids: &mut Vec<u128>, query: &SearchQuery, ascending: bool) {
+    let mut scored: Vec<(u32, String, u128)> = ids.iter()
+        .map(|&id| {
+            let name = indexer.records.get(&id).map(|r| r.name.clone()).unwrap_or_default();
+            (query.fuzzy_score(&name).unwrap_or(0), name, id)
+        })
+        .collect();
+    scored.sort_by(|(score_a, name_a, id_a), (score_b, name_b, id_b)| {
+        let by_score = if ascending { score_a.cmp(score_b) } else { score_b.cmp(score_a) };
+        by_score.then_with(|| name_a.cmp(name_b)).then_with(|| id_a.cmp(id_b))
+    });
+    *ids = scored.into_iter().map(|(_, _, id)| id).collect();
+}
+
+/// A result's value in one sort column, looked up once up front so comparisons
+/// don't go back to the index.
+enum SortKey {
+    Missing,
+    Number(i64),
+    Text(String),
+}
+
+impl SortKey {
+    fn compare(&self, other: &Self, compare_text: fn(&str, &str) -> std::cmp::Ordering) -> std::cmp::Ordering {
+        match (self, other) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.cmp(b),
+            (SortKey::Text(a), SortKey::Text(b)) => compare_text(a, b),
+            // Records that vanished since the search sort first
+            (SortKey::Missing, SortKey::Missing) => std::cmp::Ordering::Equal,
+            (SortKey::Missing, _) => std::cmp::Ordering::Less,
+            (_, SortKey::Missing) => std::cmp::Ordering::Greater,
+            (SortKey::Number(_), SortKey::Text(_)) => std::cmp::Ordering::Less,
+            (SortKey::Text(_), SortKey::Number(_)) => std::cmp::Ordering::Greater,
+        }
+    }
+}
+
+/// Each of `ids`' value in `column`, in the same order.
+fn sort_keys(indexer: &Indexer, ids: &[u128], column: SortColumn, query: &str) -> Vec<SortKey> {
+    match column {
+        SortColumn::Folder | SortColumn::Path => {
+            // Resolved in one go; building them inside the comparator re-walked each
+            // parent chain O(n log n) times
+            indexer.get_full_paths(ids).into_iter()
+                .map(|full_path| {
+                    let path = if column == SortColumn::Folder {
+                        parent_folder(&full_path.path).to_string()
+                    } else {
+                        full_path.path
+                    };
+                    SortKey::Text(path)
+                })
+                .collect()
+        }
+        _ => {
+            let now = filetime_now();
+            ids.iter()
+                .map(|id| {
+                    let Some(r) = indexer.records.get(id) else {
+                        return SortKey::Missing;
+                    };
+                    match column {
+                        SortColumn::Relevance => SortKey::Number(relevance_score(&r.name, query, r.modified, now) as i64),
+                        SortColumn::Name => SortKey::Text(r.name.clone()),
+                        SortColumn::Extension => SortKey::Text(name_extension(&r.name, r.is_dir).to_lowercase()),
+                        SortColumn::Size => SortKey::Number(r.size as i64),
+                        SortColumn::RecursiveSize if r.is_dir => {
+                            SortKey::Number(indexer.dir_sizes.get(id).map_or(0, |size| *size) as i64)
+                        }
+                        SortColumn::RecursiveSize => SortKey::Number(r.size as i64),
+                        SortColumn::Modified => SortKey::Number(r.modified),
+                        SortColumn::Created => SortKey::Number(r.created),
+                        SortColumn::Accessed => SortKey::Number(r.accessed),
+                        SortColumn::Folder | SortColumn::Path => unreachable!(),
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Orders `ids` by `column`, then by `secondary` if given, then by name ascending, so
+/// the same results always come out in the same order. `query` is only used for
+/// relevance scores, and `natural` picks `natural_cmp` over `caseless_cmp` for the text
+/// columns.
+pub fn sort_ids(
+    indexer: &Indexer,
+    ids: &mut Vec<u128>,
+    column: SortColumn,
+    ascending: bool,
+    secondary: Option<(SortColumn, bool)>,
+    query: &str,
+    natural: bool,
+) {
+    let compare_text: fn(&str, &str) -> std::cmp::Ordering =
+        if natural { natural_cmp } else { caseless_cmp };
+    let directed = |order: std::cmp::Ordering, ascending: bool| if ascending { order } else { order.reverse() };
+
+    let primary = sort_keys(indexer, ids, column, query);
+    let secondary = secondary
+        .filter(|(secondary, _)| *secondary != column)
+        .map(|(secondary, ascending)| (sort_keys(indexer, ids, secondary, query), ascending));
+    let names = if column == SortColumn::Name { Vec::new() } else { sort_keys(indexer, ids, SortColumn::Name, query) };
+
+    let mut order: Vec<usize> = (0..ids.len()).collect();
+    order.sort_by(|&a, &b| {
+        directed(primary[a].compare(&primary[b], compare_text), ascending)
+            .then_with(|| match &secondary {
+                Some((keys, ascending)) => directed(keys[a].compare(&keys[b], compare_text), *ascending),
+                None => std::cmp::Ordering::Equal,
+            })
+            .then_with(|| if names.is_empty() { std::cmp::Ordering::Equal } else { names[a].compare(&names[b], compare_text) })
+            .then_with(|| ids[a].cmp(&ids[b]))
+    });
+    *ids = order.into_iter().map(|i| ids[i]).collect();
+}
+
+impl eframe::App for RivetApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let state = AppState {
+            search_history: self.search_history.clone(),
+            show_preview: self.show_preview,
+            columns: self.columns.clone(),
+            column_widths: self.column_widths.clone(),
+            date_format: self.date_format,
+            search_mode: self.search_mode,
+            group_by_folder: self.group_by_folder,
+            sort_column: self.sort_column,
+            sort_ascending: self.sort_ascending,
+            secondary_sort: self.secondary_sort,
+            case_sensitive: self.case_sensitive,
+            // Kept for next time if the folder hasn't been indexed yet this session
+            search_folder: self.search_folder.as_ref().map(|(_, path)| path.clone())
+                .or_else(|| self.pending_search_folder.clone()),
+        };
+        eframe::set_value(storage, eframe::APP_KEY, &state);
+    }
+
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.hotkey = None;
+        self.activation = None;
+        self.cancel_token.cancel();
+    }
+
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_tray(ctx);
+
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("🔍").size(20.0));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("⚙").on_hover_text("Settings").clicked() && self.settings_window.is_none() {
+                        let indexed = self.volume_manager.lock().unwrap().volumes();
+                        self.settings_window = Some(SettingsWindow::new(&self.config.read().unwrap(), &indexed));
+                    }
+                    let can_export = self.export.is_none() && !self.results.is_empty();
+                    if ui.add_enabled(can_export, egui::Button::new("💾")).on_hover_text("Export results to CSV or JSON").clicked() {
+                        self.export_results();
+                    }
+                    ui.toggle_value(&mut self.show_preview, "👁 Preview").on_hover_text("Show preview pane");
+                    ui.toggle_value(&mut self.group_by_folder, "📁 Group").on_hover_text("Group results by folder");
+                    if ui.toggle_value(&mut self.show_dir_chart, "📊").on_hover_text("Largest folders").clicked() && self.show_dir_chart {
+                        self.load_largest_dirs();
+                    }
+                    if ui.add_enabled(self.duplicate_scan.is_none(), egui::Button::new("Duplicates"))
+                        .on_hover_text("Find files with identical contents")
+                        .clicked()
+                    {
+                        self.find_duplicates();
+                    }
+                    self.case_toggle(ui);
+                    self.search_mode_buttons(ui);
+                    self.views_menu(ui);
+                    self.attribute_filter_menu(ui);
+                    self.content_search_box(ui);
+                    if ui.selectable_label(self.search_folder.is_some(), "📂").on_hover_text("Search in folder").clicked() {
+                        self.pick_search_folder();
+                    }
+                    if ui.selectable_label(self.show_exclude, "+").on_hover_text("Leave out names matching a second query").clicked() {
+                        self.show_exclude = !self.show_exclude;
+                        self.last_query.clear();
+                        self.perform_search();
+                    }
+                    self.search_box(ui);
+                });
+            });
+            if self.show_exclude {
+                self.exclude_box(ui);
+            }
+            ui.add_space(8.0);
+        });
+
+        if let Some(window) = &mut self.settings_window {
+            match window.show(ctx) {
+                SettingsAction::None => {}
+                SettingsAction::Close => self.settings_window = None,
+                SettingsAction::Rebuild(volume) => self.volume_manager.lock().unwrap().rebuild(volume),
+                SettingsAction::Save(config) => {
+                    self.settings_window = None;
+                    self.apply_settings(ctx, config);
+                }
+            }
+        }
+
+        self.drain_progress();
+        self.refresh_stale_results();
+        self.update_window_title(ctx);
+        self.poll_export();
+        self.poll_content_search();
+        self.poll_duplicate_scan();
+        self.poll_view_scan();
+        self.poll_folder_sizes();
+        self.error_banner(ctx);
+        if self.show_dir_chart {
+            self.dir_chart_window(ctx);
+        }
+        if self.show_error_log {
+            self.error_log_modal(ctx);
+        }
+        self.update_preview(ctx);
+        if self.show_preview {
+            egui::SidePanel::right("preview_panel")
+                .resizable(true)
+                .default_width(320.0)
+                .show(ctx, |ui| self.preview_panel(ui));
+        }
+
+        if self.show_logs {
+            egui::TopBottomPanel::bottom("log_panel")
+                .resizable(true)
+                .default_height(180.0)
+                .show(ctx, |ui| self.log_panel(ui));
+        }
+
+        let date_format = self.config.read().unwrap().date_format.clone();
+        let columns = self.columns.clone();
+        let show_path_column = columns.contains(&ResultColumn::Path);
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.search_folder.is_some() {
+                egui::TopBottomPanel::top("search_folder").show_inside(ui, |ui| self.search_folder_chip(ui));
+            }
+            if self.group_by_folder {
+                self.grouped_results(ui, &date_format);
+                return;
+            }
+            if !self.results.is_empty() {
+                egui::TopBottomPanel::bottom("extension_chips").show_inside(ui, |ui| self.extension_chips(ui));
+            }
+            let filtered = self.filter_by_extension();
+            let mut highlight_spans = std::mem::take(&mut self.highlight_spans);
+            let mut link_paths = std::mem::take(&mut self.link_paths);
+            let row_count = filtered.as_ref().map_or(self.results.len(), Vec::len);
+            let name_width = if show_path_column {
+                self.column_width("Name", 250.0)
+            } else {
+                self.column_width("Name", 250.0) + self.column_width("Path", 400.0)
+            };
+            let mut table = TableBuilder::new(ui)
+                // egui keeps widths by position, so each layout gets its own state and
+                // starts from the widths saved by title
+                .id_salt(("results_table", &columns))
+                .striped(true)
+                .resizable(true)
+                .sense(egui::Sense::click())
+                .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+                // Name takes over the Path column's width while that's hidden
+                .column(egui_extras::Column::initial(name_width).resizable(true).at_least(100.0).clip(true)); // Name
+            for &column in &columns {
+                let (width, min_width, clip) = column.layout();
+                table = table.column(
+                    egui_extras::Column::initial(self.column_width(column.title(), width)).resizable(true).at_least(min_width).clip(clip)
+                );
+            }
+            // Each header cell spans its column, so their widths are what to save
+            let mut widths: Vec<(&str, f32)> = Vec::new();
+            let mut moved = None;
+            let table = table
+                .header(20.0, |mut header| {
+                    let (rect, _) = header.col(|ui| {
+                        if self.query_active
+                            && ui.selectable_label(self.sort_column == SortColumn::Relevance, "★")
+                                .on_hover_text("Sort by relevance")
+                                .clicked()
+                            && self.sort_column != SortColumn::Relevance
+                        {
+                            self.sort_column = SortColumn::Relevance;
+                            self.sort_ascending = false;
+                            self.sort_results();
+                        }
+                        self.sort_header(ui, SortColumn::Name, "Name");
+                    });
+                    // Only the width it has next to the Path column is kept
+                    if show_path_column {
+                        widths.push(("Name", rect.width()));
+                    }
+                    for &column in &columns {
+                        let (rect, response) = header.col(|ui| {
+                            ui.dnd_drag_source(egui::Id::new(("result_column", column)), column, |ui| {
+                                self.column_header(ui, column);
+                            });
+                        });
+                        if let Some(dragged) = response.dnd_release_payload::<ResultColumn>() {
+                            moved = Some((*dragged, column));
+                        }
+                        widths.push((column.title(), rect.width()));
+                    }
+                });
+            for (label, width) in widths {
+                self.column_widths.insert(label.to_string(), width);
+            }
+            if let Some((column, target)) = moved {
+                self.move_column(column, target);
+            }
+
+            let mut clicked_id = None;
+            // Visible files whose size is still unknown, to be fetched first
+            let mut unsized_rows = Vec::new();
+            // A folder to measure, picked from its context menu
+            let mut measure = None;
+            table.body(|body| {
+                body.rows(22.0, row_count, |mut row| {
+                    let row_index = row.index();
+                    let id = match &filtered {
+                        Some(ids) => ids[row_index],
+                        None => self.results[row_index],
+                    };
+                    let full_path = self.indexer.get_full_path(id);
+                    let native_path = full_path.native();
+                    let (full_path, path_complete) = (full_path.path, full_path.complete);
+                    // Resolved before the record guard below is taken
+                    let links = link_paths.entry(id).or_insert_with(|| self.indexer.link_paths(id));
+                    row.set_selected(self.selected_id == Some(id));
+                    if let Some(record) = self.indexer.records.get(&id) {
+                        row.col(|ui| {
+                            ui.horizontal(|ui| {
+                                if ui.add_enabled(path_complete, egui::Button::new("🚀")).on_hover_text("Open/Run File").clicked() {
+                                    self.open_file(&native_path);
+                                }
+                                let (icon, kind) = record_icon(&record);
+                                ui.label(icon).on_hover_text(kind);
+                                if self.duplicates.contains(&id) {
+                                    ui.colored_label(ui.visuals().warn_fg_color, "⚠ Duplicate");
+                                }
+                                if let Some(count) = self.content_matches.get(&id) {
+                                    ui.label(egui::RichText::new(count.to_string()).small().strong())
+                                        .on_hover_text(format!("{} matches in file", count));
+                                }
+                                let spans = highlight_spans.entry(id).or_insert_with(|| {
+                                    self.highlight.as_ref().map(|query| query.match_spans(&record.name)).unwrap_or_default()
+                                });
+                                let name = highlighted_name(ui, &record.name, spans);
+                                ui.add(egui::Label::new(name).truncate().sense(egui::Sense::hover()))
+                                    .on_hover_text(&record.name);
+                            });
+                        });
+                        let result_row = ResultRow {
+                            id,
+                            record: &record,
+                            full_path: full_path.clone(),
+                            native_path: &native_path,
+                            path_complete,
+                            link_paths: links,
+                            date_format: &date_format,
+                        };
+                        for &column in &columns {
+                            row.col(|ui| self.result_cell(ui, column, &result_row, &mut unsized_rows));
+                        }
+                    }
+                    let response = row.response();
+                    if response.clicked() {
+                        clicked_id = Some(id);
+                    }
+                    let is_dir = self.indexer.records.get(&id).is_some_and(|r| r.is_dir);
+                    if is_dir {
+                        response.context_menu(|ui| {
+                            if ui.button("Calculate size").clicked() {
+                                measure = Some(id);
+                                ui.close_menu();
+                            }
+                        });
+                    }
+                    // An incomplete path would open the wrong thing, if anything
+                    if response.double_clicked() && path_complete {
+                        if is_dir {
+                            self.open_folder(&native_path);
+                        } else {
+                            self.open_file(&native_path);
+                        }
+                    }
+                });
+            });
+            if clicked_id.is_some() {
+                self.selected_id = clicked_id;
+            }
+            self.fetch_visible_sizes(unsized_rows);
+            if let Some(folder) = measure {
+                self.measure_folder(folder);
+            }
+            self.filtered_results = filtered;
+            self.highlight_spans = highlight_spans;
+            self.link_paths = link_paths;
+        });
+
+        self.refresh_disk_info();
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!("{} files indexed", self.indexer.records.len()));
+                ui.separator();
+                match &self.filtered_results {
+                    Some(shown) if !self.active_extension_filter.is_empty() => {
+                        ui.label(format!("{} of {} results", shown.len(), self.results.len()));
+                    }
+                    _ => {
+                        ui.label(format!("{} results", self.results.len()));
+                    }
+                }
+                self.results_size_label(ui);
+                // Results are searched again as records stream in, see `refresh_stale_results`
+                if self.query_active && self.is_indexing() {
+                    ui.label(egui::RichText::new("⏳ Indexing in progress, results may be incomplete").weak())
+                        .on_hover_text("New matches appear as more files are indexed");
+                }
+                if let Some(error) = &self.query_error {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().error_fg_color, format!("⚠ {}", error));
+                }
+                self.disk_info_labels(ui);
+                for (volume, records) in &self.offline {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().warn_fg_color, format!("⏏ {} removed — {} entries hidden", volume, records))
+                        .on_hover_text("Plug the drive back in to search it again");
+                }
+                if let Some(error) = &self.hotkey_error {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().warn_fg_color, "⚠ Hotkey unavailable")
+                        .on_hover_text(format!("{}\nPick a different combination in Settings.", error));
+                }
+                let error_count = self.indexer.error_log.lock().unwrap().len();
+                if error_count > 0 {
+                    ui.separator();
+                    let label = egui::RichText::new(format!("⚠ {} errors", error_count)).color(ui.visuals().warn_fg_color);
+                    if ui.button(label).on_hover_text("Show indexing and monitoring errors").clicked() {
+                        self.show_error_log = true;
+                    }
+                }
+                let skipped: u64 = self.enumeration_errors.values().sum();
+                if skipped > 0 {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {} records skipped", skipped))
+                        .on_hover_text("Some MFT records could not be read; the index may be incomplete");
+                }
+                ui.separator();
+                if ui.small_button("⟳").on_hover_text("Rebuild index: re-read every volume from scratch").clicked() {
+                    self.volume_manager.lock().unwrap().rebuild_all();
+                }
+                self.indexing_progress(ui);
+                if self.duplicate_scan.is_some() {
+                    ui.separator();
+                    ui.spinner();
+                    ui.label("Finding duplicates...");
+                }
+                if let Some(scan) = &self.view_scan {
+                    ui.separator();
+                    ui.spinner();
+                    ui.label(format!("{}...", scan.view.label()));
+                }
+                if let Some(search) = &self.content_search {
+                    ui.separator();
+                    ui.add(
+                        egui::ProgressBar::new(search.progress())
+                            .desired_width(160.0)
+                            .text(format!("Searching {} files...", search.total))
+                    );
+                }
+                if let Some(job) = &self.export {
+                    ui.separator();
+                    ui.add(
+                        egui::ProgressBar::new(job.progress())
+                            .desired_width(160.0)
+                            .text(format!("Exporting {} results...", job.total))
+                    );
+                } else if let Some(status) = &self.export_status {
+                    ui.separator();
+                    ui.label(status);
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(egui::RichText::new("Rivet Alpha").text_style(egui::TextStyle::Small).weak());
+                    ui.toggle_value(&mut self.show_logs, "📜 Logs").on_hover_text("Show recent log output");
+                });
+            });
+        });
+
+        // The indexer and monitors request repaints themselves; this covers the GUI's
+        // own background jobs and throttled re-searches
+        if self.export.is_some() || self.content_search.is_some() || self.duplicate_scan.is_some() || self.view_scan.is_some() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        } else if self.show_logs {
+            ctx.request_repaint_after(LOG_PANEL_REFRESH);
+        } else if self.query_active && self.indexer.generation() != self.results_generation {
+            ctx.request_repaint_after(LIVE_SEARCH_INTERVAL);
+        } else if self.date_format == DateFormat::Relative {
+            ctx.request_repaint_after(RELATIVE_DATE_REFRESH);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn sorted(names: &[&str], compare: fn(&str, &str) -> Ordering) -> Vec<String> {
+        let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        names.sort_by(|a, b| compare(a, b));
+        names
+    }
+
+    #[test]
+    fn natural_order_compares_digit_runs_by_value() {
+        let cases: &[(&str, &str, Ordering)] = &[
+            ("IMG_9.jpg", "IMG_10.jpg", Ordering::Less),
+            ("IMG_10.jpg", "IMG_100.jpg", Ordering::Less),
+            ("file2.txt", "file10.txt", Ordering::Less),
+            ("v1.10", "v1.9", Ordering::Greater),
+            ("a", "a1", Ordering::Less),
+            ("1", "a", Ordering::Less),
+            // Leading zeros: same value, more zeros after fewer
+            ("file007", "file7", Ordering::Greater),
+            ("file007", "file08", Ordering::Less),
+            ("file0", "file00", Ordering::Less),
+            // Runs far beyond u64
+            ("n99999999999999999999999", "n100000000000000000000000", Ordering::Less),
+            ("n123456789012345678901234567890", "n123456789012345678901234567891", Ordering::Less),
+            ("n0000000000000000000000000000001", "n2", Ordering::Less),
+            // Case only breaks ties
+            ("Report.txt", "report.txt", Ordering::Less),
+            ("README", "notes", Ordering::Greater),
+            ("b", "B", Ordering::Greater),
+            // Non-ASCII text keeps UTF-8 order around the numbers
+            ("été2", "été10", Ordering::Less),
+            ("ä1", "z1", Ordering::Greater),
+            ("日本3.txt", "日本20.txt", Ordering::Less),
+            ("Ω10", "Ω9", Ordering::Greater),
+            ("same", "same", Ordering::Equal),
+        ];
+        for &(a, b, expected) in cases {
+            assert_eq!(natural_cmp(a, b), expected, "{} vs {}", a, b);
+            assert_eq!(natural_cmp(b, a), expected.reverse(), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn natural_order_sorts_a_folder_listing() {
+        assert_eq!(
+            sorted(&["IMG_100.jpg", "img_10.jpg", "IMG_9.jpg", "IMG_010.jpg", "IMG_1.jpg"], natural_cmp),
+            ["IMG_1.jpg", "IMG_9.jpg", "img_10.jpg", "IMG_010.jpg", "IMG_100.jpg"],
+        );
+    }
+
+    #[test]
+    fn case_is_ignored_with_or_without_natural_order() {
+        let names = ["banana", "Apple", "cherry", "apple"];
+        assert_eq!(sorted(&names, natural_cmp), ["Apple", "apple", "banana", "cherry"]);
+        assert_eq!(sorted(&names, caseless_cmp), ["Apple", "apple", "banana", "cherry"]);
+        // Without natural order digits compare as text
+        assert_eq!(sorted(&["file10", "File2"], caseless_cmp), ["file10", "File2"]);
+        assert_eq!(sorted(&["file10", "File2"], natural_cmp), ["File2", "file10"]);
     }
 }
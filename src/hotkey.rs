@@ -0,0 +1,114 @@
+use eframe::egui;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::JoinHandle;
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
+use windows::Win32::System::Threading::GetCurrentThreadId;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    RegisterHotKey, UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+};
+use windows::Win32::UI::WindowsAndMessaging::{GetMessageW, PostThreadMessageW, MSG, WM_HOTKEY, WM_QUIT};
+
+const HOTKEY_ID: i32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hotkey {
+    modifiers: HOT_KEY_MODIFIERS,
+    vk: u32,
+}
+
+impl Hotkey {
+    /// Parses combinations like "Alt+Space" or "Ctrl+Shift+F".
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut modifiers = HOT_KEY_MODIFIERS(0);
+        let mut vk = None;
+        for part in text.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= MOD_CONTROL,
+                "alt" => modifiers |= MOD_ALT,
+                "shift" => modifiers |= MOD_SHIFT,
+                "win" => modifiers |= MOD_WIN,
+                "space" => vk = Some(0x20),
+                key => vk = Some(virtual_key(key)?),
+            }
+        }
+        Some(Self { modifiers, vk: vk? })
+    }
+}
+
+fn virtual_key(key: &str) -> Option<u32> {
+    let bytes = key.as_bytes();
+    match bytes {
+        // Letters and digits use their uppercase ASCII codes as virtual key codes
+        [c] if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase() as u32),
+        [b'f', ..] => match key[1..].parse::<u32>() {
+            Ok(n @ 1..=12) => Some(0x70 + n - 1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Owns a thread with its own message loop that receives `WM_HOTKEY` for a
+/// registered global hotkey. Dropping it unregisters the hotkey.
+pub struct HotkeyListener {
+    thread_id: u32,
+    thread: Option<JoinHandle<()>>,
+    rx: Receiver<()>,
+}
+
+impl HotkeyListener {
+    /// Registers `hotkey` and calls `on_press` from the listener thread each time it fires.
+    pub fn start(
+        hotkey: &str,
+        ctx: &egui::Context,
+        on_press: impl Fn() + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let hotkey = Hotkey::parse(hotkey).ok_or_else(|| anyhow::anyhow!("Unrecognized hotkey \"{}\"", hotkey))?;
+        let (status_tx, status_rx) = channel();
+        let (tx, rx) = channel();
+        let ctx = ctx.clone();
+
+        let thread = std::thread::spawn(move || unsafe {
+            // Hotkeys are bound to the registering thread, so register and pump here
+            if let Err(e) = RegisterHotKey(HWND::default(), HOTKEY_ID, hotkey.modifiers | MOD_NOREPEAT, hotkey.vk) {
+                let _ = status_tx.send(Err(e));
+                return;
+            }
+            let _ = status_tx.send(Ok(GetCurrentThreadId()));
+
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+                if msg.message == WM_HOTKEY {
+                    on_press();
+                    let _ = tx.send(());
+                    ctx.request_repaint();
+                }
+            }
+            let _ = UnregisterHotKey(HWND::default(), HOTKEY_ID);
+        });
+
+        match status_rx.recv()? {
+            Ok(thread_id) => Ok(Self { thread_id, thread: Some(thread), rx }),
+            Err(e) => {
+                let _ = thread.join();
+                anyhow::bail!("Hotkey is already in use by another application ({})", e)
+            }
+        }
+    }
+
+    /// Whether the hotkey fired since the last call.
+    pub fn pressed(&self) -> bool {
+        self.rx.try_iter().count() > 0
+    }
+}
+
+impl Drop for HotkeyListener {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = PostThreadMessageW(self.thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::config::Config;
+use crate::mft_indexer::Indexer;
+use crate::query_server::{run_query, QueryError};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+// Requests are a single GET line plus headers; anything longer is refused
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+// Connections that haven't sent a whole request by then are dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves `GET /search?q=...&limit=...&case=1` on 127.0.0.1:`port` until `token` is cancelled.
+/// Responses are a JSON array of results, or `{"error": ...}` with a 4xx status. Only
+/// loopback is bound, so the index is never exposed to the network, and requests must
+/// name that address as their Host, so a web page can't reach it by DNS rebinding.
+pub async fn serve(port: u16, indexer: Arc<Indexer>, config: Arc<RwLock<Config>>, token: CancellationToken) {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(%addr, error = %e, "Failed to start HTTP API");
+            indexer.log_error(format!("Failed to start HTTP API on {}: {}", addr, e));
+            return;
+        }
+    };
+    info!(%addr, "HTTP API listening");
+
+    loop {
+        let stream = tokio::select! {
+            _ = token.cancelled() => return,
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "HTTP API connection failed");
+                    continue;
+                }
+            },
+        };
+        tokio::spawn(handle_client(stream, port, Arc::clone(&indexer), Arc::clone(&config)));
+    }
+}
+
+async fn handle_client(mut stream: TcpStream, port: u16, indexer: Arc<Indexer>, config: Arc<RwLock<Config>>) {
+    let Ok(Some(request)) = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await else {
+        return;
+    };
+    let (status, body) = match check_host(&request, port).and_then(|()| parse_search(&request)) {
+        Ok((query, limit, case_sensitive)) => {
+            // Scanning the index is CPU-bound, keep it off the async workers
+            match tokio::task::spawn_blocking(move || run_query(&indexer, &config, &query, limit, case_sensitive)).await {
+                Ok(results) => ("200 OK", serde_json::to_string(&results)),
+                Err(e) => ("500 Internal Server Error", serde_json::to_string(&QueryError { error: e.to_string() })),
+            }
+        }
+        Err((status, error)) => (status, serde_json::to_string(&QueryError { error })),
+    };
+    let Ok(body) = body else {
+        return;
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!(error = %e, "HTTP API client went away");
+    }
+}
+
+/// Reads up to the blank line that ends the request headers.
+async fn read_request(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 || buffer.len() + read > MAX_REQUEST_BYTES {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    Some(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// Rejects requests whose Host header isn't the loopback address they were sent to.
+/// A page on another site that rebinds its domain to 127.0.0.1 still sends its own
+/// domain as the Host.
+fn check_host(request: &str, port: u16) -> Result<(), (&'static str, String)> {
+    let host = request.lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim());
+    let allowed = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    match host {
+        Some(host) if allowed.iter().any(|allowed| host.eq_ignore_ascii_case(allowed)) => Ok(()),
+        Some(host) => Err(("403 Forbidden", format!("Unexpected host {}", host))),
+        None => Err(("400 Bad Request", "Missing Host header".to_string())),
+    }
+}
+
+/// The query, optional limit and case sensitivity from a `GET /search` request line, or
+/// the status and message to reject it with.
+fn parse_search(request: &str) -> Result<(String, Option<usize>, bool), (&'static str, String)> {
+    let mut parts = request.lines().next().unwrap_or_default().split(' ');
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    if method != "GET" {
+        return Err(("405 Method Not Allowed", format!("Unsupported method {}", method)));
+    }
+    let (path, params) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/search" {
+        return Err(("404 Not Found", format!("Unknown endpoint {}", path)));
+    }
+
+    let mut query = None;
+    let mut limit = None;
+    let mut case_sensitive = false;
+    for (key, value) in params.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "q" => query = Some(percent_decode(value)),
+            "limit" => match value.parse() {
+                Ok(value) => limit = Some(value),
+                Err(_) => return Err(("400 Bad Request", format!("Invalid limit {}", value))),
+            },
+            "case" => match value {
+                "1" | "true" => case_sensitive = true,
+                "0" | "false" => case_sensitive = false,
+                _ => return Err(("400 Bad Request", format!("Invalid case {}", value))),
+            },
+            _ => {}
+        }
+    }
+    match query {
+        Some(query) if !query.is_empty() => Ok((query, limit, case_sensitive)),
+        _ => Err(("400 Bad Request", "Missing q parameter".to_string())),
+    }
+}
+
+/// Decodes `%XX` escapes and `+` in a query string value.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() && bytes[i + 1..i + 3].iter().all(u8::is_ascii_hexdigit) => {
+                // Both digits are ASCII, so this slice is on char boundaries
+                decoded.push(u8::from_str_radix(&value[i + 1..i + 3], 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(host: Option<&str>) -> String {
+        let host = host.map(|host| format!("Host: {}\r\n", host)).unwrap_or_default();
+        format!("GET /search?q=x HTTP/1.1\r\n{}Accept: */*\r\n\r\n", host)
+    }
+
+    #[test]
+    fn loopback_hosts_are_accepted() {
+        assert!(check_host(&request(Some("127.0.0.1:7797")), 7797).is_ok());
+        assert!(check_host(&request(Some("localhost:7797")), 7797).is_ok());
+        assert!(check_host(&request(Some("LocalHost:7797")), 7797).is_ok());
+        assert!(check_host("GET /search?q=x HTTP/1.1\r\nhost:127.0.0.1:7797\r\n\r\n", 7797).is_ok());
+    }
+
+    #[test]
+    fn other_hosts_are_rejected() {
+        assert!(check_host(&request(Some("evil.example:7797")), 7797).is_err());
+        assert!(check_host(&request(Some("127.0.0.1:8080")), 7797).is_err());
+        assert!(check_host(&request(Some("127.0.0.1")), 7797).is_err());
+        assert!(check_host(&request(None), 7797).is_err());
+    }
+
+    #[test]
+    fn search_parameters() {
+        let (query, limit, case_sensitive) = parse_search("GET /search?q=a%20b+c&limit=5 HTTP/1.1\r\n").unwrap();
+        assert_eq!(query, "a b c");
+        assert_eq!(limit, Some(5));
+        assert!(!case_sensitive);
+        let (_, _, case_sensitive) = parse_search("GET /search?q=x&case=1 HTTP/1.1\r\n").unwrap();
+        assert!(case_sensitive);
+        assert!(parse_search("GET /search?q=x&case=maybe HTTP/1.1\r\n").is_err());
+        assert!(parse_search("POST /search?q=x HTTP/1.1\r\n").is_err());
+        assert!(parse_search("GET /other?q=x HTTP/1.1\r\n").is_err());
+        assert!(parse_search("GET /search?q= HTTP/1.1\r\n").is_err());
+    }
+}
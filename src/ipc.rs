@@ -0,0 +1,124 @@
+use crate::config::Config;
+use crate::mft_indexer::{FileRecord, Indexer, SearchQuery};
+use crate::query_server::{serve_pipe, QueryError};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::windows::named_pipe::NamedPipeServer;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// Serves whole records to apps using Rivet as a search backend, see PROTOCOL.md.
+pub const SEARCH_PIPE_NAME: &str = r"\\.\pipe\RivetSearch";
+// Requests are one small JSON object; a client that sends more without a NUL is refused
+// rather than buffered
+const MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+/// One NUL-terminated request, e.g. `{"query":"foo","limit":100,"regex":false}`.
+#[derive(Deserialize)]
+struct SearchRequest {
+    query: String,
+    // Defaults to the configured result limit, which also caps it
+    limit: Option<usize>,
+    // Match names against `query` as a regex instead of the search syntax
+    #[serde(default)]
+    regex: bool,
+    // Applies to the regex too
+    #[serde(default)]
+    case_sensitive: bool,
+}
+
+/// A `FileRecord` as serialized, plus the path its parent ID stands for.
+#[derive(Serialize)]
+struct RecordResult {
+    path: String,
+    #[serde(flatten)]
+    record: FileRecord,
+}
+
+/// Serves searches on `SEARCH_PIPE_NAME` until `token` is cancelled.
+pub async fn serve(indexer: Arc<Indexer>, config: Arc<RwLock<Config>>, token: CancellationToken) {
+    serve_pipe(SEARCH_PIPE_NAME, indexer, config, token, handle_client).await;
+}
+
+/// NUL-terminated JSON: each request gets one NUL-terminated response, a JSON array of
+/// records or `{"error": ...}`.
+async fn handle_client(client: NamedPipeServer, indexer: Arc<Indexer>, config: Arc<RwLock<Config>>) {
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut reader = BufReader::new(reader);
+    let mut request = Vec::new();
+
+    loop {
+        request.clear();
+        // A read error or EOF means the client went away
+        match (&mut reader).take(MAX_REQUEST_BYTES as u64 + 1).read_until(0, &mut request).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if request.last() == Some(&0) {
+            request.pop();
+        } else if request.len() > MAX_REQUEST_BYTES {
+            // The rest of the request is still unread, so the connection can't be used again
+            let error = QueryError { error: format!("Request longer than {} bytes", MAX_REQUEST_BYTES) };
+            if let Ok(mut response) = serde_json::to_string(&error) {
+                response.push('\0');
+                let _ = writer.write_all(response.as_bytes()).await;
+            }
+            break;
+        }
+        if request.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let response = match serde_json::from_slice::<SearchRequest>(&request) {
+            Ok(request) => {
+                let indexer = Arc::clone(&indexer);
+                let config = Arc::clone(&config);
+                // Scanning the index is CPU-bound, keep it off the async workers
+                match tokio::task::spawn_blocking(move || run_record_query(&indexer, &config, &request)).await {
+                    Ok(Ok(results)) => serde_json::to_string(&results),
+                    Ok(Err(error)) => serde_json::to_string(&QueryError { error }),
+                    Err(e) => serde_json::to_string(&QueryError { error: e.to_string() }),
+                }
+            }
+            Err(e) => serde_json::to_string(&QueryError { error: format!("Malformed request: {}", e) }),
+        };
+        let Ok(mut response) = response else {
+            continue;
+        };
+        response.push('\0');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    debug!("Search pipe client disconnected");
+}
+
+/// Like `query_server::run_query` but returns whole records, and reports a query that
+/// can't be parsed instead of matching nothing.
+fn run_record_query(indexer: &Indexer, config: &RwLock<Config>, request: &SearchRequest) -> Result<Vec<RecordResult>, String> {
+    let config = config.read().unwrap().clone();
+    let limit = request.limit.unwrap_or(config.result_limit).min(config.result_limit);
+    let search = if request.regex {
+        let regex = RegexBuilder::new(&request.query)
+            .case_insensitive(!request.case_sensitive)
+            .build()
+            .map_err(|e| format!("Invalid regex: {}", e))?;
+        let mut search = SearchQuery::from_config("", request.case_sensitive, &config);
+        search.name_regex = Some(regex);
+        search
+    } else {
+        SearchQuery::from_config(&request.query, request.case_sensitive, &config)
+    };
+    if let Some(error) = search.error() {
+        return Err(error.to_string());
+    }
+
+    let found = indexer.search(&search, limit);
+    Ok(found.ids.into_iter()
+        .filter_map(|id| {
+            let record = indexer.records.get(&id).map(|r| r.clone())?;
+            Some(RecordResult { path: indexer.get_full_path(id).path, record })
+        })
+        .collect())
+}
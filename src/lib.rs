@@ -0,0 +1,38 @@
+// The index, its query language and change handling build everywhere, so they can be
+// tested off Windows; reading volumes and everything user-facing needs Win32.
+#[cfg(windows)]
+pub mod autostart;
+#[cfg(windows)]
+pub mod cli;
+pub mod config;
+pub mod content_search;
+#[cfg(windows)]
+pub mod dir_walker;
+pub mod export;
+#[cfg(windows)]
+pub mod gui;
+#[cfg(windows)]
+pub mod hotkey;
+#[cfg(windows)]
+pub mod http_server;
+#[cfg(windows)]
+pub mod ipc;
+pub mod logging;
+pub mod mft_enumerator;
+pub mod mft_indexer;
+pub mod ntfs;
+#[cfg(windows)]
+pub mod preview;
+pub mod query;
+#[cfg(windows)]
+pub mod query_server;
+#[cfg(windows)]
+pub mod settings_window;
+#[cfg(windows)]
+pub mod single_instance;
+#[cfg(windows)]
+pub mod tray;
+pub mod usn_monitor;
+pub mod volume;
+#[cfg(windows)]
+pub mod volume_manager;
@@ -0,0 +1,67 @@
+use std::path::PathBuf;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+
+const LOG_FILE_PREFIX: &str = "rivet";
+const LOG_FILE_SUFFIX: &str = "log";
+// Daily files kept before the oldest is deleted
+const MAX_LOG_FILES: usize = 7;
+
+pub fn log_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Rivet").join("logs"))
+}
+
+/// Sends log output to stderr and to a daily-rotated file in `log_dir`. `RUST_LOG`
+/// overrides the default `info` level. The returned guard flushes the file writer when
+/// dropped, so it has to live until the process exits.
+pub fn init() -> Option<WorkerGuard> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let appender = log_dir().and_then(|dir| {
+        RollingFileAppender::builder()
+            .rotation(Rotation::DAILY)
+            .filename_prefix(LOG_FILE_PREFIX)
+            .filename_suffix(LOG_FILE_SUFFIX)
+            .max_log_files(MAX_LOG_FILES)
+            .build(dir)
+            .ok()
+    });
+    let (file_layer, guard) = match appender {
+        Some(appender) => {
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            (Some(fmt::layer().with_ansi(false).with_writer(writer)), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .with(file_layer)
+        .init();
+    guard
+}
+
+/// The most recent log file; rotated names sort by date.
+fn current_log_file() -> Option<PathBuf> {
+    std::fs::read_dir(log_dir()?)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+                name.starts_with(&format!("{}.", LOG_FILE_PREFIX)) && name.ends_with(&format!(".{}", LOG_FILE_SUFFIX))
+            })
+        })
+        .max()
+}
+
+/// The last `count` lines of the current log file, oldest first.
+pub fn tail(count: usize) -> Vec<String> {
+    let Some(text) = current_log_file().and_then(|path| std::fs::read(path).ok()) else {
+        return Vec::new();
+    };
+    let text = String::from_utf8_lossy(&text);
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(count)..].iter().map(|line| line.to_string()).collect()
+}
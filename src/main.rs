@@ -1,18 +1,52 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod mft_indexer;
-mod usn_monitor;
-mod gui;
-mod mft_enumerator;
+#[cfg(windows)]
+use {
+    std::sync::{Arc, Mutex, RwLock},
+    tokio_util::sync::CancellationToken,
+    clap::Parser,
+    rivet::cli::{self, Args},
+    rivet::config::Config,
+    rivet::http_server,
+    rivet::ipc,
+    rivet::logging,
+    rivet::mft_indexer::{estimate_record_count, Indexer},
+    rivet::query_server,
+    rivet::single_instance::SingleInstance,
+    rivet::volume_manager::{indexable_volumes, VolumeManager},
+    rivet::gui::RivetApp,
+};
 
-use std::sync::Arc;
-use tokio_util::sync::CancellationToken;
-use crate::mft_indexer::Indexer;
-use crate::usn_monitor::Monitor;
-use crate::gui::RivetApp;
+// Only the index itself builds elsewhere, for tests; there's no app to run
+#[cfg(not(windows))]
+fn main() {
+    eprintln!("Rivet reads volumes through Win32 and only runs on Windows");
+    std::process::exit(1);
+}
 
+#[cfg(windows)]
 #[tokio::main]
 async fn main() -> eframe::Result {
+    // `--search` runs headless and never opens the window
+    let args = Args::parse();
+    let log_guard = logging::init();
+    if args.search.is_some() {
+        let found = cli::run_search(&args);
+        // exit skips destructors, so flush the log file first
+        drop(log_guard);
+        std::process::exit(if found { 0 } else { 1 });
+    }
+
+    // A second launch just brings the running instance forward
+    let instance = match SingleInstance::acquire() {
+        Ok(Some(instance)) => Some(instance),
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            tracing::error!(error = %e, "Single-instance check failed");
+            None
+        }
+    };
+
     // Load icon
     let icon = image::open("rivetfavicon.ico")
         .map(|img| {
@@ -25,42 +59,56 @@ async fn main() -> eframe::Result {
             })
         })
         .ok();
+    let config = Arc::new(RwLock::new(Config::load()));
     let cancel_token = CancellationToken::new();
-    let indexer = Arc::new(Indexer::new());
-    
-    let monitor_indexer = Arc::clone(&indexer);
-    let monitor_token = cancel_token.clone();
+    let (progress_tx, progress_rx) = std::sync::mpsc::channel();
+    // Sized for the configured volumes up front, so the first index doesn't keep
+    // growing the record map
+    let estimate = {
+        let config = config.read().unwrap();
+        let volumes: Vec<_> = indexable_volumes().into_iter()
+            .filter(|volume| config.volumes.iter().any(|key| volume.matches(key)))
+            .collect();
+        estimate_record_count(&volumes)
+    };
+    let mut indexer = estimate.map_or_else(Indexer::new, Indexer::with_capacity);
+    indexer.set_progress_sender(progress_tx);
+    let indexer = Arc::new(indexer);
 
-    // Initial indexing in background
-    let bg_indexer = Arc::clone(&indexer);
-    let bg_token = cancel_token.clone();
-    std::thread::spawn(move || {
-        println!("Starting MFT index...");
-        if let Err(e) = bg_indexer.index_volume('C', &bg_token) {
-            eprintln!("Failed to index MFT: {}", e);
-        } else {
-            println!("MFT index complete. Fetching sizes...");
-            bg_indexer.fetch_sizes('C', &bg_token);
-            println!("Size fetch complete.");
-        }
-    });
+    // Index and monitor every configured volume in the background, following drives
+    // as they are plugged in and out
+    let mut volume_manager = VolumeManager::new(Arc::clone(&indexer), Arc::clone(&config), cancel_token.clone());
+    volume_manager.sync();
+    let volume_manager = Arc::new(Mutex::new(volume_manager));
+    VolumeManager::watch_devices(Arc::clone(&volume_manager), cancel_token.clone());
 
-    // Start USN monitoring in background
-    std::thread::spawn(move || {
-        let monitor = Monitor::new(monitor_indexer);
-        if let Err(e) = monitor.start_monitoring('C', &monitor_token) {
-            eprintln!("Failed to start USN monitor: {}", e);
-        }
-    });
+    // Lets launchers and scripts query the index over a named pipe, and other apps use
+    // it as a search backend over another
+    tokio::spawn(query_server::serve(Arc::clone(&indexer), Arc::clone(&config), cancel_token.clone()));
+    tokio::spawn(ipc::serve(Arc::clone(&indexer), Arc::clone(&config), cancel_token.clone()));
+
+    // ...and, when enabled, over HTTP on loopback for editor plugins and the like
+    let http_port = {
+        let config = config.read().unwrap();
+        args.http_port.or(config.http_api.then_some(config.http_port))
+    };
+    if let Some(port) = http_port {
+        tokio::spawn(http_server::serve(port, Arc::clone(&indexer), Arc::clone(&config), cancel_token.clone()));
+    }
 
     let mut native_options = eframe::NativeOptions::default();
-    native_options.viewport.icon = icon;
+    native_options.viewport.icon = icon.clone();
+    native_options.persist_window = true;
     let app_token = cancel_token.clone();
     eframe::run_native(
         "Rivet",
         native_options,
         Box::new(move |cc| {
-            Ok(Box::new(RivetApp::new(cc, indexer, app_token)))
+            let mut app = RivetApp::new(cc, indexer, config, volume_manager, progress_rx, icon, app_token);
+            if let Some(instance) = instance {
+                app.listen_for_activation(&cc.egui_ctx, instance);
+            }
+            Ok(Box::new(app))
         }),
     )
 }
@@ -1,34 +1,67 @@
-use std::ffi::OsString;
-use std::os::windows::ffi::OsStringExt;
-use windows::Win32::Foundation::{HANDLE, ERROR_HANDLE_EOF, GENERIC_READ};
-use windows::Win32::Storage::FileSystem::{
-    CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_SHARE_DELETE,
-    OPEN_EXISTING, FILE_ATTRIBUTE_DIRECTORY, FILE_FLAGS_AND_ATTRIBUTES,
+use crate::ntfs::{UsnRecordHeader, UsnRecordV2, UsnRecordV3, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT};
+#[cfg(windows)]
+use {
+    crate::volume::VolumeInfo,
+    tracing::debug,
+    windows::Win32::Foundation::{HANDLE, ERROR_HANDLE_EOF, GENERIC_READ},
+    windows::Win32::Storage::FileSystem::{
+        CreateFileW, FileIdInfo, GetFileInformationByHandleEx, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_SHARE_DELETE,
+        OPEN_EXISTING, FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_BACKUP_SEMANTICS, FILE_ID_INFO,
+    },
+    windows::Win32::System::IO::DeviceIoControl,
+    windows::Win32::System::Ioctl::{FSCTL_ENUM_USN_DATA, MFT_ENUM_DATA_V1},
+    windows::core::HSTRING,
 };
-use windows::Win32::System::IO::DeviceIoControl;
-use windows::Win32::System::Ioctl::{
-    FSCTL_ENUM_USN_DATA, MFT_ENUM_DATA_V0, USN_RECORD_V2,
-};
-use windows::core::HSTRING;
 
 pub struct MftEntry {
-    pub fid: u64,
-    pub parent_fid: u64,
+    // Full 128-bit file ID; NTFS IDs only use the low 64 bits
+    pub fid: u128,
+    pub parent_fid: u128,
     pub name: String,
+    // The exact name when it isn't valid UTF-16 and `name` is only a lossy copy
+    pub wide_name: Option<Vec<u16>>,
     pub modified: i64,
+    // FILETIMEs like `modified`; 0 when unknown, as the journal doesn't say
+    pub created: i64,
+    pub accessed: i64,
+    // 0 when unknown, as the MFT doesn't say
+    pub size: u64,
     pub is_dir: bool,
+    pub is_reparse_point: bool,
+    // Raw FILE_ATTRIBUTE_* flags
+    pub attributes: u32,
+}
+
+/// A source of file records for one volume. `MftEnumerator` reads them from the MFT;
+/// a plain `Vec` of entries stands in for a volume where there is none.
+pub trait VolumeEnumerator {
+    fn entries(&mut self) -> impl Iterator<Item = anyhow::Result<MftEntry>> + '_;
 }
 
+impl VolumeEnumerator for Vec<MftEntry> {
+    fn entries(&mut self) -> impl Iterator<Item = anyhow::Result<MftEntry>> + '_ {
+        self.drain(..).map(Ok)
+    }
+}
+
+#[cfg(windows)]
 pub struct MftEnumerator {
     handle: HANDLE,
+    buffer_size: usize,
 }
 
+#[cfg(windows)]
 impl MftEnumerator {
-    pub fn new(drive_letter: char) -> anyhow::Result<Self> {
-        let drive_path = format!("\\\\.\\{}:", drive_letter);
+    pub fn new(volume: &VolumeInfo, buffer_size: usize) -> anyhow::Result<Self> {
+        // The buffer must hold the leading next-FID plus at least one record
+        anyhow::ensure!(
+            buffer_size >= std::mem::size_of::<u64>() + std::mem::size_of::<UsnRecordV3>(),
+            "MFT read buffer of {} bytes is too small to hold a USN record",
+            buffer_size
+        );
         let handle = unsafe {
             CreateFileW(
-                &HSTRING::from(drive_path),
+                &HSTRING::from(volume.device_path()),
                 GENERIC_READ.0,
                 FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
                 None,
@@ -37,93 +70,260 @@ impl MftEnumerator {
                 None,
             )?
         };
-        Ok(Self { handle })
+        Ok(Self { handle, buffer_size })
     }
 
     pub fn iter(&self) -> MftIter {
         MftIter {
             handle: self.handle,
             next_start_fid: 0,
-            buffer: vec![0u8; 128 * 1024], // Increased buffer size
+            buffer: vec![0u8; self.buffer_size],
             offset: 0,
             bytes_read: 0,
+            reported_v3: false,
+        }
+    }
+}
+
+/// The name as a string, plus the exact UTF-16 when it has unpaired surrogates that
+/// the string had to replace. NTFS doesn't require names to be valid UTF-16. `bytes`
+/// is the little-endian name as it sits in the record.
+fn utf16_name(bytes: &[u8]) -> (String, Option<Vec<u16>>) {
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|unit| u16::from_le_bytes([unit[0], unit[1]])).collect();
+    match String::from_utf16(&units) {
+        Ok(name) => (name, None),
+        Err(_) => (String::from_utf16_lossy(&units), Some(units)),
+    }
+}
+
+/// The fields of a USN record that the index uses, from either record version.
+pub struct UsnRecord {
+    pub fid: u128,
+    pub parent_fid: u128,
+    // USN_REASON_* flags; always 0 from an MFT enumeration
+    pub reason: u32,
+    pub name: String,
+    // See `MftEntry::wide_name`
+    pub wide_name: Option<Vec<u16>>,
+    // FILETIME, 100ns intervals since 1601
+    pub timestamp: i64,
+    pub attributes: u32,
+}
+
+impl UsnRecord {
+    pub fn into_entry(self) -> MftEntry {
+        MftEntry {
+            fid: self.fid,
+            parent_fid: self.parent_fid,
+            name: self.name,
+            wide_name: self.wide_name,
+            modified: self.timestamp,
+            created: 0,
+            accessed: 0,
+            size: 0,
+            is_dir: (self.attributes & FILE_ATTRIBUTE_DIRECTORY) != 0,
+            is_reparse_point: (self.attributes & FILE_ATTRIBUTE_REPARSE_POINT) != 0,
+            attributes: self.attributes,
+        }
+    }
+}
+
+/// Parses the record at the start of `bytes` and returns it with its length. Volumes
+/// whose file IDs don't fit in 64 bits (ReFS) return V3 records with 128-bit IDs; the
+/// rest return V2. Lengths and the name's offset come from the volume, so they're
+/// checked against the buffer before anything is read through them.
+pub fn parse_usn_record(bytes: &[u8]) -> anyhow::Result<(UsnRecord, usize)> {
+    if bytes.len() < std::mem::size_of::<UsnRecordHeader>() {
+        anyhow::bail!("Truncated USN record header");
+    }
+    // Nothing guarantees the buffer is aligned for the record structs, so they're
+    // copied out rather than referenced in place
+    let header = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const UsnRecordHeader) };
+    let length = header.record_length as usize;
+    if length > bytes.len() {
+        anyhow::bail!("Invalid USN record length {}", length);
+    }
+
+    let (record, name_offset, name_length) = match header.major_version {
+        2 => {
+            if length < std::mem::size_of::<UsnRecordV2>() {
+                anyhow::bail!("Invalid USN record length {}", length);
+            }
+            let record = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const UsnRecordV2) };
+            let parsed = UsnRecord {
+                fid: record.file_reference_number as u128,
+                parent_fid: record.parent_file_reference_number as u128,
+                reason: record.reason,
+                name: String::new(),
+                wide_name: None,
+                timestamp: record.time_stamp,
+                attributes: record.file_attributes,
+            };
+            (parsed, record.file_name_offset, record.file_name_length)
+        }
+        3 => {
+            if length < std::mem::size_of::<UsnRecordV3>() {
+                anyhow::bail!("Invalid USN record length {}", length);
+            }
+            let record = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const UsnRecordV3) };
+            let parsed = UsnRecord {
+                fid: u128::from_le_bytes(record.file_reference_number),
+                parent_fid: u128::from_le_bytes(record.parent_file_reference_number),
+                reason: record.reason,
+                name: String::new(),
+                wide_name: None,
+                timestamp: record.time_stamp,
+                attributes: record.file_attributes,
+            };
+            (parsed, record.file_name_offset, record.file_name_length)
         }
+        version => anyhow::bail!("Unsupported USN record version {}.{}", version, header.minor_version),
+    };
+
+    let name_start = name_offset as usize;
+    let name_end = name_start + name_length as usize;
+    if name_end > length || name_length % 2 != 0 {
+        anyhow::bail!("USN record name at {}..{} doesn't fit its {} bytes", name_start, name_end, length);
     }
+    let (name, wide_name) = utf16_name(&bytes[name_start..name_end]);
+    Ok((UsnRecord { name, wide_name, ..record }, length))
 }
 
+/// The file ID of the volume's root folder, which on ReFS isn't a fixed record number.
+/// `root` is where the volume is mounted, see `VolumeInfo::root`.
+#[cfg(windows)]
+pub fn root_file_id(root: &str) -> Option<u128> {
+    let handle = unsafe {
+        CreateFileW(
+            &HSTRING::from(root),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            // Needed to open a directory
+            FILE_FLAG_BACKUP_SEMANTICS,
+            None,
+        )
+        .ok()?
+    };
+    let mut info = FILE_ID_INFO::default();
+    let result = unsafe {
+        GetFileInformationByHandleEx(
+            handle,
+            FileIdInfo,
+            &mut info as *mut _ as *mut std::ffi::c_void,
+            std::mem::size_of::<FILE_ID_INFO>() as u32,
+        )
+    };
+    unsafe { let _ = windows::Win32::Foundation::CloseHandle(handle); }
+    result.ok()?;
+    Some(u128::from_le_bytes(info.FileId.Identifier))
+}
+
+#[cfg(windows)]
+impl VolumeEnumerator for MftEnumerator {
+    fn entries(&mut self) -> impl Iterator<Item = anyhow::Result<MftEntry>> + '_ {
+        self.iter()
+    }
+}
+
+#[cfg(windows)]
 impl Drop for MftEnumerator {
     fn drop(&mut self) {
         unsafe { let _ = windows::Win32::Foundation::CloseHandle(self.handle); }
     }
 }
 
+#[cfg(windows)]
 pub struct MftIter {
     handle: HANDLE,
     next_start_fid: u64,
     buffer: Vec<u8>,
     offset: usize,
     bytes_read: u32,
+    // Whether we've already logged that this volume has 128-bit file IDs
+    reported_v3: bool,
+}
+
+#[cfg(windows)]
+impl MftIter {
+    /// Fills `buffer` with the records from `next_start_fid` on and returns how many
+    /// bytes it got. V1 of the request lets the volume hand back V3 records where V2
+    /// can't represent its file IDs.
+    fn read_records(&mut self) -> windows::core::Result<u32> {
+        let mft_enum_data = MFT_ENUM_DATA_V1 {
+            StartFileReferenceNumber: self.next_start_fid,
+            LowUsn: 0,
+            HighUsn: i64::MAX,
+            MinMajorVersion: 2,
+            MaxMajorVersion: 3,
+        };
+        let mut bytes_returned = 0u32;
+        unsafe {
+            DeviceIoControl(
+                self.handle,
+                FSCTL_ENUM_USN_DATA,
+                Some(&mft_enum_data as *const _ as _),
+                std::mem::size_of::<MFT_ENUM_DATA_V1>() as u32,
+                Some(self.buffer.as_mut_ptr() as _),
+                self.buffer.len() as u32,
+                Some(&mut bytes_returned),
+                None,
+            )?;
+        }
+        Ok(bytes_returned)
+    }
 }
 
+#[cfg(windows)]
 impl Iterator for MftIter {
     type Item = anyhow::Result<MftEntry>;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             if self.offset < self.bytes_read as usize {
-                let record = unsafe {
-                    &*(self.buffer.as_ptr().add(self.offset) as *const USN_RECORD_V2)
-                };
-                self.offset += record.RecordLength as usize;
-
-                let name_len = record.FileNameLength as usize / 2;
-                let name_ptr = record.FileName.as_ptr();
-                let name_slice = unsafe { std::slice::from_raw_parts(name_ptr, name_len) };
-                let name = OsString::from_wide(name_slice).to_string_lossy().into_owned();
-
-                return Some(Ok(MftEntry {
-                    fid: record.FileReferenceNumber,
-                    parent_fid: record.ParentFileReferenceNumber,
-                    name,
-                    modified: record.TimeStamp,
-                    is_dir: (record.FileAttributes & FILE_ATTRIBUTE_DIRECTORY.0) != 0,
-                }));
+                let parsed = parse_usn_record(&self.buffer[self.offset..self.bytes_read as usize]);
+                return Some(match parsed {
+                    Ok((record, length)) => {
+                        self.offset += length;
+                        if record.fid >> 64 != 0 && !self.reported_v3 {
+                            self.reported_v3 = true;
+                            debug!("Volume returns 128-bit file IDs");
+                        }
+                        Ok(record.into_entry())
+                    }
+                    Err(e) => {
+                        // Without a usable length the rest of the buffer can't be walked
+                        self.offset = self.bytes_read as usize;
+                        Err(e)
+                    }
+                });
             }
 
-            // Need to read more data
-            let mft_enum_data = MFT_ENUM_DATA_V0 {
-                StartFileReferenceNumber: self.next_start_fid,
-                LowUsn: 0,
-                HighUsn: i64::MAX,
-            };
-
-            let mut bytes_returned = 0u32;
-            let success = unsafe {
-                DeviceIoControl(
-                    self.handle,
-                    FSCTL_ENUM_USN_DATA,
-                    Some(&mft_enum_data as *const _ as _),
-                    std::mem::size_of::<MFT_ENUM_DATA_V0>() as u32,
-                    Some(self.buffer.as_mut_ptr() as _),
-                    self.buffer.len() as u32,
-                    Some(&mut bytes_returned),
-                    None,
-                )
-            };
-
-            if let Err(e) = success {
-                if e.code() == ERROR_HANDLE_EOF.into() {
-                    return None;
-                }
-                return Some(Err(anyhow::anyhow!("DeviceIoControl failed at FID 0x{:x}: {}", self.next_start_fid, e)));
+            // Need to read more data. A failed read is tried once more before it's
+            // reported, so each bad position costs the caller a single error.
+            let mut read = self.read_records();
+            if read.as_ref().is_err_and(|e| e.code() != ERROR_HANDLE_EOF.into()) {
+                read = self.read_records();
             }
+            let bytes_returned = match read {
+                Ok(bytes_returned) => bytes_returned,
+                Err(e) if e.code() == ERROR_HANDLE_EOF.into() => return None,
+                Err(e) => {
+                    let failed_fid = self.next_start_fid;
+                    // Step past it so a single bad record can't stall or truncate the
+                    // enumeration
+                    self.next_start_fid += 1;
+                    return Some(Err(anyhow::anyhow!("DeviceIoControl failed at FID 0x{:x}: {} (0x{:08X})", failed_fid, e, e.code().0)));
+                }
+            };
 
             if bytes_returned < 8 {
                 return None; // Should at least have the next start FID
             }
 
             self.bytes_read = bytes_returned;
-            self.next_start_fid = unsafe { *(self.buffer.as_ptr() as *const u64) };
+            self.next_start_fid = u64::from_le_bytes(self.buffer[..8].try_into().unwrap());
             self.offset = 8; // Skip the next start FID
             
             // If we only got the next FID but no records, loop again
@@ -133,3 +333,105 @@ impl Iterator for MftIter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Offsets of the V2 fields the tests fill in
+    const V2_FRN: usize = 8;
+    const V2_PARENT_FRN: usize = 16;
+    const V2_NAME_LENGTH: usize = 56;
+    const V2_NAME_OFFSET: usize = 58;
+    const V2_NAME: usize = 60;
+
+    /// A V2 record named `name`, placed `gap` bytes past where the name usually starts,
+    /// padded to 8 bytes like the records the volume returns.
+    fn v2_record(name: &[u16], gap: usize) -> Vec<u8> {
+        let name_offset = V2_NAME + gap;
+        let length = (name_offset + name.len() * 2).next_multiple_of(8).max(std::mem::size_of::<UsnRecordV2>());
+        let mut bytes = vec![0u8; length];
+        bytes[0..4].copy_from_slice(&(length as u32).to_le_bytes());
+        bytes[4..6].copy_from_slice(&2u16.to_le_bytes());
+        bytes[V2_FRN..V2_FRN + 8].copy_from_slice(&0x0003_0000_0000_0064u64.to_le_bytes());
+        bytes[V2_PARENT_FRN..V2_PARENT_FRN + 8].copy_from_slice(&5u64.to_le_bytes());
+        bytes[V2_NAME_LENGTH..V2_NAME_LENGTH + 2].copy_from_slice(&(name.len() as u16 * 2).to_le_bytes());
+        bytes[V2_NAME_OFFSET..V2_NAME_OFFSET + 2].copy_from_slice(&(name_offset as u16).to_le_bytes());
+        for (i, unit) in name.iter().enumerate() {
+            bytes[name_offset + i * 2..name_offset + i * 2 + 2].copy_from_slice(&unit.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn wide(name: &str) -> Vec<u16> {
+        name.encode_utf16().collect()
+    }
+
+    #[test]
+    fn v2_records_parse() {
+        let bytes = v2_record(&wide("report.txt"), 0);
+        let (record, length) = parse_usn_record(&bytes).unwrap();
+        assert_eq!(length, bytes.len());
+        assert_eq!(record.fid, 0x0003_0000_0000_0064);
+        assert_eq!(record.parent_fid, 5);
+        assert_eq!(record.name, "report.txt");
+        assert!(record.wide_name.is_none());
+    }
+
+    #[test]
+    fn the_name_is_read_from_its_offset() {
+        let bytes = v2_record(&wide("moved.txt"), 12);
+        assert_eq!(parse_usn_record(&bytes).unwrap().0.name, "moved.txt");
+    }
+
+    #[test]
+    fn unaligned_records_parse() {
+        let mut bytes = vec![0u8; 1];
+        bytes.extend(v2_record(&wide("a.txt"), 0));
+        assert_eq!(parse_usn_record(&bytes[1..]).unwrap().0.name, "a.txt");
+    }
+
+    #[test]
+    fn lengths_past_the_buffer_are_rejected() {
+        let bytes = v2_record(&wide("report.txt"), 0);
+        assert!(parse_usn_record(&bytes[..bytes.len() - 8]).is_err());
+        assert!(parse_usn_record(&bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn names_past_the_record_are_rejected() {
+        let mut bytes = v2_record(&wide("report.txt"), 0);
+        let too_long = (bytes.len() as u16).to_le_bytes();
+        bytes[V2_NAME_LENGTH..V2_NAME_LENGTH + 2].copy_from_slice(&too_long);
+        assert!(parse_usn_record(&bytes).is_err());
+
+        let mut bytes = v2_record(&wide("report.txt"), 0);
+        bytes[V2_NAME_OFFSET..V2_NAME_OFFSET + 2].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert!(parse_usn_record(&bytes).is_err());
+    }
+
+    #[test]
+    fn short_records_are_rejected() {
+        let mut bytes = v2_record(&wide("a"), 0);
+        bytes[0..4].copy_from_slice(&8u32.to_le_bytes());
+        assert!(parse_usn_record(&bytes).is_err());
+        bytes[0..4].copy_from_slice(&0u32.to_le_bytes());
+        assert!(parse_usn_record(&bytes).is_err());
+    }
+
+    #[test]
+    fn unpaired_surrogates_keep_the_exact_name() {
+        let name = [0x61, 0xD800, 0x2E, 0x74, 0x78, 0x74];
+        let (record, _) = parse_usn_record(&v2_record(&name, 0)).unwrap();
+        assert_eq!(record.name, "a\u{FFFD}.txt");
+        assert_eq!(record.wide_name.as_deref(), Some(&name[..]));
+
+        // A low surrogate on its own, and a high one at the very end
+        for name in [vec![0xDC00u16, 0x62], vec![0x62, 0xDBFF]] {
+            let bytes: Vec<u8> = name.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+            assert_eq!(utf16_name(&bytes).1, Some(name));
+        }
+        // A proper pair is just a character
+        assert_eq!(utf16_name(&[0x3D, 0xD8, 0x00, 0xDE]), ("😀".to_string(), None));
+    }
+}
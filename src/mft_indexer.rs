@@ -1,43 +1,1324 @@
-use crate::mft_enumerator::MftEnumerator;
-use dashmap::DashMap;
+use crate::config::{is_excluded, Config};
+use crate::mft_enumerator::VolumeEnumerator;
+use crate::ntfs::{FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM};
+use crate::query::{self, Expr};
+use crate::volume::VolumeId;
+use dashmap::{DashMap, DashSet};
+use dashmap::mapref::entry::Entry;
+use nucleo_matcher::pattern::{AtomKind, CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Matcher, Utf32Str};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_with::hex::Hex;
+use serde_with::serde_as;
+use sha2::{Digest, Sha256};
+use chrono::{DateTime, Utc};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
-use windows::Win32::Foundation::{HANDLE, CloseHandle, GENERIC_READ};
-use windows::Win32::Storage::FileSystem::{
-    CreateFileW, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
-    OPEN_EXISTING,
+use tracing::{debug, error, info, warn};
+#[cfg(windows)]
+use {
+    crate::dir_walker::DirWalker,
+    crate::mft_enumerator::{root_file_id, MftEnumerator},
+    crate::volume::VolumeInfo,
+    crate::volume_manager::has_usn_journal,
+    std::os::windows::ffi::{OsStrExt, OsStringExt},
+    windows::Win32::Foundation::{HANDLE, CloseHandle, ERROR_JOURNAL_NOT_ACTIVE, FILETIME, GENERIC_READ, GENERIC_WRITE},
+    windows::Win32::Storage::FileSystem::{
+        CreateFileW, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    },
+    windows::Win32::System::Ioctl::{
+        CREATE_USN_JOURNAL_DATA, FSCTL_CREATE_USN_JOURNAL, FSCTL_GET_NTFS_VOLUME_DATA, FSCTL_QUERY_USN_JOURNAL,
+        NTFS_VOLUME_DATA_BUFFER, USN_JOURNAL_DATA_V0,
+    },
+    windows::Win32::System::IO::DeviceIoControl,
+    windows::core::HSTRING,
 };
-use windows::Win32::System::Ioctl::{FSCTL_QUERY_USN_JOURNAL, USN_JOURNAL_DATA_V0};
-use windows::Win32::System::IO::DeviceIoControl;
-use windows::core::HSTRING;
 
-#[derive(Debug, Clone)]
+/// Whether `fid` needs more than 64 bits, as ReFS file IDs do. NTFS IDs are 64-bit file
+/// reference numbers with the high half zero.
+fn is_wide_fid(fid: u128) -> bool {
+    fid >> 64 != 0
+}
+
+/// The MFT record index: the low 48 bits of an NTFS file reference number. ReFS IDs
+/// have no sequence number to drop and are kept whole.
+pub fn fid_to_mft_index(fid: u128) -> u128 {
+    if is_wide_fid(fid) {
+        fid & KEY_ID_MASK
+    } else {
+        fid & 0x0000_FFFF_FFFF_FFFF
+    }
+}
+
+/// The top 16 bits of an NTFS file reference number, bumped each time the MFT slot is
+/// reused. ReFS IDs aren't reused this way, so theirs is always 0.
+pub fn fid_sequence(fid: u128) -> u16 {
+    if is_wide_fid(fid) { 0 } else { (fid >> 48) as u16 }
+}
+
+/// Whether sequence number `a` predates `b`, allowing for wraparound.
+fn sequence_is_older(a: u16, b: u16) -> bool {
+    (a.wrapping_sub(b) as i16) < 0
+}
+
+// Keys keep the volume ID in the top byte and the file ID in the rest
+const KEY_VOLUME_SHIFT: u32 = 120;
+const KEY_ID_MASK: u128 = (1 << KEY_VOLUME_SHIFT) - 1;
+
+/// Records from every volume share one map, so keys combine the volume ID with the
+/// MFT record index. Keying by index rather than the full FRN means a reused slot maps
+/// to one entry; `FileRecord::sequence` tells which incarnation it holds.
+pub fn record_key(volume: VolumeId, fid: u128) -> u128 {
+    if fid >> KEY_VOLUME_SHIFT != 0 {
+        report_oversized_fid(volume, fid);
+    }
+    ((volume.0 as u128) << KEY_VOLUME_SHIFT) | fid_to_mft_index(fid)
+}
+
+/// Warns, once, about a 128-bit file ID whose top byte `record_key` has to drop to make
+/// room for the volume. Files whose IDs only differ in that byte end up sharing a record.
+#[cold]
+fn report_oversized_fid(volume: VolumeId, fid: u128) {
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+    if !REPORTED.swap(true, Ordering::Relaxed) {
+        warn!(%volume, fid = format!("{:#x}", fid), "File ID too wide for the index; its top byte is ignored");
+    }
+}
+
+pub fn key_volume(key: u128) -> VolumeId {
+    VolumeId((key >> KEY_VOLUME_SHIFT) as u8)
+}
+
+// Counted in UTF-16 units and including the terminating NUL
+const MAX_PATH: usize = 260;
+const LONG_PATH_PREFIX: &str = "\\\\?\\";
+const LONG_UNC_PREFIX: &str = "\\\\?\\UNC\\";
+// A path is at most 32,767 UTF-16 units and every level adds a separator and a name,
+// so no real parent chain is deeper than this
+const MAX_PATH_DEPTH: usize = 16_384;
+// MFT record number of every NTFS volume's root directory
+const ROOT_RECORD: u128 = 5;
+// Stands in for the folders above a break in a parent chain
+const UNRESOLVED_PREFIX: &str = "…\\";
+
+/// Adds the `\\?\` prefix to paths that don't fit in MAX_PATH so Win32 file APIs accept
+/// them. UNC paths (`\\server\share`) take the `\\?\UNC\` form.
+pub fn normalize_path(raw: &str) -> String {
+    if raw.encode_utf16().count() < MAX_PATH || raw.starts_with(LONG_PATH_PREFIX) {
+        raw.to_string()
+    } else if let Some(unc) = raw.strip_prefix("\\\\") {
+        format!("{}{}", LONG_UNC_PREFIX, unc)
+    } else {
+        format!("{}{}", LONG_PATH_PREFIX, raw)
+    }
+}
+
+/// The UTF-16 units of a path.
+#[cfg(windows)]
+fn os_to_wide(raw: &OsStr) -> Vec<u16> {
+    raw.encode_wide().collect()
+}
+
+/// The path with UTF-16 units `wide`, exactly.
+#[cfg(windows)]
+fn os_from_wide(wide: &[u16]) -> OsString {
+    OsString::from_wide(wide)
+}
+
+// Only Windows paths can hold unpaired surrogates; elsewhere they're taken lossily
+#[cfg(not(windows))]
+fn os_to_wide(raw: &OsStr) -> Vec<u16> {
+    raw.to_string_lossy().encode_utf16().collect()
+}
+
+#[cfg(not(windows))]
+fn os_from_wide(wide: &[u16]) -> OsString {
+    String::from_utf16_lossy(wide).into()
+}
+
+/// `normalize_path` for a path that may not be valid Unicode.
+fn normalize_os_path(raw: &OsStr) -> OsString {
+    if let Some(raw) = raw.to_str() {
+        return normalize_path(raw).into();
+    }
+    let wide = os_to_wide(raw);
+    let long_prefix: Vec<u16> = LONG_PATH_PREFIX.encode_utf16().collect();
+    if wide.len() < MAX_PATH || wide.starts_with(&long_prefix) {
+        return raw.to_os_string();
+    }
+    let backslash = b'\\' as u16;
+    let (prefix, rest) = match wide.strip_prefix(&[backslash, backslash][..]) {
+        Some(unc) => (LONG_UNC_PREFIX, unc),
+        None => (LONG_PATH_PREFIX, &wide[..]),
+    };
+    let mut path = OsString::from(prefix);
+    path.push(os_from_wide(rest));
+    path
+}
+
+/// The attributes shown in the attributes column and offered as filters, with their
+/// letter in `attribute_letters`.
+pub const ATTRIBUTE_FLAGS: [(u32, char, &str); 4] = [
+    (FILE_ATTRIBUTE_READONLY, 'R', "Read-only"),
+    (FILE_ATTRIBUTE_HIDDEN, 'H', "Hidden"),
+    (FILE_ATTRIBUTE_SYSTEM, 'S', "System"),
+    (FILE_ATTRIBUTE_ARCHIVE, 'A', "Archive"),
+];
+
+/// Compact form of an attribute mask, e.g. "RA" for a read-only file awaiting backup.
+pub fn attribute_letters(attributes: u32) -> String {
+    ATTRIBUTE_FLAGS.iter()
+        .filter(|(flag, _, _)| attributes & flag != 0)
+        .map(|(_, letter, _)| *letter)
+        .collect()
+}
+
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FileRecord {
-    pub id: u64,
-    pub parent_id: u64,
+    // Volume-qualified keys, see `record_key`
+    pub id: u128,
+    pub parent_id: u128,
+    // Sequence number of the FRN this record came from, see `fid_sequence`
+    pub sequence: u16,
     pub name: String,
+    // The exact name when it isn't valid UTF-16; `name` is then a lossy copy that's
+    // fine for searching and showing, but doesn't open the file
+    pub wide_name: Option<Vec<u16>>,
     pub size: u64,
     pub modified: i64,
+    // Fetched along with the size; 0 until then
+    pub created: i64,
+    pub accessed: i64,
     pub is_dir: bool,
+    // Symlinks, junctions and cloud placeholders
+    pub is_reparse_point: bool,
+    // Raw FILE_ATTRIBUTE_* flags
+    pub attributes: u32,
+    // SHA-256 of the contents, once `Indexer::compute_hashes` has read the file.
+    // Serialized as a hex string.
+    #[serde_as(as = "Option<Hex>")]
+    pub hash: Option<[u8; 32]>,
+    // The file's other hard links. Results list the file once, under `name` in
+    // `parent_id`; these names match searches too and their paths show on hover.
+    pub links: Vec<HardLink>,
+}
+
+/// A further name for a file, possibly in another folder.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HardLink {
+    pub parent_id: u128,
+    pub name: String,
+}
+
+impl FileRecord {
+    /// Hidden or system files, plus the `$` NTFS metafiles ($MFT, $Secure, ...), which
+    /// are left out of results unless the user asks for them.
+    pub fn is_hidden_or_system(&self) -> bool {
+        self.attributes & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0 || self.name.starts_with('$')
+    }
+
+    pub fn volume(&self) -> VolumeId {
+        key_volume(self.id)
+    }
+
+    /// `name` followed by the names of the file's other hard links.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.links.iter().map(|link| link.name.as_str()))
+    }
+}
+
+/// What to look for, shared by the search box, the CLI and the query pipe. The text
+/// uses the syntax described in the `query` module.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    text: String,
+    // Parsed `text`, or why it couldn't be parsed
+    expr: Result<Expr, String>,
+    case_sensitive: bool,
+    pub include_hidden: bool,
+    // Folders or name patterns to leave out, see `Config::is_excluded`
+    pub excluded: Vec<String>,
+    // Only records on these volumes; empty means all
+    pub volumes: Vec<VolumeId>,
+    // Only files with one of these extensions, without the dot; empty means any
+    pub extensions: Vec<String>,
+    // Only records with all of these FILE_ATTRIBUTE_* flags; 0 means any
+    pub attributes: u32,
+    // Only records with a name matching this, for API clients that ask for a regex
+    pub name_regex: Option<Regex>,
+    // Only records somewhere beneath this folder, by id; see `Indexer::find_folder`
+    pub within: Option<u128>,
+    // `text` as a fuzzy pattern, matched against names instead of parsing it; see `fuzzy`
+    fuzzy: Option<Pattern>,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self::new("", false)
+    }
+}
+
+impl SearchQuery {
+    pub fn new(text: &str, case_sensitive: bool) -> Self {
+        Self {
+            text: text.to_string(),
+            expr: query::parse(text, case_sensitive),
+            case_sensitive,
+            include_hidden: false,
+            excluded: Vec::new(),
+            volumes: Vec::new(),
+            extensions: Vec::new(),
+            attributes: 0,
+            name_regex: None,
+            within: None,
+            fuzzy: None,
+        }
+    }
+
+    /// Matches names containing the text's characters in order instead of parsing it,
+    /// so "mftidx" finds mft_indexer.rs. Filters other than the text still apply.
+    /// Uppercase in the text makes it case-sensitive.
+    pub fn fuzzy(self) -> Self {
+        let pattern = Pattern::new(&self.text, CaseMatching::Smart, Normalization::Smart, AtomKind::Fuzzy);
+        Self { expr: Ok(Expr::And(Vec::new())), fuzzy: Some(pattern), ..self }
+    }
+
+    /// How closely `name` matches a fuzzy query, higher is better. None if it doesn't
+    /// match or the query isn't fuzzy.
+    pub fn fuzzy_score(&self, name: &str) -> Option<u32> {
+        let pattern = self.fuzzy.as_ref()?;
+        FUZZY_MATCHER.with_borrow_mut(|(matcher, chars)| pattern.score(Utf32Str::new(name, chars), matcher))
+    }
+
+    /// A query that applies the configured exclusions and hidden-file setting.
+    pub fn from_config(text: &str, case_sensitive: bool, config: &Config) -> Self {
+        Self {
+            include_hidden: config.show_hidden,
+            excluded: config.excluded.clone(),
+            ..Self::new(text, case_sensitive)
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Why the text isn't a valid query, if it isn't. Nothing matches an invalid query.
+    pub fn error(&self) -> Option<&str> {
+        self.expr.as_ref().err().map(String::as_str)
+    }
+
+    /// Whether the text is only words that must all appear in the name, so that a
+    /// longer query starting with it can only narrow its results. Fuzzy queries
+    /// narrow the same way.
+    pub fn is_plain(&self) -> bool {
+        self.name_regex.is_none() && self.expr.as_ref().is_ok_and(Expr::is_plain)
+    }
+
+    /// Whether `record` matches, apart from `excluded` and any `path:` filters, which
+    /// need the full path.
+    pub fn matches(&self, record: &FileRecord) -> bool {
+        self.eval(record, None) != Some(false)
+    }
+
+    /// Like `matches`, but None when the answer depends on a `path:` filter and `path`
+    /// wasn't given.
+    fn eval(&self, record: &FileRecord, path: Option<&str>) -> Option<bool> {
+        let Ok(expr) = &self.expr else {
+            return Some(false);
+        };
+        // Asking for hidden or system files by attribute implies showing them
+        let wants_hidden = self.attributes & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0;
+        if !self.include_hidden && !wants_hidden && record.is_hidden_or_system() {
+            return Some(false);
+        }
+        if record.attributes & self.attributes != self.attributes {
+            return Some(false);
+        }
+        if !self.volumes.is_empty() && !self.volumes.contains(&record.volume()) {
+            return Some(false);
+        }
+        if let Some(regex) = &self.name_regex
+            && !record.names().any(|name| regex.is_match(name))
+        {
+            return Some(false);
+        }
+        if !self.extensions.is_empty() {
+            let has_extension = record.names().any(|name| {
+                name.rsplit_once('.')
+                    .is_some_and(|(_, ext)| self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            });
+            if !has_extension {
+                return Some(false);
+            }
+        }
+        if self.fuzzy.is_some() && !record.names().any(|name| self.fuzzy_score(name).is_some()) {
+            return Some(false);
+        }
+        expr.eval(record, path, self.case_sensitive)
+    }
+
+    /// Lowercased text every match's name must contain, used to narrow the scan with
+    /// the trigram index and to rank results. Empty if there's no such text.
+    pub fn required_text(&self) -> String {
+        self.expr.as_ref().ok().and_then(Expr::required_text).unwrap_or_default()
+    }
+
+    /// Byte ranges of `name` that the query matched, in order and not overlapping, for
+    /// highlighting. Wildcard terms match the whole name and aren't included.
+    pub fn match_spans(&self, name: &str) -> Vec<std::ops::Range<usize>> {
+        let mut spans: Vec<std::ops::Range<usize>> = Vec::new();
+        if let Some(pattern) = &self.fuzzy {
+            // The matcher reports character positions, not byte offsets
+            let mut positions = Vec::new();
+            FUZZY_MATCHER.with_borrow_mut(|(matcher, chars)| {
+                pattern.indices(Utf32Str::new(name, chars), matcher, &mut positions)
+            });
+            spans.extend(name.char_indices().enumerate()
+                .filter(|(i, _)| positions.contains(&(*i as u32)))
+                .map(|(_, (start, c))| start..start + c.len_utf8()));
+        }
+        if let Some(regex) = &self.name_regex {
+            spans.extend(regex.find_iter(name).map(|m| m.range()).filter(|range| !range.is_empty()));
+        }
+        if let Ok(expr) = &self.expr {
+            // Offsets into the lowercased name only carry over if lowercasing kept its length
+            let lower = if self.case_sensitive { name.to_string() } else { name.to_lowercase() };
+            if lower.len() == name.len() {
+                for text in expr.name_texts().into_iter().filter(|text| !text.is_empty()) {
+                    spans.extend(lower.match_indices(text).map(|(start, text)| start..start + text.len()));
+                }
+            }
+        }
+        spans.retain(|span| name.is_char_boundary(span.start) && name.is_char_boundary(span.end));
+        spans.sort_by_key(|span| span.start);
+        let mut merged: Vec<std::ops::Range<usize>> = Vec::new();
+        for span in spans {
+            match merged.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ => merged.push(span),
+            }
+        }
+        merged
+    }
+
+    fn needs_path(&self) -> bool {
+        !self.excluded.is_empty() || self.expr.as_ref().is_ok_and(Expr::needs_path)
+    }
+}
+
+thread_local! {
+    // Fuzzy matching needs a mutable `Matcher`, whose scratch space is costly to allocate,
+    // and a buffer for the name's characters; kept per thread and reused across queries
+    static FUZZY_MATCHER: RefCell<(Matcher, Vec<char>)> = RefCell::new((Matcher::default(), Vec::new()));
+}
+
+/// Puts the calling thread in background mode, which lowers its CPU and I/O priority
+/// until it exits.
+pub fn begin_background_priority() {
+    #[cfg(windows)]
+    {
+        use windows::Win32::System::Threading::{GetCurrentThread, SetThreadPriority, THREAD_MODE_BACKGROUND_BEGIN};
+
+        if let Err(e) = unsafe { SetThreadPriority(GetCurrentThread(), THREAD_MODE_BACKGROUND_BEGIN) } {
+            warn!(error = %e, "Failed to lower indexing thread priority");
+        }
+    }
+}
+
+/// How many records indexing `volumes` should produce, from the size of their MFTs.
+/// None if none of them could be queried, e.g. they aren't NTFS or Rivet isn't elevated.
+#[cfg(windows)]
+pub fn estimate_record_count<'a>(volumes: impl IntoIterator<Item = &'a VolumeInfo>) -> Option<usize> {
+    volumes.into_iter()
+        .filter_map(|volume| {
+            let handle = unsafe {
+                CreateFileW(
+                    &HSTRING::from(volume.device_path()),
+                    GENERIC_READ.0,
+                    FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                    None,
+                    OPEN_EXISTING,
+                    Default::default(),
+                    HANDLE::default(),
+                )
+            }
+            .ok()?;
+            let estimate = mft_record_estimate(handle);
+            unsafe { let _ = CloseHandle(handle); }
+            estimate
+        })
+        .map(|estimate| estimate as usize)
+        .reduce(|a, b| a + b)
+}
+
+/// The MFT's record slots, used or not, as an estimate of how many records the volume
+/// open as `volume_handle` holds.
+#[cfg(windows)]
+fn mft_record_estimate(volume_handle: HANDLE) -> Option<u64> {
+    let mut ntfs_data = NTFS_VOLUME_DATA_BUFFER::default();
+    let mut bytes_returned = 0;
+    unsafe {
+        DeviceIoControl(
+            volume_handle,
+            FSCTL_GET_NTFS_VOLUME_DATA,
+            None,
+            0,
+            Some(&mut ntfs_data as *mut _ as *mut std::ffi::c_void),
+            std::mem::size_of::<NTFS_VOLUME_DATA_BUFFER>() as u32,
+            Some(&mut bytes_returned),
+            None,
+        )
+    }
+    .ok()?;
+    if ntfs_data.BytesPerFileRecordSegment == 0 {
+        return None;
+    }
+    Some(ntfs_data.MftValidDataLength as u64 / ntfs_data.BytesPerFileRecordSegment as u64)
+}
+
+/// What `refresh_size` measures; times are FILETIMEs.
+struct FileMetadata {
+    size: u64,
+    created: i64,
+    accessed: i64,
+}
+
+#[cfg(windows)]
+fn file_metadata(path: &OsStr) -> Option<FileMetadata> {
+    use windows::Win32::Storage::FileSystem::{GetFileAttributesExW, GetFileExInfoStandard, WIN32_FILE_ATTRIBUTE_DATA};
+
+    let mut data = WIN32_FILE_ATTRIBUTE_DATA::default();
+    unsafe {
+        GetFileAttributesExW(&HSTRING::from(path), GetFileExInfoStandard, &mut data as *mut _ as *mut _).ok()?;
+    }
+    let filetime = |time: FILETIME| ((time.dwHighDateTime as i64) << 32) | (time.dwLowDateTime as i64);
+    Some(FileMetadata {
+        size: ((data.nFileSizeHigh as u64) << 32) | (data.nFileSizeLow as u64),
+        created: filetime(data.ftCreationTime),
+        accessed: filetime(data.ftLastAccessTime),
+    })
+}
+
+/// Sizes only; the dates stay unknown off Windows.
+#[cfg(not(windows))]
+fn file_metadata(path: &OsStr) -> Option<FileMetadata> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some(FileMetadata { size: metadata.len(), created: 0, accessed: 0 })
+}
+
+/// SHA-256 of a file's contents, None if it can't be read.
+fn hash_file(path: &OsStr) -> Option<[u8; 32]> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(hasher.finalize().into())
+}
+
+/// A record's path as far as its parent chain could be followed.
+pub struct FullPath {
+    // `C:\dir\file.txt` when `complete`; otherwise starts with `…\` in place of the
+    // folders that couldn't be resolved, e.g. `…\nested\file.txt`
+    pub path: String,
+    // Whether the chain reached the volume root, so `path` names the real location
+    pub complete: bool,
+    // The path as it is on disk, when a name on it isn't valid UTF-16 and `path`
+    // only shows it lossily
+    pub exact: Option<OsString>,
+}
+
+impl FullPath {
+    /// The path for Win32 file APIs: exact where `path` is lossy, and prefixed where
+    /// it's too long, see `normalize_path`.
+    pub fn native(&self) -> OsString {
+        match &self.exact {
+            Some(exact) => normalize_os_path(exact),
+            None => normalize_path(&self.path).into(),
+        }
+    }
+
+    /// The path of `name` in this folder; `wide_name` as in `FileRecord::wide_name`.
+    fn child(&self, name: &str, wide_name: Option<&[u16]>) -> FullPath {
+        let separator = if self.path.ends_with('\\') { "" } else { "\\" };
+        let exact = (self.exact.is_some() || wide_name.is_some()).then(|| {
+            let mut exact = self.exact.clone().unwrap_or_else(|| OsString::from(&self.path));
+            exact.push(separator);
+            exact.push(wide_name.map_or_else(|| OsString::from(name), os_from_wide));
+            exact
+        });
+        FullPath { path: format!("{}{}{}", self.path, separator, name), complete: self.complete, exact }
+    }
+}
+
+pub struct SearchResults {
+    pub ids: Vec<u128>,
+    // The scan stopped at the limit, so there may be more matches
+    pub truncated: bool,
+}
+
+pub enum IndexProgress {
+    Started { volume: VolumeId },
+    RecordInserted { volume: VolumeId, count: u64 },
+    RecordFailed { volume: VolumeId, errors: u64 },
+    Complete { volume: VolumeId },
+    // Sizes fetched so far out of the volume's `total` files; `done == total` once finished.
+    // On-demand fetches of a few files report as one finished batch.
+    SizesFetched { volume: VolumeId, done: u64, total: u64 },
+    // The volume was unplugged; its `records` are kept but hidden until it's back
+    Offline { volume: VolumeId, records: u64 },
+    // The volume's records were purged (it was disabled, removed or is being rebuilt)
+    Removed { volume: VolumeId },
+}
+
+/// What a volume's indexing is busy with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexPhase {
+    Enumerating,
+    // Fetching file sizes after enumeration
+    FetchingMetadata,
+    Ready,
+    Error,
+}
+
+/// How far a volume's indexing has come, for the status bar.
+#[derive(Debug, Clone)]
+pub struct IndexingState {
+    pub phase: IndexPhase,
+    // Records enumerated or sizes fetched so far in this phase
+    pub processed: u64,
+    // What `processed` is expected to reach by the end of the phase; 0 if unknown
+    pub total: u64,
+    // When the phase started
+    pub started: Instant,
+}
+
+impl IndexingState {
+    fn new(phase: IndexPhase, total: u64) -> Self {
+        Self { phase, processed: 0, total, started: Instant::now() }
+    }
+
+    /// How much of the phase is done, None while there's no estimate to go by.
+    pub fn fraction(&self) -> Option<f32> {
+        (self.total > 0).then(|| (self.processed as f32 / self.total as f32).min(1.0))
+    }
+}
+
+const PROGRESS_INTERVAL: u64 = 10_000;
+// Size of a USN journal recreated after it was deleted, matching Windows' own default
+#[cfg(windows)]
+const USN_JOURNAL_MAX_SIZE: u64 = 32 * 1024 * 1024;
+#[cfg(windows)]
+const USN_JOURNAL_ALLOCATION_DELTA: u64 = 8 * 1024 * 1024;
+// How often a paused worker checks whether it was cancelled
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+// Sizes are slower to fetch than records are to read, so they're reported more often
+const SIZE_PROGRESS_INTERVAL: u64 = 1_000;
+// Stop intersecting posting lists once the next one is this many times the candidate count
+const MAX_INTERSECT_RATIO: usize = 16;
+// Search results whose paths are resolved at a time when filtering by path
+const PATH_FILTER_CHUNK: usize = 4096;
+// Give up on the volume after this many failed reads in a row (e.g. the drive went away)
+const MAX_CONSECUTIVE_ERRORS: u32 = 32;
+// Entries kept in `Indexer::error_log`
+const ERROR_LOG_LEN: usize = 200;
+// Starts a saved index; the last byte is the format version
+const INDEX_MAGIC: &[u8; 7] = b"RVTIDX\x03";
+const INDEX_COMPRESSION_LEVEL: i32 = 3;
+// Batches at least this big are inserted from several threads, see `batch_insert`
+const PARALLEL_INSERT_THRESHOLD: usize = 10_000;
+// Records read from a saved index before they're inserted together
+const LOAD_BATCH_SIZE: usize = 64 * 1024;
+
+/// Distinct byte trigrams of the lowercased name. Queries are lowercased the same way,
+/// so every trigram of a matching query also appears in the name.
+fn name_trigrams(name: &str) -> Vec<[u8; 3]> {
+    let lower = name.to_lowercase();
+    let mut grams: Vec<[u8; 3]> = lower.as_bytes().windows(3).map(|w| [w[0], w[1], w[2]]).collect();
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+/// Trigrams of every name a record can be found by.
+fn record_trigrams(record: &FileRecord) -> Vec<[u8; 3]> {
+    let mut grams = name_trigrams(&record.name);
+    for link in &record.links {
+        grams.extend(name_trigrams(&link.name));
+    }
+    grams.sort_unstable();
+    grams.dedup();
+    grams
+}
+
+/// Whether `name` looks like an 8.3 alias such as `PROGRA~1` or `MICROS~2.TXT`.
+fn is_short_name(name: &str) -> bool {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let valid = |part: &str, max: usize| {
+        part.len() <= max && part.chars().all(|c| c.is_ascii_graphic() && !c.is_ascii_lowercase())
+    };
+    !base.is_empty()
+        && valid(base, 8)
+        && valid(ext, 3)
+        && base.rsplit_once('~').is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
 }
 
 pub struct Indexer {
     // FileId -> FileRecord
-    pub records: DashMap<u64, FileRecord>,
+    pub records: DashMap<u128, FileRecord>,
+    // Trigram -> ids whose name contains it. Postings may hold stale ids after renames,
+    // so callers must verify candidates against the record's current name.
+    trigrams: DashMap<[u8; 3], Vec<u128>>,
+    // Set once the bulk build starts; from then on inserts keep `trigrams` up to date
+    trigrams_maintained: AtomicBool,
+    // Set once the bulk build finishes and `trigrams` can be trusted for lookups
+    trigrams_ready: AtomicBool,
+    // Folder id -> total size of every file beneath it, as of the last `compute_dir_sizes`
+    // or `compute_folder_size`
+    pub dir_sizes: DashMap<u128, u64>,
+    // Folders in `dir_sizes` with changes beneath them since they were measured
+    pub stale_dir_sizes: DashSet<u128>,
+    // Volume -> key of its root folder, for volumes where it was looked up
+    roots: DashMap<VolumeId, u128>,
+    // Volume -> folder its paths start from, e.g. `D:\` or `C:\Data\`
+    mounts: DashMap<VolumeId, String>,
+    // Volumes indexed by walking their folders, whose records are keyed by path
+    walked: DashSet<VolumeId>,
+    // Unplugged volumes whose records are left out of search results
+    offline: DashSet<VolumeId>,
+    // Volume -> how far its indexing has come
+    states: DashMap<VolumeId, IndexingState>,
+    // Volumes whose journal lost changes, waiting for `VolumeManager` to rebuild them
+    lost_journals: Mutex<Vec<VolumeId>>,
+    // Volumes being reindexed because their journal lost changes
+    catching_up: DashSet<VolumeId>,
+    // Set while the user has indexing paused; workers park on `resumed` until it's cleared
+    paused: AtomicBool,
+    pause_lock: Mutex<()>,
+    resumed: Condvar,
+    // Run indexing threads in background mode, see `begin_background_priority`
+    low_priority: AtomicBool,
+    // Files whose sizes the GUI is showing, fetched ahead of the rest of the volume
+    size_requests: Mutex<Vec<u128>>,
+    // Bumped whenever records are added, changed or removed, so the GUI can tell its
+    // results may be stale
+    generation: AtomicU64,
+    // Recent indexing and monitoring failures for the GUI, newest last
+    pub error_log: Mutex<VecDeque<(DateTime<Utc>, String)>>,
+    progress_tx: Option<mpsc::Sender<IndexProgress>>,
+    // Wakes the GUI when background work changed something it shows
+    repaint: OnceLock<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl Default for Indexer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl Indexer {
-    pub fn new() -> Self {
-        Self {
-            records: DashMap::new(),
+impl Indexer {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// An empty index with room for `records` records, so indexing that many doesn't
+    /// keep growing the map. See `estimate_record_count`.
+    pub fn with_capacity(records: usize) -> Self {
+        Self {
+            records: DashMap::with_capacity(records),
+            trigrams: DashMap::new(),
+            trigrams_maintained: AtomicBool::new(false),
+            trigrams_ready: AtomicBool::new(false),
+            dir_sizes: DashMap::new(),
+            stale_dir_sizes: DashSet::new(),
+            roots: DashMap::new(),
+            mounts: DashMap::new(),
+            walked: DashSet::new(),
+            offline: DashSet::new(),
+            states: DashMap::new(),
+            lost_journals: Mutex::new(Vec::new()),
+            catching_up: DashSet::new(),
+            paused: AtomicBool::new(false),
+            pause_lock: Mutex::new(()),
+            resumed: Condvar::new(),
+            low_priority: AtomicBool::new(false),
+            size_requests: Mutex::new(Vec::new()),
+            generation: AtomicU64::new(0),
+            error_log: Mutex::new(VecDeque::new()),
+            progress_tx: None,
+            repaint: OnceLock::new(),
+        }
+    }
+
+    /// Inserts or replaces a record, keeping the name index consistent.
+    pub fn insert_record(&self, record: FileRecord) {
+        let id = record.id;
+        let new_grams = self.trigrams_maintained.load(Ordering::Acquire)
+            .then(|| record_trigrams(&record));
+
+        // The entry guard is dropped before touching `trigrams`
+        let old = match self.records.entry(id) {
+            Entry::Occupied(mut entry) => {
+                // Enumeration and the USN monitor can race on a reused MFT slot; never
+                // let the previous incarnation overwrite the current one
+                if sequence_is_older(record.sequence, entry.get().sequence) {
+                    return;
+                }
+                Some(entry.insert(record))
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(record);
+                None
+            }
+        };
+        self.generation.fetch_add(1, Ordering::Relaxed);
+
+        let Some(new_grams) = new_grams else {
+            return;
+        };
+        let Some(old) = old else {
+            // A new id can't be in any posting list yet, so skip the membership checks
+            for gram in new_grams {
+                self.trigrams.entry(gram).or_default().push(id);
+            }
+            return;
+        };
+        let old_grams = record_trigrams(&old);
+
+        for gram in old_grams.iter().filter(|g| !new_grams.contains(g)) {
+            if let Some(mut ids) = self.trigrams.get_mut(gram)
+                && let Some(pos) = ids.iter().position(|&x| x == id)
+            {
+                ids.swap_remove(pos);
+            }
+        }
+        for gram in new_grams.iter().filter(|g| !old_grams.contains(g)) {
+            let mut ids = self.trigrams.entry(*gram).or_default();
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    /// The MFT lists a file once per name: its long name, possibly an 8.3 alias, and
+    /// any further hard links. Folds `record` into the one already stored for the same
+    /// file, preferring long names over aliases and keeping other links in `links`.
+    /// Returns the record to insert, or None if the stored one already covers it.
+    fn merge_names(&self, mut record: FileRecord) -> Option<FileRecord> {
+        let Some(mut existing) = self.records.get(&record.id).map(|r| r.clone()) else {
+            return Some(record);
+        };
+        // A different incarnation of the slot; `insert_record` picks the newer one
+        if existing.sequence != record.sequence {
+            return Some(record);
+        }
+        if is_short_name(&record.name) {
+            return None;
+        }
+        if is_short_name(&existing.name) {
+            record.links = existing.links;
+            return Some(record);
+        }
+        let link = HardLink { parent_id: record.parent_id, name: record.name };
+        if (existing.parent_id == link.parent_id && existing.name == link.name) || existing.links.contains(&link) {
+            return None;
+        }
+        existing.links.push(link);
+        Some(existing)
+    }
+
+    /// `insert_record` for many records at once. Big batches are spread over rayon's
+    /// threads, which DashMap's sharded locks let run side by side.
+    pub fn batch_insert(&self, records: Vec<FileRecord>) {
+        use rayon::prelude::*;
+
+        if records.len() < PARALLEL_INSERT_THRESHOLD {
+            for record in records {
+                self.insert_record(record);
+            }
+        } else {
+            records.into_par_iter().for_each(|record| self.insert_record(record));
+        }
+    }
+
+    /// Removes one record, e.g. after the file was deleted.
+    pub fn remove_record(&self, id: u128) {
+        let Some((_, old)) = self.records.remove(&id) else {
+            return;
+        };
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        if !self.trigrams_maintained.load(Ordering::Acquire) {
+            return;
+        }
+        for gram in record_trigrams(&old) {
+            if let Some(mut ids) = self.trigrams.get_mut(&gram)
+                && let Some(pos) = ids.iter().position(|&x| x == id)
+            {
+                ids.swap_remove(pos);
+            }
+        }
+    }
+
+    /// Builds the trigram name index over everything currently in `records`.
+    /// Inserts that race with the build are indexed incrementally.
+    pub fn build_name_index(&self) {
+        // Only the first volume needs a bulk build; later ones are indexed as they insert
+        if self.trigrams_maintained.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        for entry in self.records.iter() {
+            let id = *entry.key();
+            for gram in record_trigrams(&entry) {
+                self.trigrams.entry(gram).or_default().push(id);
+            }
+        }
+
+        self.trigrams_ready.store(true, Ordering::Release);
+    }
+
+    /// Returns the ids that may contain `query` (already lowercased), or None when the
+    /// index can't narrow the search and the caller should scan all records instead.
+    /// Candidates are deduplicated but still need to be verified.
+    pub fn name_candidates(&self, query: &str) -> Option<Vec<u128>> {
+        if !self.trigrams_ready.load(Ordering::Acquire) {
+            return None;
+        }
+        let grams = name_trigrams(query);
+        if grams.is_empty() {
+            return None;
+        }
+
+        // Rarest trigrams first. Only one posting list guard is held at a time.
+        let mut by_size = Vec::with_capacity(grams.len());
+        for gram in grams {
+            let Some(len) = self.trigrams.get(&gram).map(|ids| ids.len()) else {
+                return Some(Vec::new());
+            };
+            by_size.push((len, gram));
+        }
+        by_size.sort_unstable();
+
+        let mut candidates: HashSet<u128> = match self.trigrams.get(&by_size[0].1) {
+            Some(ids) => ids.iter().copied().collect(),
+            None => return Some(Vec::new()),
+        };
+        for (len, gram) in &by_size[1..] {
+            // Walking a list much longer than the candidates costs more than just
+            // verifying them, and lists only get longer from here
+            if candidates.is_empty() || *len > candidates.len() * MAX_INTERSECT_RATIO {
+                break;
+            }
+            let Some(ids) = self.trigrams.get(gram) else {
+                return Some(Vec::new());
+            };
+            candidates = ids.iter().copied().filter(|id| candidates.contains(id)).collect();
+        }
+
+        let mut candidates: Vec<u128> = candidates.into_iter().collect();
+        candidates.sort_unstable();
+        Some(candidates)
+    }
+
+    pub fn set_progress_sender(&mut self, tx: mpsc::Sender<IndexProgress>) {
+        self.progress_tx = Some(tx);
+    }
+
+    fn report_progress(&self, progress: IndexProgress) {
+        if let Some(tx) = &self.progress_tx {
+            // The receiver going away just means nobody is watching any more
+            let _ = tx.send(progress);
+        }
+        self.request_repaint();
+    }
+
+    /// Sets what `request_repaint` calls; only the first signal set is kept.
+    pub fn set_repaint_signal(&self, signal: impl Fn() + Send + Sync + 'static) {
+        let _ = self.repaint.set(Box::new(signal));
+    }
+
+    /// Lets the GUI know the index changed, so it redraws without polling.
+    pub fn request_repaint(&self) {
+        if let Some(signal) = self.repaint.get() {
+            signal();
+        }
+    }
+
+    /// Finds up to `limit` records matching `query`, leaving out those of offline
+    /// volumes. Uses the trigram index when it can narrow the scan.
+    pub fn search(&self, query: &SearchQuery, limit: usize) -> SearchResults {
+        // Records that pass everything but exclusions, `path:` and folder filters are
+        // collected in full and checked afterwards, since any number of them may be
+        // dropped and the limit only counts the ones kept
+        let filtered_later = query.within.is_some() || query.needs_path();
+        let scan_limit = if filtered_later { usize::MAX } else { limit };
+        let offline: Vec<VolumeId> = self.offline.iter().map(|volume| *volume).collect();
+        let online = |id: u128| offline.is_empty() || !offline.contains(&key_volume(id));
+        let mut ids = Vec::new();
+
+        if let Some(candidates) = self.name_candidates(&query.required_text()) {
+            for id in candidates {
+                if online(id) && self.records.get(&id).is_some_and(|r| query.matches(&r)) {
+                    ids.push(id);
+                }
+                if ids.len() > scan_limit {
+                    break;
+                }
+            }
+        } else {
+            for entry in self.records.iter() {
+                if online(*entry.key()) && query.matches(&entry) {
+                    ids.push(*entry.key());
+                }
+                if ids.len() > scan_limit {
+                    break;
+                }
+            }
+        }
+
+        // Applied after the scan because resolving paths while iterating `records`
+        // would re-enter DashMap shard locks
+        if let Some(folder) = query.within {
+            let mut memo = HashMap::new();
+            ids.retain(|&id| self.is_within(id, folder, &mut memo));
+        }
+        if query.needs_path() {
+            let mut kept = Vec::new();
+            // Only as many paths are resolved as it takes to get past the limit
+            for chunk in ids.chunks(PATH_FILTER_CHUNK) {
+                let paths = self.get_full_paths(chunk);
+                for (&id, full_path) in chunk.iter().zip(paths) {
+                    let Some(record) = self.records.get(&id).map(|r| r.clone()) else {
+                        continue;
+                    };
+                    if is_excluded(&query.excluded, &full_path.path, &record.name) {
+                        continue;
+                    }
+                    // `path:` filters match any of the file's hard links
+                    let link_paths = if record.links.is_empty() { Vec::new() } else { self.link_paths(id) };
+                    let matched = std::iter::once(&full_path).chain(&link_paths)
+                        .any(|path| query.eval(&record, Some(&path.path)) == Some(true));
+                    if matched {
+                        kept.push(id);
+                    }
+                }
+                if kept.len() > limit {
+                    break;
+                }
+            }
+            ids = kept;
+        }
+
+        let truncated = ids.len() > limit;
+        ids.truncate(limit);
+        SearchResults { ids, truncated }
+    }
+
+    /// The `count` biggest files matching `query`, biggest first.
+    pub fn largest_files(&self, query: &SearchQuery, count: usize) -> Vec<u128> {
+        self.top_files(query, count, |record| Some(record.size as i64))
+    }
+
+    /// Up to `count` files matching `query` modified at or after the FILETIME `since`,
+    /// newest first.
+    pub fn recent_files(&self, query: &SearchQuery, since: i64, count: usize) -> Vec<u128> {
+        self.top_files(query, count, |record| (record.modified >= since).then_some(record.modified))
+    }
+
+    /// The `count` files matching `query` with the highest `key`, highest first. Files
+    /// without a key are left out. Scans the whole index, so best run off the UI thread.
+    fn top_files(&self, query: &SearchQuery, count: usize, key: impl Fn(&FileRecord) -> Option<i64>) -> Vec<u128> {
+        let ids = self.search(query, usize::MAX).ids;
+        let mut keyed: Vec<(i64, u128)> = ids.into_iter()
+            .filter_map(|id| {
+                let record = self.records.get(&id).filter(|r| !r.is_dir)?;
+                key(&record).map(|key| (key, id))
+            })
+            .collect();
+        // Only the ones kept need sorting
+        if keyed.len() > count {
+            keyed.select_nth_unstable_by(count, |a, b| b.cmp(a));
+            keyed.truncate(count);
+        }
+        keyed.sort_unstable_by(|a, b| b.cmp(a));
+        keyed.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Where the index is saved by default.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("Rivet").join("index.rivet.zst"))
+    }
+
+    /// Writes every record to `path`: `INDEX_MAGIC`, then the records in bincode,
+    /// zstd-compressed. `compress: false` writes the bare bincode, which is handy for
+    /// inspecting but can't be loaded back.
+    pub fn save(&self, path: &Path, compress: bool) -> anyhow::Result<()> {
+        let started = Instant::now();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        if compress {
+            file.write_all(INDEX_MAGIC)?;
+            let mut encoder = zstd::Encoder::new(file, INDEX_COMPRESSION_LEVEL)?;
+            self.write_records(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        } else {
+            self.write_records(&mut file)?;
+            file.flush()?;
+        }
+        info!(
+            path = %path.display(),
+            record_count = self.records.len(),
+            bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or_default(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Index saved"
+        );
+        Ok(())
+    }
+
+    // One record at a time, so saving doesn't need a copy of the whole index
+    fn write_records(&self, writer: &mut impl Write) -> anyhow::Result<()> {
+        bincode::serialize_into(&mut *writer, &(self.records.len() as u64))?;
+        for record in self.records.iter() {
+            bincode::serialize_into(&mut *writer, record.value())?;
+        }
+        Ok(())
+    }
+
+    /// Reads an index written by `save`. The name index isn't saved, so call
+    /// `build_name_index` afterwards.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let started = Instant::now();
+        let mut file = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; INDEX_MAGIC.len()];
+        let has_magic = file.read_exact(&mut magic).is_ok() && &magic == INDEX_MAGIC;
+        if !has_magic {
+            if magic.starts_with(&INDEX_MAGIC[..INDEX_MAGIC.len() - 1]) {
+                anyhow::bail!("{} is saved in an unsupported index format version {}", path.display(), magic[INDEX_MAGIC.len() - 1]);
+            }
+            anyhow::bail!("{} is not a compressed Rivet index; it may be uncompressed or from an older version", path.display());
+        }
+
+        let mut decoder = zstd::Decoder::new(file)?;
+        let count: u64 = bincode::deserialize_from(&mut decoder)?;
+        let indexer = Self::new();
+        // In batches, so inserting runs in parallel without reading the whole file first
+        let mut batch = Vec::with_capacity(LOAD_BATCH_SIZE.min(count as usize));
+        for _ in 0..count {
+            batch.push(bincode::deserialize_from(&mut decoder)?);
+            if batch.len() == LOAD_BATCH_SIZE {
+                indexer.batch_insert(std::mem::take(&mut batch));
+            }
+        }
+        indexer.batch_insert(batch);
+        info!(path = %path.display(), record_count = count, elapsed_ms = started.elapsed().as_millis() as u64, "Index loaded");
+        Ok(indexer)
+    }
+
+    /// Drops every record belonging to `volume`, e.g. after it was disabled or removed.
+    pub fn remove_volume(&self, volume: VolumeId) {
+        self.records.retain(|id, _| key_volume(*id) != volume);
+        self.dir_sizes.retain(|id, _| key_volume(*id) != volume);
+        self.stale_dir_sizes.retain(|id| key_volume(*id) != volume);
+        self.roots.remove(&volume);
+        self.mounts.remove(&volume);
+        self.walked.remove(&volume);
+        self.offline.remove(&volume);
+        self.states.remove(&volume);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        for mut ids in self.trigrams.iter_mut() {
+            ids.retain(|id| key_volume(*id) != volume);
+        }
+        self.report_progress(IndexProgress::Removed { volume });
+    }
+
+    /// Hides the volume's records from searches until it's removed with `remove_volume`,
+    /// e.g. while its drive is unplugged.
+    pub fn set_offline(&self, volume: VolumeId) {
+        self.offline.insert(volume);
+        self.states.remove(&volume);
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        let records = self.records.iter().filter(|r| key_volume(r.id) == volume).count() as u64;
+        self.report_progress(IndexProgress::Offline { volume, records });
+    }
+
+    /// A counter that changes whenever the set of records does. Results from a search
+    /// are current as long as it reads the same as when the search ran.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes enumeration and the size fetch. Paused workers finish the record
+    /// they're on and then wait.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+        if !paused {
+            // Taking the lock orders this after a worker's check, so the wakeup isn't lost
+            let _guard = self.pause_lock.lock().unwrap();
+            self.resumed.notify_all();
+        }
+        self.request_repaint();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Acquire)
+    }
+
+    /// Parks the calling worker while indexing is paused, returning early once `token`
+    /// is cancelled.
+    fn wait_while_paused(&self, token: &CancellationToken) {
+        if !self.is_paused() {
+            return;
+        }
+        let mut guard = self.pause_lock.lock().unwrap();
+        while self.is_paused() && !token.is_cancelled() {
+            guard = self.resumed.wait_timeout(guard, PAUSE_CHECK_INTERVAL).unwrap().0;
+        }
+    }
+
+    /// Whether indexing threads should call `begin_background_priority`. Applies to
+    /// work started after it's set.
+    pub fn set_low_priority(&self, low_priority: bool) {
+        self.low_priority.store(low_priority, Ordering::Relaxed);
+    }
+
+    pub fn is_low_priority(&self) -> bool {
+        self.low_priority.load(Ordering::Relaxed)
+    }
+
+    /// Asks for `volume` to be reindexed because its journal lost changes, see
+    /// `take_lost_journals`.
+    pub fn report_lost_journal(&self, volume: VolumeId) {
+        self.catching_up.insert(volume);
+        self.lost_journals.lock().unwrap().push(volume);
+        self.request_repaint();
+    }
+
+    /// Volumes reported by `report_lost_journal` since the last call.
+    pub fn take_lost_journals(&self) -> Vec<VolumeId> {
+        std::mem::take(&mut *self.lost_journals.lock().unwrap())
+    }
+
+    /// Whether `volume` is being reindexed to catch up with changes its journal lost.
+    pub fn is_catching_up(&self, volume: VolumeId) -> bool {
+        self.catching_up.contains(&volume)
+    }
+
+    /// Progress of every volume being indexed or done indexing, in volume order.
+    pub fn indexing_states(&self) -> Vec<(VolumeId, IndexingState)> {
+        let mut states: Vec<(VolumeId, IndexingState)> = self.states.iter()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+            .collect();
+        states.sort_by_key(|(volume, _)| *volume);
+        states
+    }
+
+    fn set_phase(&self, volume: VolumeId, phase: IndexPhase, total: u64) {
+        self.states.insert(volume, IndexingState::new(phase, total));
+    }
+
+    fn set_processed(&self, volume: VolumeId, processed: u64) {
+        if let Some(mut state) = self.states.get_mut(&volume) {
+            state.processed = processed;
+        }
+    }
+
+    /// Records a failure for the GUI's error log, dropping the oldest past `ERROR_LOG_LEN`.
+    pub fn log_error(&self, message: String) {
+        let mut log = self.error_log.lock().unwrap();
+        if log.len() >= ERROR_LOG_LEN {
+            log.pop_front();
+        }
+        log.push_back((Utc::now(), message));
+        drop(log);
+        self.request_repaint();
+    }
+
+    #[cfg(windows)]
+    pub fn index_volume(&self, volume: &VolumeInfo, buffer_size: usize, token: &CancellationToken) -> anyhow::Result<()> {
+        let started = Instant::now();
+        info!(%volume, "MFT enumeration started");
+        self.set_phase(volume.id, IndexPhase::Enumerating, 0);
+        self.report_progress(IndexProgress::Started { volume: volume.id });
+        self.mounts.insert(volume.id, volume.root().to_string());
+        let result = if has_usn_journal(volume) {
+            self.enumerate_volume(volume, buffer_size, token)
+        } else {
+            self.walk_volume(volume, token)
+        };
+        self.catching_up.remove(&volume.id);
+        self.report_progress(IndexProgress::Complete { volume: volume.id });
+
+        let elapsed_ms = started.elapsed().as_millis() as u64;
+        match &result {
+            Ok(record_count) => {
+                info!(%volume, record_count, elapsed_ms, "MFT enumeration complete");
+                self.set_phase(volume.id, IndexPhase::Ready, 0);
+                self.set_processed(volume.id, *record_count);
+            }
+            Err(e) => {
+                self.set_phase(volume.id, IndexPhase::Error, 0);
+                error!(%volume, elapsed_ms, error = %e, "MFT enumeration failed");
+                self.log_error(format!("{}: MFT enumeration failed: {}", volume, e));
+            }
         }
+        result.map(|_| ())
+    }
+
+    /// Creates the volume's USN journal, e.g. after someone deleted it with fsutil.
+    #[cfg(windows)]
+    fn create_usn_journal(volume: &VolumeInfo) -> anyhow::Result<()> {
+        let handle = unsafe {
+            CreateFileW(
+                &HSTRING::from(volume.device_path()),
+                (GENERIC_READ | GENERIC_WRITE).0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                Default::default(),
+                HANDLE::default(),
+            )?
+        };
+        let request = CREATE_USN_JOURNAL_DATA {
+            MaximumSize: USN_JOURNAL_MAX_SIZE,
+            AllocationDelta: USN_JOURNAL_ALLOCATION_DELTA,
+        };
+        let result = unsafe {
+            DeviceIoControl(
+                handle,
+                FSCTL_CREATE_USN_JOURNAL,
+                Some(&request as *const _ as *const std::ffi::c_void),
+                std::mem::size_of::<CREATE_USN_JOURNAL_DATA>() as u32,
+                None,
+                0,
+                None,
+                None,
+            )
+        };
+        unsafe { let _ = CloseHandle(handle); }
+        result?;
+        Ok(())
     }
 
-    pub fn index_volume(&self, drive_letter: char, token: &CancellationToken) -> anyhow::Result<()> {
+    /// Checks the volume's USN journal is active, then indexes its MFT.
+    #[cfg(windows)]
+    fn enumerate_volume(&self, volume: &VolumeInfo, buffer_size: usize, token: &CancellationToken) -> anyhow::Result<u64> {
         // Ensure USN journal is active
-        let volume_path = format!("\\\\.\\{}:", drive_letter);
         let volume_handle = unsafe {
             CreateFileW(
-                &HSTRING::from(volume_path),
+                &HSTRING::from(volume.device_path()),
                 GENERIC_READ.0,
                 FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
                 None,
@@ -63,102 +1344,1086 @@ impl Indexer {
             )
         };
 
+        let estimate = mft_record_estimate(volume_handle);
+
         unsafe { let _ = CloseHandle(volume_handle); }
 
+        if let Some(mut state) = self.states.get_mut(&volume.id) {
+            state.total = estimate.unwrap_or(0);
+        }
+        // Without a journal the volume couldn't be monitored, so put one back
+        let result = match result {
+            Err(e) if e.code() == ERROR_JOURNAL_NOT_ACTIVE.into() => {
+                warn!(%volume, "USN journal is missing, creating a new one");
+                Self::create_usn_journal(volume)
+                    .map_err(|e| anyhow::anyhow!("Failed to create USN journal for volume {}: {}", volume.root(), e))?;
+                Ok(())
+            }
+            result => result,
+        };
         if let Err(e) = result {
-            anyhow::bail!("Failed to query USN journal for volume {}:\\: {}", drive_letter, e);
+            anyhow::bail!("Failed to query USN journal for volume {}: {} (0x{:08X})", volume.root(), e, e.code().0);
+        }
+
+        match root_file_id(volume.root()) {
+            Some(fid) => {
+                self.roots.insert(volume.id, record_key(volume.id, fid));
+            }
+            None => warn!(%volume, "Failed to look up the root folder's file ID"),
+        }
+        let enumerator = MftEnumerator::new(volume, buffer_size)?;
+        let count = self.index_from(volume.id, enumerator, token)?;
+        debug!(%volume, estimated = estimate.unwrap_or(0), actual = count, "MFT record estimate");
+        Ok(count)
+    }
+
+    /// Indexes a volume without an MFT (FAT32, exFAT, network shares) by walking it.
+    #[cfg(windows)]
+    fn walk_volume(&self, volume: &VolumeInfo, token: &CancellationToken) -> anyhow::Result<u64> {
+        info!(%volume, "No USN journal, walking folders instead");
+        self.walked.insert(volume.id);
+        self.roots.insert(volume.id, record_key(volume.id, DirWalker::root_fid(volume.root())));
+        self.index_from(volume.id, DirWalker::new(volume.root()), token)
+    }
+
+    /// Whether `volume` was indexed by walking its folders, see `walk_volume`.
+    pub fn is_walked(&self, volume: VolumeId) -> bool {
+        self.walked.contains(&volume)
+    }
+
+    /// Removes a record and everything beneath it. Only needed where records are keyed
+    /// by path, so a folder's contents can't outlive it; costs a pass over the volume.
+    pub fn remove_subtree(&self, id: u128) {
+        let volume = key_volume(id);
+        let mut children: HashMap<u128, Vec<u128>> = HashMap::new();
+        for record in self.records.iter().filter(|r| key_volume(r.id) == volume) {
+            children.entry(record.parent_id).or_default().push(record.id);
+        }
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(ids) = children.remove(&id) {
+                stack.extend(ids);
+            }
+            self.remove_record(id);
         }
+    }
 
-        let enumerator = MftEnumerator::new(drive_letter)?;
-        
-        for entry in enumerator.iter() {
+    /// Inserts every record `source` yields as belonging to `volume` and returns how
+    /// many were read. Stops early, without error, once `token` is cancelled.
+    pub fn index_from(&self, volume: VolumeId, mut source: impl VolumeEnumerator, token: &CancellationToken) -> anyhow::Result<u64> {
+        let mut count = 0u64;
+        let mut errors = 0u64;
+        let mut consecutive_errors = 0u32;
+
+        for entry in source.entries() {
+            self.wait_while_paused(token);
             if token.is_cancelled() {
-                return Ok(());
+                return Ok(count);
             }
-            let entry = entry?;
+            let entry = match entry {
+                Ok(entry) => {
+                    consecutive_errors = 0;
+                    entry
+                }
+                Err(e) => {
+                    errors += 1;
+                    consecutive_errors += 1;
+                    warn!(%volume, errors, error = %e, "Skipping unreadable MFT record");
+                    self.report_progress(IndexProgress::RecordFailed { volume, errors });
+                    if consecutive_errors >= MAX_CONSECUTIVE_ERRORS {
+                        anyhow::bail!("Giving up on {} after {} consecutive read errors: {}", volume, consecutive_errors, e);
+                    }
+                    continue;
+                }
+            };
             
             let record = FileRecord {
-                id: entry.fid,
-                parent_id: entry.parent_fid,
+                id: record_key(volume, entry.fid),
+                parent_id: record_key(volume, entry.parent_fid),
+                sequence: fid_sequence(entry.fid),
                 name: entry.name,
-                size: 0, // Will be fetched later
+                wide_name: entry.wide_name,
+                // Fetched later unless the source already knew it
+                size: entry.size,
                 modified: entry.modified,
+                created: entry.created,
+                accessed: entry.accessed,
                 is_dir: entry.is_dir,
+                is_reparse_point: entry.is_reparse_point,
+                attributes: entry.attributes,
+                hash: None,
+                links: Vec::new(),
             };
-            
-            self.records.insert(record.id, record);
+
+            if let Some(record) = self.merge_names(record) {
+                self.insert_record(record);
+            }
+
+            count += 1;
+            if count.is_multiple_of(PROGRESS_INTERVAL) {
+                self.set_processed(volume, count);
+                self.report_progress(IndexProgress::RecordInserted { volume, count });
+            }
         }
-        
-        Ok(())
+
+        self.set_processed(volume, count);
+        self.report_progress(IndexProgress::RecordInserted { volume, count });
+        Ok(count)
+    }
+
+    /// Fetches sizes for every file on `volume` on a dedicated rayon pool of `threads`
+    /// workers (0 lets rayon pick one per core).
+    pub fn fetch_sizes(&self, volume: VolumeId, threads: usize, token: &CancellationToken) {
+        use rayon::prelude::*;
+
+        // Collect ids up front so no DashMap shard lock is held while workers write sizes
+        let all_ids: Vec<u128> = self.snapshot_ids()
+            .into_iter()
+            .filter(|id| key_volume(*id) == volume)
+            .collect();
+        let done = AtomicUsize::new(0);
+        let total = all_ids.len() as u64;
+        info!(%volume, record_count = all_ids.len(), "Fetching file sizes");
+        self.set_phase(volume, IndexPhase::FetchingMetadata, total);
+        self.report_progress(IndexProgress::SizesFetched { volume, done: 0, total });
+
+        let result = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("rivet-size-{}", i))
+            .build_scoped(
+                |thread| {
+                    if self.is_low_priority() {
+                        begin_background_priority();
+                    }
+                    thread.run()
+                },
+                |pool| pool.install(|| {
+                    all_ids.par_iter().for_each(|id| {
+                        self.wait_while_paused(token);
+                        if token.is_cancelled() { return; }
+                        let i = done.fetch_add(1, Ordering::Relaxed) + 1;
+                        if i.is_multiple_of(10000) {
+                            debug!(%volume, done = i, record_count = all_ids.len(), "Size fetch progress");
+                        }
+                        if (i as u64).is_multiple_of(SIZE_PROGRESS_INTERVAL) {
+                            self.set_processed(volume, i as u64);
+                            self.report_progress(IndexProgress::SizesFetched { volume, done: i as u64, total });
+                        }
+                        self.fetch_requested_sizes();
+                        self.fetch_size(*id);
+                    });
+                }),
+            );
+
+        if let Err(e) = result {
+            error!(%volume, error = %e, "Failed to start size fetch pool");
+            self.log_error(format!("{}: Failed to start size fetch pool: {}", volume, e));
+        }
+        self.set_phase(volume, IndexPhase::Ready, 0);
+        self.set_processed(volume, total);
+        self.report_progress(IndexProgress::SizesFetched { volume, done: total, total });
+    }
+
+    /// Asks a running `fetch_sizes` to measure `ids` next, e.g. the rows on screen.
+    /// Replaces the previous request, whose rows are no longer of interest.
+    pub fn request_sizes(&self, ids: Vec<u128>) {
+        *self.size_requests.lock().unwrap() = ids;
     }
 
-    pub fn fetch_sizes(&self, drive_letter: char, token: &CancellationToken) {
-        use windows::Win32::Storage::FileSystem::{GetFileAttributesExW, GetFileExInfoStandard, WIN32_FILE_ATTRIBUTE_DATA};
-        use windows::core::HSTRING;
+    /// Fills in the sizes of `ids` that aren't known yet, right away rather than
+    /// waiting for `fetch_sizes` to reach them. Blocks while the files are queried.
+    pub fn ensure_sizes(&self, ids: &[u128]) {
+        let mut measured: HashMap<VolumeId, u64> = HashMap::new();
+        for &id in ids {
+            let old_size = self.records.get(&id).map_or(0, |r| r.size);
+            if self.fetch_size(id) {
+                *measured.entry(key_volume(id)).or_default() += 1;
+                // So folder totals count the file just measured
+                let new_size = self.records.get(&id).map_or(old_size, |r| r.size);
+                self.add_to_dir_sizes(id, new_size as i64 - old_size as i64);
+            }
+        }
+        // So sums over the files just measured are redone
+        for (volume, count) in measured {
+            self.report_progress(IndexProgress::SizesFetched { volume, done: count, total: count });
+        }
+        self.request_repaint();
+    }
+
+    fn fetch_requested_sizes(&self) {
+        let mut fetched = false;
+        loop {
+            // Popped separately so the lock isn't held while the file is queried
+            let next = self.size_requests.lock().unwrap().pop();
+            match next {
+                Some(id) => {
+                    self.fetch_size(id);
+                    fetched = true;
+                }
+                None => break,
+            }
+        }
+        if fetched {
+            self.request_repaint();
+        }
+    }
+
+    /// Fills in the size and creation/access dates of one file if they aren't known yet.
+    /// Folders only get their dates. Returns whether a file's size was measured.
+    // Safe to call from many threads at once: each step takes at most one DashMap
+    // guard and releases it before the next, so workers can't deadlock on shards.
+    pub fn fetch_size(&self, id: u128) -> bool {
+        // Check if we need to fetch (using read lock)
+        let (is_dir, current_size, created) = if let Some(r) = self.records.get(&id) {
+            (r.is_dir, r.size, r.created)
+        } else {
+            return false;
+        };
+
+        if created != 0 && (is_dir || current_size > 0) {
+            return false;
+        }
+        self.refresh_size(id);
+        !is_dir
+    }
+
+    /// Measures a file's size again, e.g. after it was written to, along with its
+    /// creation and last access dates. The stored values are left alone if the file
+    /// can't be queried, such as when it was just deleted.
+    pub fn refresh_size(&self, id: u128) {
+        // 1. Build path WITHOUT holding a lock on the record we're about to update
+        let full_path = self.get_full_path(id);
+        if !full_path.complete {
+            return;
+        }
+
+        // 2. System call
+        let Some(metadata) = file_metadata(&full_path.native()) else {
+            return;
+        };
+
+        // 3. Update (using write lock)
+        if let Some(mut item) = self.records.get_mut(&id) {
+            // Folders report 0, and their sizes are totalled separately
+            if !item.is_dir {
+                item.size = metadata.size;
+            }
+            item.created = metadata.created;
+            item.accessed = metadata.accessed;
+        }
+    }
 
-        println!("Indexing complete. Starting metadata fetch for {} items...", self.records.len());
+    /// Hashes every file that shares its size with another one, since only those can
+    /// have a duplicate. Folders, empty files and reparse points (which may be cloud
+    /// placeholders that reading would download) are skipped, as are files already hashed.
+    pub fn compute_hashes(&self, token: &CancellationToken) {
+        use rayon::prelude::*;
 
-        // Process in large chunks to avoid holding million-entry vectors
-        let all_ids: Vec<u64> = self.records.iter().map(|r| *r.key()).collect();
-        
-        for (i, id) in all_ids.iter().enumerate() {
-            if token.is_cancelled() { break; }
-            if i % 10000 == 0 && i > 0 {
-                println!("Metadata progress: {}/{}", i, all_ids.len());
+        let started = Instant::now();
+        let files: Vec<u128> = self.snapshot_ids()
+            .into_iter()
+            .filter(|id| self.records.get(id).is_some_and(|r| !r.is_dir && !r.is_reparse_point))
+            .collect();
+        // Same-size grouping needs every size, not just the ones fetched so far
+        files.par_iter().for_each(|id| {
+            if !token.is_cancelled() {
+                self.fetch_size(*id);
             }
+        });
 
-            // 1. Check if we need to fetch (using read lock)
-            let (is_dir, current_size) = if let Some(r) = self.records.get(id) {
-                (r.is_dir, r.size)
-            } else {
+        // Hashed files still count towards a group, so a new file with the same size as
+        // one hashed on an earlier run gets hashed too
+        let mut by_size: HashMap<u64, Vec<(u128, bool)>> = HashMap::new();
+        for id in files {
+            let Some((size, hashed)) = self.records.get(&id).map(|r| (r.size, r.hash.is_some())) else {
                 continue;
             };
+            if size > 0 {
+                by_size.entry(size).or_default().push((id, hashed));
+            }
+        }
+        let candidates: Vec<u128> = by_size.into_values()
+            .filter(|group| group.len() > 1)
+            .flatten()
+            .filter(|&(_, hashed)| !hashed)
+            .map(|(id, _)| id)
+            .collect();
+        info!(file_count = candidates.len(), "Hashing files with duplicate sizes");
 
-            if is_dir || current_size > 0 {
-                continue;
+        candidates.par_iter().for_each(|&id| {
+            if token.is_cancelled() {
+                return;
+            }
+            let full_path = self.get_full_path(id);
+            if !full_path.complete {
+                return;
             }
+            if let Some(hash) = hash_file(&full_path.native())
+                && let Some(mut record) = self.records.get_mut(&id)
+            {
+                record.hash = Some(hash);
+            }
+        });
+        info!(elapsed_ms = started.elapsed().as_millis() as u64, "Hashing complete");
+    }
 
-            // 2. Build path WITHOUT holding a lock on the record we're about to update
-            let path = self.get_full_path(*id, drive_letter);
-            
-            // 3. System call
-            let mut data = WIN32_FILE_ATTRIBUTE_DATA::default();
-            let size = unsafe {
-                if GetFileAttributesExW(&HSTRING::from(path), GetFileExInfoStandard, &mut data as *mut _ as *mut _).is_ok() {
-                    Some(((data.nFileSizeHigh as u64) << 32) | (data.nFileSizeLow as u64))
-                } else {
-                    None
+    /// Groups of files with identical contents, keyed by their hash. Only covers files
+    /// hashed by `compute_hashes`.
+    pub fn find_duplicates(&self) -> HashMap<[u8; 32], Vec<u128>> {
+        let mut groups: HashMap<[u8; 32], Vec<u128>> = HashMap::new();
+        for record in self.records.iter() {
+            if let Some(hash) = record.hash {
+                groups.entry(hash).or_default().push(record.id);
+            }
+        }
+        groups.retain(|_, ids| ids.len() > 1);
+        groups
+    }
+
+    /// Recomputes `dir_sizes` for the folders of `volume` from the file sizes currently
+    /// known: every file's size is added to each folder above it. Files whose size
+    /// hasn't been fetched count as 0, and live changes aren't reflected until the next
+    /// call. Other volumes' folders are left alone.
+    pub fn compute_dir_sizes(&self, volume: VolumeId) {
+        let started = Instant::now();
+        // Snapshot the tree so no shard lock is held while walking it
+        let mut parents: HashMap<u128, u128> = HashMap::new();
+        let mut files: Vec<(u128, u64)> = Vec::new();
+        for record in self.records.iter().filter(|r| key_volume(r.id) == volume) {
+            parents.insert(record.id, record.parent_id);
+            if !record.is_dir && record.size > 0 {
+                files.push((record.parent_id, record.size));
+            }
+        }
+
+        let mut sizes: HashMap<u128, u64> = HashMap::new();
+        for (parent_id, size) in files {
+            let mut current = parent_id;
+            // Bounded so a corrupt cycle can't spin forever
+            for _ in 0..MAX_PATH_DEPTH {
+                *sizes.entry(current).or_default() += size;
+                match parents.get(&current) {
+                    Some(&next) if next != current && next != 0 => current = next,
+                    _ => break,
                 }
-            };
+            }
+        }
+
+        self.dir_sizes.retain(|id, _| key_volume(*id) != volume);
+        self.stale_dir_sizes.retain(|id| key_volume(*id) != volume);
+        let folder_count = sizes.len();
+        for (id, size) in sizes {
+            self.dir_sizes.insert(id, size);
+        }
+        info!(%volume, folder_count, elapsed_ms = started.elapsed().as_millis() as u64, "Folder sizes computed");
+    }
 
-            // 4. Update (using write lock)
-            if let Some(s) = size {
-                if let Some(mut item) = self.records.get_mut(id) {
-                    item.size = s;
+    /// The `count` folders with the most data beneath them, largest first. Volume roots
+    /// are left out since they'd always top the list.
+    pub fn largest_dirs(&self, count: usize) -> Vec<(u128, u64)> {
+        let mut dirs: Vec<(u128, u64)> = self.dir_sizes.iter()
+            .filter(|entry| !self.is_root(*entry.key()))
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect();
+        dirs.sort_unstable_by_key(|d| std::cmp::Reverse(d.1));
+        dirs.truncate(count);
+        dirs
+    }
+
+    /// `get_full_path` for many records at once, in the same order. Each folder's path is
+    /// resolved once and shared, so files in the same folder don't each walk the chain
+    /// up to the root again.
+    pub fn get_full_paths(&self, ids: &[u128]) -> Vec<FullPath> {
+        let mut folders: HashMap<u128, FullPath> = HashMap::new();
+        ids.iter()
+            .map(|&id| {
+                let Some((name, wide_name, parent_id)) = self.records.get(&id)
+                    .map(|r| (r.name.clone(), r.wide_name.clone(), r.parent_id))
+                else {
+                    return self.get_full_path(id);
+                };
+                // The chain stops at a link parent, which `get_full_path` of the parent
+                // itself wouldn't do, so those (and the root) take the slow path
+                let walkable = !self.is_root(id)
+                    && parent_id != id
+                    && parent_id != 0
+                    && self.records.get(&parent_id).is_some_and(|r| !r.is_reparse_point);
+                if !walkable {
+                    return self.get_full_path(id);
                 }
+                let folder = folders.entry(parent_id).or_insert_with(|| self.get_full_path(parent_id));
+                folder.child(&name, wide_name.as_deref())
+            })
+            .collect()
+    }
+
+    /// A copy of every record, taken in one pass, for work on the whole index that
+    /// shouldn't hold shard locks while it runs. Records changed meanwhile aren't in it.
+    pub fn snapshot(&self) -> Vec<FileRecord> {
+        self.records.iter().map(|r| r.value().clone()).collect()
+    }
+
+    /// The id of every record, like `snapshot` for when the ids are all that's needed.
+    pub fn snapshot_ids(&self) -> Vec<u128> {
+        self.records.iter().map(|r| *r.key()).collect()
+    }
+
+    /// Whether `id` is a folder the index should know about but doesn't, so paths
+    /// through it come out incomplete. The root is always known.
+    pub fn is_missing(&self, id: u128) -> bool {
+        !self.is_root(id) && !self.records.contains_key(&id)
+    }
+
+    /// Whether `id` is its volume's root folder. That's MFT record 5 on NTFS; ReFS roots
+    /// have to be looked up, which `index_volume` does.
+    fn is_root(&self, id: u128) -> bool {
+        match self.roots.get(&key_volume(id)) {
+            Some(root) => *root == id,
+            None => fid_to_mft_index(id) == ROOT_RECORD,
+        }
+    }
+
+    /// The indexed folder at `path`, for limiting a search to what's beneath it. Looked
+    /// up by name, then checked against the full path. Volume roots aren't records, so
+    /// they're never found.
+    pub fn find_folder(&self, path: &str) -> Option<u128> {
+        let path = path.trim_end_matches('\\');
+        let name = path.rsplit('\\').next()?.to_lowercase();
+        let is_folder = |record: &FileRecord| record.is_dir && record.name.to_lowercase() == name;
+        let candidates = self.name_candidates(&name).unwrap_or_else(|| {
+            self.records.iter().filter(|r| is_folder(r)).map(|r| *r.key()).collect()
+        });
+        candidates.into_iter().find(|&id| {
+            self.records.get(&id).is_some_and(|r| is_folder(&r))
+                && self.get_full_path(id).path.to_lowercase() == path.to_lowercase()
+        })
+    }
+
+    /// Whether `id`, or any of its hard links, is somewhere beneath `folder`. Walks the
+    /// parent chains instead of comparing paths; `memo` keeps the answer for each
+    /// folder passed on the way, so later results in the same folders stop early.
+    fn is_within(&self, id: u128, folder: u128, memo: &mut HashMap<u128, bool>) -> bool {
+        let Some(parents) = self.records.get(&id)
+            .map(|r| std::iter::once(r.parent_id).chain(r.links.iter().map(|link| link.parent_id)).collect::<Vec<_>>())
+        else {
+            return false;
+        };
+        parents.into_iter().any(|parent| self.folder_within(parent, folder, memo))
+    }
+
+    /// Whether the folder `start` is `folder` or beneath it, see `is_within`.
+    fn folder_within(&self, start: u128, folder: u128, memo: &mut HashMap<u128, bool>) -> bool {
+        let mut chain = Vec::new();
+        let mut current = start;
+        let within = loop {
+            if current == folder {
+                break true;
+            }
+            if let Some(&known) = memo.get(&current) {
+                break known;
+            }
+            // A cycle runs into the depth limit, like in `get_full_path`
+            if self.is_root(current) || chain.len() > MAX_PATH_DEPTH {
+                break false;
             }
+            let Some(parent) = self.records.get(&current).map(|r| r.parent_id) else {
+                break false;
+            };
+            chain.push(current);
+            if parent == current || parent == 0 {
+                break false;
+            }
+            current = parent;
+        };
+        for id in chain {
+            memo.insert(id, within);
+        }
+        within
+    }
+
+    /// Totals the size of every file beneath `folder` and stores it in `dir_sizes`, for
+    /// one folder wanted before the next `compute_dir_sizes`, or changed since the last.
+    /// Costs a pass over the folder's volume.
+    pub fn compute_folder_size(&self, folder: u128) -> u64 {
+        let volume = key_volume(folder);
+        // Snapshot the files so no shard lock is held while walking parent chains
+        let files: Vec<(u128, u64)> = self.snapshot_ids()
+            .into_iter()
+            .filter(|id| key_volume(*id) == volume)
+            .filter_map(|id| self.records.get(&id).filter(|r| !r.is_dir).map(|r| (id, r.size)))
+            .collect();
+        let mut memo = HashMap::new();
+        let size = files.into_iter()
+            .filter(|&(id, _)| self.is_within(id, folder, &mut memo))
+            .map(|(_, size)| size)
+            .sum();
+        self.dir_sizes.insert(folder, size);
+        self.stale_dir_sizes.remove(&folder);
+        size
+    }
+
+    /// Adds `delta` bytes to the measured folders above `id`, for a file whose size just
+    /// became known. Folders not measured yet are left for `compute_dir_sizes`.
+    fn add_to_dir_sizes(&self, id: u128, delta: i64) {
+        if delta == 0 {
+            return;
+        }
+        let mut current = id;
+        for _ in 0..MAX_PATH_DEPTH {
+            let Some(parent) = self.records.get(&current).map(|r| r.parent_id) else {
+                return;
+            };
+            if parent == current || parent == 0 {
+                return;
+            }
+            if let Some(mut size) = self.dir_sizes.get_mut(&parent) {
+                *size = size.saturating_add_signed(delta);
+            }
+            current = parent;
+        }
+    }
+
+    /// Marks the measured folders above `id` as out of date, for a change to `id`.
+    pub fn mark_dir_sizes_stale(&self, id: u128) {
+        let mut current = id;
+        for _ in 0..MAX_PATH_DEPTH {
+            let Some(parent) = self.records.get(&current).map(|r| r.parent_id) else {
+                return;
+            };
+            if parent == current || parent == 0 {
+                return;
+            }
+            if self.dir_sizes.contains_key(&parent) {
+                self.stale_dir_sizes.insert(parent);
+            }
+            current = parent;
         }
     }
 
-    pub fn get_full_path(&self, id: u64, drive_letter: char) -> String {
+    /// Paths of the file's other hard links, see `FileRecord::links`.
+    pub fn link_paths(&self, id: u128) -> Vec<FullPath> {
+        let links = self.records.get(&id).map(|r| r.links.clone()).unwrap_or_default();
+        links.into_iter()
+            .map(|link| self.get_full_path(link.parent_id).child(&link.name, None))
+            .collect()
+    }
+
+    /// Builds the path of `id` by walking its parents up to the volume root. If the
+    /// chain breaks first (a parent missing from the index, a cycle, a self-parented
+    /// record or a link), the result is marked incomplete rather than passed off as a
+    /// path directly under the root.
+    pub fn get_full_path(&self, id: u128) -> FullPath {
         let mut components = Vec::new();
+        // Alongside `components`, for names that aren't valid UTF-16
+        let mut wide_names: Vec<Option<Vec<u16>>> = Vec::new();
         let mut current_id = id;
         let mut visited = std::collections::HashSet::new();
-        
-        while let Some(record) = self.records.get(&current_id) {
-            if !visited.insert(current_id) || visited.len() > 64 {
-                break; 
+        let mut complete = false;
+
+        loop {
+            // The root contributes no name of its own; its path is where the volume is mounted
+            if self.is_root(current_id) {
+                complete = true;
+                break;
+            }
+            let Some(record) = self.records.get(&current_id) else {
+                break;
+            };
+            if !visited.insert(current_id) {
+                warn!("Cycle in parent chain of record 0x{:x} at 0x{:x}", id, current_id);
+                break;
+            }
+            if visited.len() > MAX_PATH_DEPTH {
+                warn!("Parent chain of record 0x{:x} is deeper than {} levels", id, MAX_PATH_DEPTH);
+                break;
+            }
+            // Parent chains should never pass through a link; if one does, the chain is
+            // likely a junction loop, so stop here instead of walking it to the depth limit
+            if current_id != id && record.is_reparse_point {
+                warn!("Parent chain of record 0x{:x} passes through reparse point {:?}", id, record.name);
+                components.push(record.name.clone());
+                wide_names.push(record.wide_name.clone());
+                break;
             }
 
             components.push(record.name.clone());
-            
+            wide_names.push(record.wide_name.clone());
+
             if record.parent_id == current_id || record.parent_id == 0 {
                 break;
             }
             current_id = record.parent_id;
         }
-        
+
         components.reverse();
-        format!("{}:\\{}", drive_letter, components.join("\\"))
+        wide_names.reverse();
+        let root = if complete { self.volume_root(key_volume(id)) } else { None };
+        let complete = root.is_some();
+        let start = root.as_deref().unwrap_or(UNRESOLVED_PREFIX);
+        let path = format!("{}{}", start, components.join("\\"));
+        let exact = wide_names.iter().any(Option::is_some).then(|| {
+            let mut exact = OsString::from(start);
+            for (i, (name, wide_name)) in components.iter().zip(&wide_names).enumerate() {
+                if i > 0 {
+                    exact.push("\\");
+                }
+                match wide_name {
+                    Some(wide_name) => exact.push(os_from_wide(wide_name)),
+                    None => exact.push(name),
+                }
+            }
+            exact
+        });
+        FullPath { path, complete, exact }
+    }
+
+    /// Where paths on `volume` start, e.g. `D:\` or `C:\Data\`. Volumes not indexed by
+    /// `index_volume`, such as ones loaded from a file, fall back to their drive letter.
+    fn volume_root(&self, volume: VolumeId) -> Option<String> {
+        self.mounts.get(&volume)
+            .map(|root| root.clone())
+            .or_else(|| volume.letter().map(|letter| format!("{}:\\", letter)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume() -> VolumeId {
+        VolumeId::from_letter('C')
+    }
+
+    fn record(fid: u128, parent_fid: u128, name: &str, is_dir: bool) -> FileRecord {
+        FileRecord {
+            id: record_key(volume(), fid),
+            parent_id: record_key(volume(), parent_fid),
+            sequence: 1,
+            name: name.to_string(),
+            wide_name: None,
+            size: 0,
+            modified: 0,
+            created: 0,
+            accessed: 0,
+            is_dir,
+            is_reparse_point: false,
+            attributes: 0,
+            hash: None,
+            links: Vec::new(),
+        }
+    }
+
+    // C:\docs\{Report.txt, report-old.txt, notes.md} and C:\hidden.txt
+    fn sample_index() -> Indexer {
+        let indexer = Indexer::new();
+        indexer.insert_record(record(ROOT_RECORD, ROOT_RECORD, "C:", true));
+        indexer.insert_record(record(100, ROOT_RECORD, "docs", true));
+        indexer.insert_record(record(101, 100, "Report.txt", false));
+        indexer.insert_record(record(102, 100, "report-old.txt", false));
+        indexer.insert_record(record(103, 100, "notes.md", false));
+        indexer.insert_record(FileRecord { attributes: FILE_ATTRIBUTE_HIDDEN, ..record(104, ROOT_RECORD, "hidden.txt", false) });
+        indexer
+    }
+
+    fn found(indexer: &Indexer, query: &SearchQuery) -> Vec<String> {
+        let mut names: Vec<String> = indexer.search(query, usize::MAX).ids.iter()
+            .map(|id| indexer.records.get(id).unwrap().name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn oversized_file_ids_keep_their_volume() {
+        let fid = (0xAB << KEY_VOLUME_SHIFT) | (1 << 64) | 7;
+        let key = record_key(volume(), fid);
+        assert_eq!(key_volume(key), volume());
+        assert_eq!(key & KEY_ID_MASK, (1 << 64) | 7);
+    }
+
+    #[test]
+    fn folder_sizes_are_computed_per_volume() {
+        let indexer = sample_index();
+        indexer.records.get_mut(&record_key(volume(), 101)).unwrap().size = 10;
+        indexer.records.get_mut(&record_key(volume(), 103)).unwrap().size = 5;
+        let other = VolumeId::from_letter('D');
+        let other_folder = record_key(other, 100);
+        indexer.dir_sizes.insert(other_folder, 99);
+
+        indexer.compute_dir_sizes(volume());
+        assert_eq!(indexer.dir_sizes.get(&record_key(volume(), 100)).map(|size| *size), Some(15));
+        assert_eq!(indexer.dir_sizes.get(&record_key(volume(), ROOT_RECORD)).map(|size| *size), Some(15));
+        assert_eq!(indexer.dir_sizes.get(&other_folder).map(|size| *size), Some(99));
+    }
+
+    #[test]
+    fn measured_files_add_to_measured_folders_only() {
+        let indexer = sample_index();
+        indexer.records.get_mut(&record_key(volume(), 101)).unwrap().size = 10;
+        indexer.compute_dir_sizes(volume());
+        let docs = record_key(volume(), 100);
+        let root = record_key(volume(), ROOT_RECORD);
+        // A folder nobody measured stays unmeasured
+        indexer.dir_sizes.remove(&docs);
+
+        indexer.records.get_mut(&record_key(volume(), 103)).unwrap().size = 5;
+        indexer.add_to_dir_sizes(record_key(volume(), 103), 5);
+        assert_eq!(indexer.dir_sizes.get(&root).map(|size| *size), Some(15));
+        assert!(indexer.dir_sizes.get(&docs).is_none());
+    }
+
+    #[test]
+    fn search_ignores_case_unless_asked() {
+        let indexer = sample_index();
+        assert_eq!(found(&indexer, &SearchQuery::new("report", false)), ["Report.txt", "report-old.txt"]);
+        assert_eq!(found(&indexer, &SearchQuery::new("REPORT", false)), ["Report.txt", "report-old.txt"]);
+        assert_eq!(found(&indexer, &SearchQuery::new("report", true)), ["report-old.txt"]);
+        assert_eq!(found(&indexer, &SearchQuery::new("Report", true)), ["Report.txt"]);
+        assert!(found(&indexer, &SearchQuery::new("REPORT", true)).is_empty());
+    }
+
+    #[test]
+    fn search_matches_paths_in_the_same_case() {
+        let indexer = sample_index();
+        assert_eq!(found(&indexer, &SearchQuery::new("path:DOCS md", false)), ["notes.md"]);
+        assert!(found(&indexer, &SearchQuery::new("path:DOCS md", true)).is_empty());
+        assert_eq!(found(&indexer, &SearchQuery::new("path:docs md", true)), ["notes.md"]);
+    }
+
+    #[test]
+    fn search_through_the_name_index_agrees_with_a_scan() {
+        let indexer = sample_index();
+        let queries = ["report", "Report", "txt", "old", "zzz"];
+        let scanned: Vec<_> = queries.iter()
+            .flat_map(|text| [false, true].map(|case| found(&indexer, &SearchQuery::new(text, case))))
+            .collect();
+        indexer.build_name_index();
+        let indexed: Vec<_> = queries.iter()
+            .flat_map(|text| [false, true].map(|case| found(&indexer, &SearchQuery::new(text, case))))
+            .collect();
+        assert_eq!(scanned, indexed);
+    }
+
+    #[test]
+    fn search_leaves_out_hidden_files_unless_included() {
+        let indexer = sample_index();
+        assert_eq!(found(&indexer, &SearchQuery::new("txt", false)), ["Report.txt", "report-old.txt"]);
+        let search = SearchQuery { include_hidden: true, ..SearchQuery::new("txt", false) };
+        assert_eq!(found(&indexer, &search), ["Report.txt", "hidden.txt", "report-old.txt"]);
+    }
+
+    #[test]
+    fn exclusions_apply_before_the_limit() {
+        let indexer = sample_index();
+        let search = SearchQuery { excluded: vec!["report-*".to_string()], ..SearchQuery::new("txt", false) };
+        let results = indexer.search(&search, 1);
+        assert_eq!(results.ids, [record_key(volume(), 101)]);
+        assert!(!results.truncated);
+
+        let search = SearchQuery { excluded: vec!["C:\\docs".to_string()], ..SearchQuery::new("txt", false) };
+        let results = indexer.search(&search, 1);
+        assert!(results.ids.is_empty());
+        assert!(!results.truncated);
+    }
+
+    #[test]
+    fn search_reports_truncation_only_past_the_limit() {
+        let indexer = sample_index();
+        let search = SearchQuery::new("txt", false);
+        let results = indexer.search(&search, 1);
+        assert_eq!(results.ids.len(), 1);
+        assert!(results.truncated);
+        let results = indexer.search(&search, 2);
+        assert_eq!(results.ids.len(), 2);
+        assert!(!results.truncated);
+    }
+
+    // sample_index with Report.txt at 30 bytes, report-old.txt and notes.md tied at 20,
+    // modified at 300, 100 and 200, and the docs folder bigger and newer than all of them
+    fn sized_index() -> Indexer {
+        let indexer = sample_index();
+        for (fid, size, modified) in [(100, 1000, 900), (101, 30, 300), (102, 20, 100), (103, 20, 200)] {
+            let mut record = indexer.records.get_mut(&record_key(volume(), fid)).unwrap();
+            record.size = size;
+            record.modified = modified;
+        }
+        indexer
+    }
+
+    fn names(indexer: &Indexer, ids: &[u128]) -> Vec<String> {
+        ids.iter().map(|id| indexer.records.get(id).unwrap().name.clone()).collect()
+    }
+
+    #[test]
+    fn largest_files_come_biggest_first_without_folders() {
+        let indexer = sized_index();
+        let largest = names(&indexer, &indexer.largest_files(&SearchQuery::new("", false), 10));
+        assert_eq!(largest.len(), 3);
+        assert_eq!(largest[0], "Report.txt");
+        let mut tied = largest[1..].to_vec();
+        tied.sort();
+        assert_eq!(tied, ["notes.md", "report-old.txt"]);
+    }
+
+    #[test]
+    fn largest_files_keep_only_count() {
+        let indexer = sized_index();
+        let query = SearchQuery::new("", false);
+        assert_eq!(names(&indexer, &indexer.largest_files(&query, 1)), ["Report.txt"]);
+        // One of the tied files makes the cut
+        let two = names(&indexer, &indexer.largest_files(&query, 2));
+        assert_eq!(two.len(), 2);
+        assert_eq!(two[0], "Report.txt");
+        assert!(two[1] == "notes.md" || two[1] == "report-old.txt");
+        assert!(indexer.largest_files(&query, 0).is_empty());
+    }
+
+    #[test]
+    fn largest_files_are_narrowed_by_the_search_text() {
+        let indexer = sized_index();
+        let largest = indexer.largest_files(&SearchQuery::new("old", false), 10);
+        assert_eq!(names(&indexer, &largest), ["report-old.txt"]);
+    }
+
+    #[test]
+    fn recent_files_stop_at_the_cutoff() {
+        let indexer = sized_index();
+        let query = SearchQuery::new("", false);
+        assert_eq!(names(&indexer, &indexer.recent_files(&query, 0, 10)), ["Report.txt", "notes.md", "report-old.txt"]);
+        // A file modified exactly at the cutoff is kept
+        assert_eq!(names(&indexer, &indexer.recent_files(&query, 200, 10)), ["Report.txt", "notes.md"]);
+        assert_eq!(names(&indexer, &indexer.recent_files(&query, 0, 1)), ["Report.txt"]);
+        assert!(indexer.recent_files(&query, 0, 0).is_empty());
+        assert!(indexer.recent_files(&query, 1000, 10).is_empty());
+    }
+
+    #[test]
+    fn recent_files_keep_files_modified_at_the_same_time() {
+        let indexer = sized_index();
+        indexer.records.get_mut(&record_key(volume(), 102)).unwrap().modified = 200;
+        let recent = names(&indexer, &indexer.recent_files(&SearchQuery::new("", false), 200, 10));
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[0], "Report.txt");
+        let mut tied = recent[1..].to_vec();
+        tied.sort();
+        assert_eq!(tied, ["notes.md", "report-old.txt"]);
+    }
+
+    #[test]
+    fn recent_files_are_narrowed_by_the_search_text() {
+        let indexer = sized_index();
+        let recent = indexer.recent_files(&SearchQuery::new("report", false), 150, 10);
+        assert_eq!(names(&indexer, &recent), ["Report.txt"]);
+    }
+
+    #[test]
+    fn short_paths_are_left_alone() {
+        assert_eq!(normalize_path("C:\\Users\\a.txt"), "C:\\Users\\a.txt");
+        assert_eq!(normalize_path("\\\\server\\share\\a.txt"), "\\\\server\\share\\a.txt");
+    }
+
+    #[test]
+    fn long_paths_get_the_long_prefix() {
+        let path = format!("C:\\{}\\a.txt", "x".repeat(300));
+        assert_eq!(normalize_path(&path), format!("\\\\?\\{}", path));
+        let unc = format!("\\\\server\\share\\{}", "x".repeat(300));
+        assert_eq!(normalize_path(&unc), format!("\\\\?\\UNC\\server\\share\\{}", "x".repeat(300)));
+    }
+
+    #[test]
+    fn the_limit_counts_utf16_units() {
+        // 259 units is the longest path that fits with its terminating NUL
+        let fits = format!("C:\\{}", "x".repeat(MAX_PATH - 4));
+        assert_eq!(normalize_path(&fits), fits);
+        let too_long = format!("C:\\{}", "x".repeat(MAX_PATH - 3));
+        assert!(normalize_path(&too_long).starts_with(LONG_PATH_PREFIX));
+        // Each of these is two bytes in UTF-8 but one unit in UTF-16
+        let accented = format!("C:\\{}", "é".repeat(200));
+        assert_eq!(normalize_path(&accented), accented);
+    }
+
+    #[test]
+    fn prefixed_paths_are_not_prefixed_again() {
+        let path = format!("\\\\?\\C:\\{}\\a.txt", "x".repeat(300));
+        assert_eq!(normalize_path(&path), path);
+        let unc = format!("\\\\?\\UNC\\server\\share\\{}", "x".repeat(300));
+        assert_eq!(normalize_path(&unc), unc);
+        assert_eq!(normalize_os_path(OsStr::new(&path)), OsString::from(&path));
+    }
+
+    #[test]
+    fn full_path_of_the_root_is_the_volume_root() {
+        let indexer = sample_index();
+        let root = indexer.get_full_path(record_key(volume(), ROOT_RECORD));
+        assert_eq!(root.path, "C:\\");
+        assert!(root.complete);
+    }
+
+    #[test]
+    fn full_path_of_a_deep_file() {
+        let indexer = sample_index();
+        let mut parent = ROOT_RECORD;
+        for level in 0..1000 {
+            indexer.insert_record(record(1000 + level, parent, &format!("d{}", level), true));
+            parent = 1000 + level;
+        }
+        indexer.insert_record(record(5000, parent, "deep.txt", false));
+        let full_path = indexer.get_full_path(record_key(volume(), 5000));
+        assert!(full_path.complete);
+        assert!(full_path.path.starts_with("C:\\d0\\d1\\d2\\"));
+        assert!(full_path.path.ends_with("\\d998\\d999\\deep.txt"));
+        assert_eq!(full_path.path.matches('\\').count(), 1001);
+    }
+
+    #[test]
+    fn full_path_below_a_missing_folder_is_incomplete() {
+        let indexer = sample_index();
+        indexer.insert_record(record(200, 199, "orphan", true));
+        indexer.insert_record(record(201, 200, "a.txt", false));
+        let full_path = indexer.get_full_path(record_key(volume(), 201));
+        assert!(!full_path.complete);
+        assert_eq!(full_path.path, "…\\orphan\\a.txt");
+    }
+
+    #[test]
+    fn full_path_through_a_parent_cycle_stops() {
+        let indexer = sample_index();
+        indexer.insert_record(record(200, 201, "a", true));
+        indexer.insert_record(record(201, 200, "b", true));
+        indexer.insert_record(record(202, 201, "c.txt", false));
+        let full_path = indexer.get_full_path(record_key(volume(), 202));
+        assert!(!full_path.complete);
+        assert_eq!(full_path.path, "…\\a\\b\\c.txt");
+
+        indexer.insert_record(record(203, 203, "self", true));
+        let full_path = indexer.get_full_path(record_key(volume(), 203));
+        assert!(!full_path.complete);
+        assert_eq!(full_path.path, "…\\self");
+    }
+
+    fn merge(indexer: &Indexer, record: FileRecord) {
+        if let Some(record) = indexer.merge_names(record) {
+            indexer.insert_record(record);
+        }
+    }
+
+    #[test]
+    fn short_names_are_recognized() {
+        assert!(is_short_name("PROGRA~1"));
+        assert!(is_short_name("REPO~12.TXT"));
+        assert!(!is_short_name("report~1.txt"));
+        assert!(!is_short_name("LONGNAME~1"));
+        assert!(!is_short_name("REPORT~1.HTML"));
+        assert!(!is_short_name("REPORT~.TXT"));
+        assert!(!is_short_name("README.TXT"));
+    }
+
+    #[test]
+    fn short_names_after_the_long_name_are_dropped() {
+        let indexer = sample_index();
+        merge(&indexer, record(105, 100, "Quarterly Report.docx", false));
+        merge(&indexer, record(105, 100, "QUARTE~1.DOC", false));
+        let stored = indexer.records.get(&record_key(volume(), 105)).unwrap();
+        assert_eq!(stored.name, "Quarterly Report.docx");
+        assert!(stored.links.is_empty());
+    }
+
+    #[test]
+    fn long_names_replace_a_short_name_seen_first() {
+        let indexer = sample_index();
+        merge(&indexer, record(105, 100, "QUARTE~1.DOC", false));
+        merge(&indexer, record(105, ROOT_RECORD, "backup.docx", false));
+        merge(&indexer, record(105, 100, "Quarterly Report.docx", false));
+        let stored = indexer.records.get(&record_key(volume(), 105)).unwrap();
+        assert_eq!(stored.name, "backup.docx");
+        assert_eq!(stored.links, vec![HardLink { parent_id: record_key(volume(), 100), name: "Quarterly Report.docx".to_string() }]);
+    }
+
+    #[test]
+    fn hard_links_are_recorded_once() {
+        let indexer = sample_index();
+        merge(&indexer, record(105, 100, "a.txt", false));
+        merge(&indexer, record(105, ROOT_RECORD, "b.txt", false));
+        merge(&indexer, record(105, ROOT_RECORD, "b.txt", false));
+        merge(&indexer, record(105, 100, "a.txt", false));
+        let stored = indexer.records.get(&record_key(volume(), 105)).unwrap();
+        assert_eq!(stored.name, "a.txt");
+        assert_eq!(stored.links, vec![HardLink { parent_id: record_key(volume(), ROOT_RECORD), name: "b.txt".to_string() }]);
+    }
+
+    #[test]
+    fn names_of_a_reused_slot_are_not_merged() {
+        let indexer = sample_index();
+        merge(&indexer, record(105, 100, "old.txt", false));
+        merge(&indexer, FileRecord { sequence: 2, ..record(105, 100, "new.txt", false) });
+        let stored = indexer.records.get(&record_key(volume(), 105)).unwrap();
+        assert_eq!(stored.name, "new.txt");
+        assert!(stored.links.is_empty());
+    }
+
+    #[test]
+    fn records_round_trip_through_json() {
+        let record = FileRecord {
+            wide_name: Some(vec![0x61, 0xD800]),
+            size: 48_213,
+            modified: 133_485_408_000_000_000,
+            attributes: FILE_ATTRIBUTE_HIDDEN,
+            hash: Some([0xAB; 32]),
+            links: vec![HardLink { parent_id: record_key(volume(), ROOT_RECORD), name: "b.txt".to_string() }],
+            ..record(105, 100, "a\u{FFFD}", false)
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(json.contains(&format!("\"hash\":\"{}\"", "ab".repeat(32))));
+        assert!(json.contains("\"parentId\":"));
+        let parsed: FileRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        assert_eq!(parsed.id, record.id);
+        assert_eq!(parsed.hash, record.hash);
+        assert_eq!(parsed.wide_name, record.wide_name);
+        assert_eq!(parsed.links, record.links);
+    }
+
+    #[test]
+    fn link_paths_list_every_other_link() {
+        let indexer = sample_index();
+        merge(&indexer, record(105, 100, "a.txt", false));
+        merge(&indexer, record(105, ROOT_RECORD, "b.txt", false));
+        merge(&indexer, record(105, 100, "c.txt", false));
+        let paths: Vec<String> = indexer.link_paths(record_key(volume(), 105)).into_iter()
+            .map(|path| path.path)
+            .collect();
+        assert_eq!(paths, ["C:\\b.txt", "C:\\docs\\c.txt"]);
+        assert_eq!(indexer.get_full_path(record_key(volume(), 105)).path, "C:\\docs\\a.txt");
+        assert!(indexer.link_paths(record_key(volume(), 101)).is_empty());
+    }
+
+    #[test]
+    fn paths_through_unpaired_surrogates_keep_the_exact_name() {
+        let indexer = sample_index();
+        let wide_name = vec![0x61, 0xD800, 0x2E, 0x74, 0x78, 0x74];
+        indexer.insert_record(FileRecord { wide_name: Some(wide_name), ..record(105, 100, "a\u{FFFD}.txt", false) });
+        let full_path = indexer.get_full_path(record_key(volume(), 105));
+        assert_eq!(full_path.path, "C:\\docs\\a\u{FFFD}.txt");
+        assert!(full_path.exact.is_some());
+        assert!(indexer.get_full_path(record_key(volume(), 101)).exact.is_none());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn native_paths_keep_unpaired_surrogates() {
+        let indexer = sample_index();
+        let wide_name = vec![0x61, 0xD800, 0x2E, 0x74, 0x78, 0x74];
+        indexer.insert_record(FileRecord { wide_name: Some(wide_name.clone()), ..record(105, 100, "a\u{FFFD}.txt", false) });
+        let mut expected: Vec<u16> = "C:\\docs\\".encode_utf16().collect();
+        expected.extend(&wide_name);
+        assert_eq!(os_to_wide(&indexer.get_full_path(record_key(volume(), 105)).native()), expected);
+
+        // Still exact once the path needs the long prefix
+        let folder = "f".repeat(300);
+        indexer.insert_record(record(106, ROOT_RECORD, &folder, true));
+        indexer.insert_record(FileRecord { wide_name: Some(wide_name.clone()), ..record(107, 106, "a\u{FFFD}.txt", false) });
+        let mut expected: Vec<u16> = format!("\\\\?\\C:\\{}\\", folder).encode_utf16().collect();
+        expected.extend(&wide_name);
+        assert_eq!(os_to_wide(&indexer.get_full_path(record_key(volume(), 107)).native()), expected);
     }
 }
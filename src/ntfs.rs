@@ -0,0 +1,108 @@
+//! The file attribute flags and USN record layouts the index reads, mirrored from Win32
+//! so that parsing records and applying changes build and run on every platform.
+
+// FILE_ATTRIBUTE_* flags
+pub const FILE_ATTRIBUTE_READONLY: u32 = 0x0000_0001;
+pub const FILE_ATTRIBUTE_HIDDEN: u32 = 0x0000_0002;
+pub const FILE_ATTRIBUTE_SYSTEM: u32 = 0x0000_0004;
+pub const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x0000_0010;
+pub const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x0000_0020;
+pub const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x0000_0400;
+
+// USN_REASON_* flags
+pub const USN_REASON_DATA_OVERWRITE: u32 = 0x0000_0001;
+pub const USN_REASON_DATA_EXTEND: u32 = 0x0000_0002;
+pub const USN_REASON_DATA_TRUNCATION: u32 = 0x0000_0004;
+pub const USN_REASON_FILE_CREATE: u32 = 0x0000_0100;
+pub const USN_REASON_FILE_DELETE: u32 = 0x0000_0200;
+pub const USN_REASON_SECURITY_CHANGE: u32 = 0x0000_0800;
+pub const USN_REASON_RENAME_OLD_NAME: u32 = 0x0000_1000;
+pub const USN_REASON_RENAME_NEW_NAME: u32 = 0x0000_2000;
+pub const USN_REASON_BASIC_INFO_CHANGE: u32 = 0x0000_8000;
+pub const USN_REASON_HARD_LINK_CHANGE: u32 = 0x0001_0000;
+pub const USN_REASON_CLOSE: u32 = 0x8000_0000;
+
+/// `USN_RECORD_COMMON_HEADER`: the start of every USN record version.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UsnRecordHeader {
+    pub record_length: u32,
+    pub major_version: u16,
+    pub minor_version: u16,
+}
+
+/// `USN_RECORD_V2`, returned by NTFS. The name follows at `file_name_offset`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UsnRecordV2 {
+    pub header: UsnRecordHeader,
+    pub file_reference_number: u64,
+    pub parent_file_reference_number: u64,
+    pub usn: i64,
+    pub time_stamp: i64,
+    pub reason: u32,
+    pub source_info: u32,
+    pub security_id: u32,
+    pub file_attributes: u32,
+    pub file_name_length: u16,
+    pub file_name_offset: u16,
+    pub file_name: [u16; 1],
+}
+
+/// `USN_RECORD_V3`, returned by ReFS, whose file IDs take 128 bits.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct UsnRecordV3 {
+    pub header: UsnRecordHeader,
+    pub file_reference_number: [u8; 16],
+    pub parent_file_reference_number: [u8; 16],
+    pub usn: i64,
+    pub time_stamp: i64,
+    pub reason: u32,
+    pub source_info: u32,
+    pub security_id: u32,
+    pub file_attributes: u32,
+    pub file_name_length: u16,
+    pub file_name_offset: u16,
+    pub file_name: [u16; 1],
+}
+
+#[cfg(all(test, windows))]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+    use windows::Win32::Storage::FileSystem as fs;
+    use windows::Win32::System::Ioctl as ioctl;
+
+    #[test]
+    fn flags_match_win32() {
+        assert_eq!(FILE_ATTRIBUTE_READONLY, fs::FILE_ATTRIBUTE_READONLY.0);
+        assert_eq!(FILE_ATTRIBUTE_HIDDEN, fs::FILE_ATTRIBUTE_HIDDEN.0);
+        assert_eq!(FILE_ATTRIBUTE_SYSTEM, fs::FILE_ATTRIBUTE_SYSTEM.0);
+        assert_eq!(FILE_ATTRIBUTE_DIRECTORY, fs::FILE_ATTRIBUTE_DIRECTORY.0);
+        assert_eq!(FILE_ATTRIBUTE_ARCHIVE, fs::FILE_ATTRIBUTE_ARCHIVE.0);
+        assert_eq!(FILE_ATTRIBUTE_REPARSE_POINT, fs::FILE_ATTRIBUTE_REPARSE_POINT.0);
+        assert_eq!(USN_REASON_DATA_OVERWRITE, ioctl::USN_REASON_DATA_OVERWRITE);
+        assert_eq!(USN_REASON_DATA_EXTEND, ioctl::USN_REASON_DATA_EXTEND);
+        assert_eq!(USN_REASON_DATA_TRUNCATION, ioctl::USN_REASON_DATA_TRUNCATION);
+        assert_eq!(USN_REASON_FILE_CREATE, ioctl::USN_REASON_FILE_CREATE);
+        assert_eq!(USN_REASON_FILE_DELETE, ioctl::USN_REASON_FILE_DELETE);
+        assert_eq!(USN_REASON_SECURITY_CHANGE, ioctl::USN_REASON_SECURITY_CHANGE);
+        assert_eq!(USN_REASON_RENAME_OLD_NAME, ioctl::USN_REASON_RENAME_OLD_NAME);
+        assert_eq!(USN_REASON_RENAME_NEW_NAME, ioctl::USN_REASON_RENAME_NEW_NAME);
+        assert_eq!(USN_REASON_BASIC_INFO_CHANGE, ioctl::USN_REASON_BASIC_INFO_CHANGE);
+        assert_eq!(USN_REASON_HARD_LINK_CHANGE, ioctl::USN_REASON_HARD_LINK_CHANGE);
+        assert_eq!(USN_REASON_CLOSE, ioctl::USN_REASON_CLOSE);
+    }
+
+    #[test]
+    fn layouts_match_win32() {
+        assert_eq!(size_of::<UsnRecordHeader>(), size_of::<ioctl::USN_RECORD_COMMON_HEADER>());
+        assert_eq!(size_of::<UsnRecordV2>(), size_of::<ioctl::USN_RECORD_V2>());
+        assert_eq!(size_of::<UsnRecordV3>(), size_of::<ioctl::USN_RECORD_V3>());
+        assert_eq!(offset_of!(UsnRecordV2, time_stamp), offset_of!(ioctl::USN_RECORD_V2, TimeStamp));
+        assert_eq!(offset_of!(UsnRecordV2, file_name), offset_of!(ioctl::USN_RECORD_V2, FileName));
+        assert_eq!(offset_of!(UsnRecordV3, time_stamp), offset_of!(ioctl::USN_RECORD_V3, TimeStamp));
+        assert_eq!(offset_of!(UsnRecordV3, file_name), offset_of!(ioctl::USN_RECORD_V3, FileName));
+    }
+}
@@ -0,0 +1,167 @@
+use eframe::egui;
+use std::ffi::{OsStr, OsString};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+use windows::Win32::Storage::FileSystem::{
+    GetFileAttributesExW, GetFileExInfoStandard, FILE_ATTRIBUTE_REPARSE_POINT, WIN32_FILE_ATTRIBUTE_DATA,
+};
+use windows::core::HSTRING;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "bmp", "webp"];
+const TEXT_EXTENSIONS: &[&str] = &["txt", "md", "rs", "log", "toml", "json", "ini", "csv", "xml", "yaml", "yml"];
+// Longer text files show only their start
+const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+// Bytes checked for NULs to tell text from binary data
+const SNIFF_LEN: usize = 512;
+// Images are downscaled before upload so huge photos don't become huge textures
+const MAX_IMAGE_DIM: u32 = 1024;
+
+pub enum PreviewContent {
+    Image(egui::ColorImage),
+    // `truncated` when only the first `MAX_TEXT_BYTES` were read
+    Text { text: String, truncated: bool },
+    Binary,
+    Unsupported,
+    Error(String),
+}
+
+pub struct Preview {
+    pub id: u128,
+    pub attributes: Option<u32>,
+    // Where a symlink or junction points
+    pub link_target: Option<String>,
+    pub content: PreviewContent,
+}
+
+pub struct PreviewLoader {
+    tx: Sender<Preview>,
+    rx: Receiver<Preview>,
+    current: Option<CancellationToken>,
+}
+
+impl Default for PreviewLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PreviewLoader {
+    pub fn new() -> Self {
+        let (tx, rx) = channel();
+        Self { tx, rx, current: None }
+    }
+
+    /// Starts loading a preview on a background thread, cancelling any load still in
+    /// flight. `path` is ready for Win32 file APIs, see `FullPath::native`.
+    pub fn request(&mut self, id: u128, path: OsString, ctx: &egui::Context) {
+        if let Some(token) = self.current.take() {
+            token.cancel();
+        }
+        let token = CancellationToken::new();
+        self.current = Some(token.clone());
+
+        let tx = self.tx.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let attributes = file_attributes(&path);
+            let link_target = attributes
+                .filter(|a| a & FILE_ATTRIBUTE_REPARSE_POINT.0 != 0)
+                .and_then(|_| std::fs::read_link(&path).ok())
+                .map(|target| target.display().to_string());
+            let content = load_content(&path, &token);
+            if token.is_cancelled() {
+                return;
+            }
+            let _ = tx.send(Preview { id, attributes, link_target, content });
+            ctx.request_repaint();
+        });
+    }
+
+    pub fn cancel(&mut self) {
+        if let Some(token) = self.current.take() {
+            token.cancel();
+        }
+    }
+
+    /// Returns the most recently delivered preview, discarding any older ones.
+    pub fn poll(&self) -> Option<Preview> {
+        self.rx.try_iter().last()
+    }
+}
+
+fn extension(path: &OsStr) -> Option<String> {
+    std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+fn file_attributes(path: &OsStr) -> Option<u32> {
+    let mut data = WIN32_FILE_ATTRIBUTE_DATA::default();
+    unsafe {
+        GetFileAttributesExW(&HSTRING::from(path), GetFileExInfoStandard, &mut data as *mut _ as *mut _)
+            .ok()
+            .map(|_| data.dwFileAttributes)
+    }
+}
+
+fn load_content(path: &OsStr, token: &CancellationToken) -> PreviewContent {
+    let ext = extension(path).unwrap_or_default();
+
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        let img = match image::open(path) {
+            Ok(img) => img,
+            Err(e) => return PreviewContent::Error(format!("Failed to decode image: {}", e)),
+        };
+        if token.is_cancelled() {
+            return PreviewContent::Unsupported;
+        }
+        let img = img.thumbnail(MAX_IMAGE_DIM, MAX_IMAGE_DIM).to_rgba8();
+        let size = [img.width() as usize, img.height() as usize];
+        return PreviewContent::Image(egui::ColorImage::from_rgba_unmultiplied(size, img.as_raw()));
+    }
+
+    // Anything else may be text too, e.g. source files and configs; the contents decide
+    match load_text(path) {
+        Ok(content) => content,
+        Err(e) if TEXT_EXTENSIONS.contains(&ext.as_str()) => PreviewContent::Error(format!("Failed to read file: {}", e)),
+        Err(_) => PreviewContent::Unsupported,
+    }
+}
+
+/// The start of a text file, or `Binary` if its first bytes contain NULs.
+fn load_text(path: &OsStr) -> std::io::Result<PreviewContent> {
+    use std::io::Read;
+
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(MAX_TEXT_BYTES).read_to_end(&mut bytes)?;
+    if bytes[..bytes.len().min(SNIFF_LEN)].contains(&0) {
+        return Ok(PreviewContent::Binary);
+    }
+    Ok(PreviewContent::Text {
+        text: String::from_utf8_lossy(&bytes).into_owned(),
+        truncated: len > MAX_TEXT_BYTES,
+    })
+}
+
+pub fn format_attributes(attributes: u32) -> String {
+    use windows::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_ARCHIVE, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM,
+    };
+
+    let flags = [
+        (FILE_ATTRIBUTE_READONLY.0, "Read-only"),
+        (FILE_ATTRIBUTE_HIDDEN.0, "Hidden"),
+        (FILE_ATTRIBUTE_SYSTEM.0, "System"),
+        (FILE_ATTRIBUTE_ARCHIVE.0, "Archive"),
+        (FILE_ATTRIBUTE_REPARSE_POINT.0, "Link"),
+    ];
+    let names: Vec<&str> = flags
+        .iter()
+        .filter(|(flag, _)| attributes & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if names.is_empty() { "Normal".to_string() } else { names.join(", ") }
+}
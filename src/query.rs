@@ -0,0 +1,614 @@
+//! The search box syntax, modelled on Everything's:
+//!
+//! - words separated by spaces must all match the name (`report 2024`)
+//! - `"quoted phrases"` keep their spaces
+//! - `OR` or `|` between terms, `NOT` or a leading `!` to exclude, `AND` is optional
+//! - parentheses group terms
+//! - `*` and `?` make a term a wildcard pattern over the whole name
+//! - filters: `ext:txt;md`, `size:>1mb`, `size:10kb..2mb`, `dm:today`,
+//!   `dm:>=2024-01-01`, `dm:2024-01-01..2024-03-31`, `path:projects`
+
+use crate::config::wildcard_match;
+use crate::mft_indexer::FileRecord;
+use chrono::{Duration, NaiveDate, Utc};
+
+// 100ns intervals from the FILETIME epoch (1601) to the Unix epoch (1970)
+const FILETIME_UNIX_EPOCH: i64 = 116_444_736_000_000_000;
+const FILETIME_PER_SEC: i64 = 10_000_000;
+// Nested parentheses and NOTs deeper than this are rejected rather than risking the
+// stack on a pasted query
+const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    // Every child matches; empty matches everything
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+    Name(Pattern),
+    Path(Pattern),
+    // Lowercased, without the dot
+    Ext(Vec<String>),
+    Size(Bounds),
+    // FILETIME bounds
+    Modified(Bounds),
+}
+
+/// Text to find in a name or path: anywhere in it, or the whole of it for wildcards.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    // Lowercased unless the search is case sensitive
+    text: String,
+    wildcard: bool,
+}
+
+impl Pattern {
+    fn new(text: &str, case_sensitive: bool) -> Self {
+        Self {
+            text: if case_sensitive { text.to_string() } else { text.to_lowercase() },
+            wildcard: text.contains(['*', '?']),
+        }
+    }
+
+    fn matches(&self, value: &str, case_sensitive: bool) -> bool {
+        match (self.wildcard, case_sensitive) {
+            (false, true) => value.contains(self.text.as_str()),
+            (false, false) => contains_lowercase(value, &self.text),
+            (true, true) => wildcard_match(&self.text, value),
+            (true, false) => wildcard_match(&self.text, &value.to_lowercase()),
+        }
+    }
+}
+
+/// Whether `value` contains `lower`, which is already lowercased, ignoring case. ASCII
+/// values, which most names are, are compared in place instead of lowercasing a copy
+/// for every record.
+fn contains_lowercase(value: &str, lower: &str) -> bool {
+    if !value.is_ascii() {
+        return value.to_lowercase().contains(lower);
+    }
+    let (value, lower) = (value.as_bytes(), lower.as_bytes());
+    lower.is_empty() || value.windows(lower.len()).any(|window| window.eq_ignore_ascii_case(lower))
+}
+
+// Turns the range one value covers into the bounds a comparison operator means
+type Comparison = fn((i64, i64)) -> Bounds;
+
+/// A half-open range, `min <= value < max`, either end optional.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bounds {
+    min: Option<i64>,
+    max: Option<i64>,
+}
+
+impl Bounds {
+    fn contains(&self, value: i64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value < max)
+    }
+
+    /// Bounds for `spec`, where `parse` turns one value into the range it covers (a
+    /// whole day for dates, a single byte count for sizes).
+    fn parse(spec: &str, parse: impl Fn(&str) -> Option<(i64, i64)>) -> Option<Self> {
+        if let Some((from, to)) = spec.split_once("..") {
+            let min = if from.is_empty() { None } else { Some(parse(from)?.0) };
+            let max = if to.is_empty() { None } else { Some(parse(to)?.1) };
+            return Some(Self { min, max });
+        }
+        let ops: [(&str, Comparison); 5] = [
+            (">=", |(start, _)| Bounds { min: Some(start), max: None }),
+            ("<=", |(_, end)| Bounds { min: None, max: Some(end) }),
+            (">", |(_, end)| Bounds { min: Some(end), max: None }),
+            ("<", |(start, _)| Bounds { min: None, max: Some(start) }),
+            ("=", |(start, end)| Bounds { min: Some(start), max: Some(end) }),
+        ];
+        for (op, bounds) in ops {
+            if let Some(value) = spec.strip_prefix(op) {
+                return parse(value).map(bounds);
+            }
+        }
+        parse(spec).map(|(start, end)| Bounds { min: Some(start), max: Some(end) })
+    }
+}
+
+/// `10`, `10kb`, `1.5mb`, `2gb`; units are powers of 1024.
+fn parse_size(text: &str) -> Option<(i64, i64)> {
+    let text = text.to_ascii_lowercase();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier: f64 = match unit {
+        "" | "b" => 1.0,
+        "kb" | "k" => 1024.0,
+        "mb" | "m" => 1024.0 * 1024.0,
+        "gb" | "g" => 1024.0 * 1024.0 * 1024.0,
+        "tb" | "t" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    let bytes = (number.parse::<f64>().ok()? * multiplier) as i64;
+    Some((bytes, bytes + 1))
+}
+
+/// `today`, `yesterday` or `YYYY-MM-DD`, as the FILETIME range of that UTC day (the
+/// Date Modified column shows UTC as well).
+fn parse_date(text: &str) -> Option<(i64, i64)> {
+    let today = Utc::now().date_naive();
+    let day = match text.to_ascii_lowercase().as_str() {
+        "today" => today,
+        "yesterday" => today - Duration::days(1),
+        other => NaiveDate::parse_from_str(other, "%Y-%m-%d").ok()?,
+    };
+    let start = day.and_hms_opt(0, 0, 0)?.and_utc().timestamp();
+    let to_filetime = |secs: i64| FILETIME_UNIX_EPOCH + secs * FILETIME_PER_SEC;
+    Some((to_filetime(start), to_filetime(start + 24 * 60 * 60)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | '|' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    _ => Token::Or,
+                });
+            }
+            '!' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            '"' => {
+                chars.next();
+                // An unclosed quote runs to the end, so a phrase still being typed works
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                tokens.push(Token::Phrase(phrase));
+            }
+            _ => {
+                // Quotes inside a word only group, as in `path:"program files"`
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '|') {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        word.extend(chars.by_ref().take_while(|&c| c != '"'));
+                    } else {
+                        word.push(c);
+                    }
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    case_sensitive: bool,
+    // Parentheses and NOTs currently open
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or_expr(&mut self) -> Result<Expr, String> {
+        let mut terms = vec![self.and_expr()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            terms.push(self.and_expr()?);
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Expr::Or(terms) })
+    }
+
+    fn and_expr(&mut self) -> Result<Expr, String> {
+        let mut terms = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => self.pos += 1,
+                Some(_) => terms.push(self.unary()?),
+            }
+        }
+        Ok(if terms.len() == 1 { terms.pop().unwrap() } else { Expr::And(terms) })
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        match token {
+            Token::Not => match self.peek() {
+                Some(Token::And | Token::Or | Token::Close) | None => Err("NOT needs something to exclude".to_string()),
+                _ => self.nested(|parser| Ok(Expr::Not(Box::new(parser.unary()?)))),
+            },
+            Token::Open => self.nested(|parser| {
+                let inner = parser.or_expr()?;
+                // A missing `)` at the end is forgiven while typing
+                if parser.peek() == Some(&Token::Close) {
+                    parser.pos += 1;
+                }
+                Ok(inner)
+            }),
+            Token::Close => Err("Unmatched )".to_string()),
+            Token::Phrase(phrase) => Ok(Expr::Term(Term::Name(Pattern::new(&phrase, self.case_sensitive)))),
+            Token::Word(word) => self.term(&word).map(Expr::Term),
+            Token::And => Err("AND needs something before it".to_string()),
+            Token::Or => Err("OR needs something before it".to_string()),
+        }
+    }
+
+    /// Runs `parse` one level deeper, failing once `MAX_NESTING` is reached.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, String>) -> Result<Expr, String> {
+        if self.depth >= MAX_NESTING {
+            return Err(format!("Query is nested more than {} levels deep", MAX_NESTING));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn term(&self, word: &str) -> Result<Term, String> {
+        let Some((key, value)) = word.split_once(':') else {
+            return Ok(Term::Name(Pattern::new(word, self.case_sensitive)));
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "ext" => Ok(Term::Ext(
+                value.split(';')
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .filter(|ext| !ext.is_empty())
+                    .collect(),
+            )),
+            "size" => Bounds::parse(value, parse_size)
+                .map(Term::Size)
+                .ok_or_else(|| format!("Invalid size {:?}", value)),
+            "dm" => Bounds::parse(value, parse_date)
+                .map(Term::Modified)
+                .ok_or_else(|| format!("Invalid date {:?}", value)),
+            "path" => Ok(Term::Path(Pattern::new(value, self.case_sensitive))),
+            // Not a filter, e.g. a name with a colon in it
+            _ => Ok(Term::Name(Pattern::new(word, self.case_sensitive))),
+        }
+    }
+}
+
+/// Parses the search box syntax described at the top of this module.
+pub fn parse(text: &str, case_sensitive: bool) -> Result<Expr, String> {
+    let tokens = tokenize(text);
+    let mut parser = Parser { tokens: &tokens, pos: 0, case_sensitive, depth: 0 };
+    let expr = parser.or_expr()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(_) => Err("Unmatched )".to_string()),
+    }
+}
+
+impl Expr {
+    /// Whether `record` matches. `path` is the record's full path if it has been
+    /// resolved; without it, path filters can't be decided and the result is None.
+    pub fn eval(&self, record: &FileRecord, path: Option<&str>, case_sensitive: bool) -> Option<bool> {
+        match self {
+            Expr::And(terms) => {
+                let mut result = Some(true);
+                for term in terms {
+                    match term.eval(record, path, case_sensitive) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => {}
+                    }
+                }
+                result
+            }
+            Expr::Or(terms) => {
+                let mut result = Some(false);
+                for term in terms {
+                    match term.eval(record, path, case_sensitive) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => {}
+                    }
+                }
+                result
+            }
+            Expr::Not(inner) => inner.eval(record, path, case_sensitive).map(|matched| !matched),
+            Expr::Term(term) => term.eval(record, path, case_sensitive),
+        }
+    }
+
+    /// Whether evaluating needs the full path, which is costly to build.
+    pub fn needs_path(&self) -> bool {
+        match self {
+            Expr::And(terms) | Expr::Or(terms) => terms.iter().any(Expr::needs_path),
+            Expr::Not(inner) => inner.needs_path(),
+            Expr::Term(term) => matches!(term, Term::Path(_)),
+        }
+    }
+
+    /// Whether this is just name terms that must all match, so that a query extending
+    /// it can only match a subset of its results.
+    pub fn is_plain(&self) -> bool {
+        match self {
+            Expr::And(terms) => terms.iter().all(Expr::is_plain),
+            Expr::Term(Term::Name(pattern)) => !pattern.wildcard,
+            _ => false,
+        }
+    }
+
+    /// Lowercased text that every match's name must contain, for narrowing the search
+    /// with the trigram index. Picks the longest such literal.
+    pub fn required_text(&self) -> Option<String> {
+        match self {
+            Expr::And(terms) => terms.iter()
+                .filter_map(Expr::required_text)
+                .max_by_key(|text| text.len()),
+            Expr::Term(Term::Name(pattern)) => {
+                let text = if pattern.wildcard {
+                    pattern.text.split(['*', '?']).max_by_key(|part| part.len()).unwrap_or_default()
+                } else {
+                    pattern.text.as_str()
+                };
+                (!text.is_empty()).then(|| text.to_lowercase())
+            }
+            _ => None,
+        }
+    }
+
+    /// Literal text of the name terms that can show up in a match, for highlighting.
+    /// Wildcards are left out since they match the whole name, as are excluded terms.
+    pub fn name_texts(&self) -> Vec<&str> {
+        match self {
+            Expr::And(terms) | Expr::Or(terms) => terms.iter().flat_map(Expr::name_texts).collect(),
+            Expr::Term(Term::Name(pattern)) if !pattern.wildcard => vec![pattern.text.as_str()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Term {
+    fn eval(&self, record: &FileRecord, path: Option<&str>, case_sensitive: bool) -> Option<bool> {
+        let mut names = record.names();
+        Some(match self {
+            Term::Name(pattern) => names.any(|name| pattern.matches(name, case_sensitive)),
+            Term::Path(pattern) => pattern.matches(path?, case_sensitive),
+            Term::Ext(extensions) => names.any(|name| {
+                name.rsplit_once('.')
+                    .is_some_and(|(_, ext)| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            }),
+            Term::Size(bounds) => !record.is_dir && bounds.contains(record.size as i64),
+            Term::Modified(bounds) => bounds.contains(record.modified),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_without_operand_is_an_error() {
+        for text in ["NOT", "!", "NOT AND x", "!AND", "! AND x", "NOT OR x", "!|x", "(NOT)", "x !"] {
+            assert!(parse(text, false).is_err(), "{:?} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn leading_operators() {
+        assert!(parse("AND x", false).is_ok());
+        assert!(parse("x AND y", false).is_ok());
+        assert!(parse("x | !y", false).is_ok());
+        assert!(parse("!(x y)", false).is_ok());
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected_without_overflowing() {
+        let depth = 100_000;
+        let parens = format!("{}x{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&parens, false).is_err());
+        let nots = format!("{}x", "!".repeat(depth));
+        assert!(parse(&nots, false).is_err());
+        let mixed = format!("{}x", "!(".repeat(depth));
+        assert!(parse(&mixed, false).is_err());
+    }
+
+    #[test]
+    fn nesting_up_to_the_limit_parses() {
+        let parens = format!("{}x{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING));
+        assert!(parse(&parens, false).is_ok());
+        let nots = format!("{}x", "!".repeat(MAX_NESTING));
+        assert!(parse(&nots, false).is_ok());
+        let too_deep = format!("{}x{}", "(".repeat(MAX_NESTING + 1), ")".repeat(MAX_NESTING + 1));
+        assert!(parse(&too_deep, false).is_err());
+    }
+
+    #[test]
+    fn unmatched_close_is_an_error() {
+        assert!(parse("x)", false).is_err());
+        assert!(parse(")", false).is_err());
+        // A missing `)` is forgiven while typing
+        assert!(parse("(x", false).is_ok());
+    }
+
+    fn record(name: &str, size: u64, modified: i64, is_dir: bool) -> FileRecord {
+        FileRecord {
+            id: 1,
+            parent_id: 0,
+            sequence: 1,
+            name: name.to_string(),
+            wide_name: None,
+            size,
+            modified,
+            created: 0,
+            accessed: 0,
+            is_dir,
+            is_reparse_point: false,
+            attributes: 0,
+            hash: None,
+            links: Vec::new(),
+        }
+    }
+
+    fn file(name: &str) -> FileRecord {
+        record(name, 0, 0, false)
+    }
+
+    fn matches(query: &str, record: &FileRecord) -> bool {
+        parse(query, false).unwrap().eval(record, None, false).unwrap()
+    }
+
+    // FILETIMEs of 2024-01-01 00:00 UTC and a day
+    const NEW_YEAR: i64 = 133_485_408_000_000_000;
+    const DAY: i64 = 24 * 60 * 60 * FILETIME_PER_SEC;
+
+    #[test]
+    fn words_must_all_match_the_name_in_any_case() {
+        assert!(matches("report", &file("Report.txt")));
+        assert!(matches("REPORT 2024", &file("annual-report-2024.pdf")));
+        assert!(!matches("report 2023", &file("annual-report-2024.pdf")));
+        assert!(matches("été", &file("Photos ÉTÉ.jpg")));
+        assert!(matches("", &file("anything")));
+    }
+
+    #[test]
+    fn case_sensitive_terms_match_exactly() {
+        let expr = parse("Report", true).unwrap();
+        assert_eq!(expr.eval(&file("Report.txt"), None, true), Some(true));
+        assert_eq!(expr.eval(&file("report.txt"), None, true), Some(false));
+        let expr = parse("R*.txt", true).unwrap();
+        assert_eq!(expr.eval(&file("Report.txt"), None, true), Some(true));
+        assert_eq!(expr.eval(&file("report.txt"), None, true), Some(false));
+    }
+
+    #[test]
+    fn wildcards_match_the_whole_name() {
+        assert!(matches("*.TXT", &file("notes.txt")));
+        assert!(matches("r?port*", &file("Report-old.txt")));
+        assert!(!matches("*.txt", &file("notes.txt.bak")));
+    }
+
+    #[test]
+    fn quoted_phrases_keep_their_spaces() {
+        assert!(matches("\"annual report\"", &file("Annual Report 2024.pdf")));
+        assert!(!matches("\"annual report\"", &file("annual-report.pdf")));
+        assert!(!matches("\"annual report\"", &file("report annual.pdf")));
+    }
+
+    #[test]
+    fn extensions_match_any_listed_without_case() {
+        assert!(matches("ext:txt;md", &file("Notes.MD")));
+        assert!(matches("ext:.txt", &file("a.txt")));
+        assert!(!matches("ext:txt;md", &file("a.rs")));
+        assert!(!matches("ext:txt", &file("txt")));
+        assert!(!matches("ext:txt", &file("a.txt.bak")));
+    }
+
+    #[test]
+    fn sizes_compare_with_units() {
+        let mb = 1024 * 1024;
+        assert!(matches("size:>1mb", &record("big", 2 * mb, 0, false)));
+        assert!(!matches("size:>1mb", &record("exact", mb, 0, false)));
+        assert!(matches("size:>=1mb", &record("exact", mb, 0, false)));
+        assert!(matches("size:<1kb", &record("small", 1023, 0, false)));
+        assert!(!matches("size:<1kb", &record("kb", 1024, 0, false)));
+        assert!(matches("size:<=1kb", &record("kb", 1024, 0, false)));
+        assert!(matches("size:1.5kb", &record("odd", 1536, 0, false)));
+        assert!(matches("size:10kb..2mb", &record("mid", 10 * 1024, 0, false)));
+        assert!(matches("size:10kb..2mb", &record("mid", 2 * mb, 0, false)));
+        assert!(!matches("size:10kb..2mb", &record("mid", 2 * mb + 1, 0, false)));
+        assert!(matches("size:..1gb", &record("any", 5, 0, false)));
+        // Folders have no size of their own
+        assert!(!matches("size:<1kb", &record("folder", 0, 0, true)));
+        assert!(parse("size:>1xb", false).is_err());
+    }
+
+    #[test]
+    fn modified_dates_cover_whole_utc_days() {
+        let at = |modified| record("a.txt", 0, modified, false);
+        assert!(matches("dm:2024-01-01", &at(NEW_YEAR)));
+        assert!(matches("dm:2024-01-01", &at(NEW_YEAR + DAY - 1)));
+        assert!(!matches("dm:2024-01-01", &at(NEW_YEAR + DAY)));
+        assert!(!matches("dm:2024-01-01", &at(NEW_YEAR - 1)));
+        assert!(matches("dm:>=2024-01-01", &at(NEW_YEAR)));
+        assert!(!matches("dm:>2024-01-01", &at(NEW_YEAR + DAY - 1)));
+        assert!(matches("dm:>2024-01-01", &at(NEW_YEAR + DAY)));
+        assert!(matches("dm:<2024-01-01", &at(NEW_YEAR - 1)));
+        // The last day of a range is included
+        assert!(matches("dm:2024-01-01..2024-03-31", &at(133_563_996_000_000_000)));
+        assert!(!matches("dm:2024-01-01..2024-03-31", &at(133_564_032_000_000_000)));
+        assert!(parse("dm:2024-13-01", false).is_err());
+    }
+
+    #[test]
+    fn today_is_the_current_utc_day() {
+        let now = FILETIME_UNIX_EPOCH + Utc::now().timestamp() * FILETIME_PER_SEC;
+        assert!(matches("dm:today", &record("a.txt", 0, now, false)));
+        assert!(!matches("dm:today", &record("a.txt", 0, now - DAY, false)));
+        assert!(matches("dm:yesterday", &record("a.txt", 0, now - DAY, false)));
+    }
+
+    #[test]
+    fn paths_need_the_full_path() {
+        let expr = parse("path:projects report", false).unwrap();
+        assert!(expr.needs_path());
+        assert_eq!(expr.eval(&file("report.txt"), None, false), None);
+        assert_eq!(expr.eval(&file("report.txt"), Some("C:\\Projects\\report.txt"), false), Some(true));
+        assert_eq!(expr.eval(&file("report.txt"), Some("C:\\Docs\\report.txt"), false), Some(false));
+        // A name that already fails decides the result without the path
+        assert_eq!(expr.eval(&file("notes.txt"), None, false), Some(false));
+        let quoted = parse("path:\"program files\"", false).unwrap();
+        assert_eq!(quoted.eval(&file("a.exe"), Some("C:\\Program Files\\a.exe"), false), Some(true));
+    }
+
+    #[test]
+    fn or_not_and_grouping() {
+        assert!(matches("report OR invoice", &file("invoice.pdf")));
+        assert!(matches("report | invoice", &file("report.pdf")));
+        assert!(!matches("report | invoice", &file("notes.pdf")));
+        assert!(!matches("report NOT draft", &file("report-draft.txt")));
+        assert!(matches("report !draft", &file("report-final.txt")));
+        assert!(matches("(report | invoice) ext:pdf", &file("invoice.pdf")));
+        assert!(!matches("(report | invoice) ext:pdf", &file("invoice.txt")));
+        // Without parentheses OR binds looser than the implicit AND
+        assert!(matches("report | invoice ext:pdf", &file("report.txt")));
+        assert!(!matches("!(report | invoice)", &file("report.txt")));
+        assert!(matches("!(report | invoice)", &file("notes.txt")));
+    }
+
+    #[test]
+    fn other_hard_link_names_match_too() {
+        let mut record = file("a.txt");
+        record.links.push(crate::mft_indexer::HardLink { parent_id: 0, name: "budget.xlsx".to_string() });
+        assert!(matches("budget", &record));
+        assert!(matches("ext:xlsx", &record));
+        assert!(!matches("!budget", &record));
+    }
+}
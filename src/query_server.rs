@@ -0,0 +1,144 @@
+use crate::config::Config;
+use crate::mft_indexer::{Indexer, SearchQuery};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, warn};
+
+pub const PIPE_NAME: &str = r"\\.\pipe\rivet-query";
+
+/// One line of input, e.g. `{"query":"cargo.toml","limit":100}`.
+#[derive(Deserialize)]
+struct QueryRequest {
+    query: String,
+    // Defaults to the configured result limit, which also caps it
+    limit: Option<usize>,
+    #[serde(default)]
+    case_sensitive: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct QueryResult {
+    name: String,
+    path: String,
+    size: u64,
+    // FILETIME, 100ns intervals since 1601
+    modified: i64,
+    is_dir: bool,
+}
+
+#[derive(Serialize)]
+pub(crate) struct QueryError {
+    pub error: String,
+}
+
+/// Serves queries on `PIPE_NAME` until `token` is cancelled.
+pub async fn serve(indexer: Arc<Indexer>, config: Arc<RwLock<Config>>, token: CancellationToken) {
+    serve_pipe(PIPE_NAME, indexer, config, token, handle_client).await;
+}
+
+/// Accepts clients on `name` and runs `handle` for each on a task of its own.
+pub(crate) async fn serve_pipe<F, Fut>(
+    name: &'static str,
+    indexer: Arc<Indexer>,
+    config: Arc<RwLock<Config>>,
+    token: CancellationToken,
+    handle: F,
+) where
+    F: Fn(NamedPipeServer, Arc<Indexer>, Arc<RwLock<Config>>) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    // first_pipe_instance fails if another process already owns the name
+    let mut server = match ServerOptions::new().first_pipe_instance(true).create(name) {
+        Ok(server) => server,
+        Err(e) => {
+            error!(pipe = name, error = %e, "Failed to create query pipe");
+            indexer.log_error(format!("Failed to create query pipe {}: {}", name, e));
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            _ = token.cancelled() => return,
+            connected = server.connect() => {
+                if let Err(e) = connected {
+                    warn!(pipe = name, error = %e, "Query pipe connection failed");
+                    continue;
+                }
+            }
+        }
+
+        // Open the next instance before handing this one off so clients never see
+        // the pipe missing
+        let client = server;
+        server = match ServerOptions::new().create(name) {
+            Ok(server) => server,
+            Err(e) => {
+                error!(pipe = name, error = %e, "Failed to create query pipe");
+                indexer.log_error(format!("Failed to create query pipe {}: {}", name, e));
+                return;
+            }
+        };
+        tokio::spawn(handle(client, Arc::clone(&indexer), Arc::clone(&config)));
+    }
+}
+
+/// Line-delimited JSON: each request line gets one response line, a JSON array of
+/// results or `{"error": ...}`.
+async fn handle_client(client: NamedPipeServer, indexer: Arc<Indexer>, config: Arc<RwLock<Config>>) {
+    let (reader, mut writer) = tokio::io::split(client);
+    let mut lines = BufReader::new(reader).lines();
+
+    // A read error or EOF means the client went away
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<QueryRequest>(&line) {
+            Ok(request) => {
+                let indexer = Arc::clone(&indexer);
+                let config = Arc::clone(&config);
+                // Scanning the index is CPU-bound, keep it off the async workers
+                match tokio::task::spawn_blocking(move || run_query(&indexer, &config, &request.query, request.limit, request.case_sensitive)).await {
+                    Ok(results) => serde_json::to_string(&results),
+                    Err(e) => serde_json::to_string(&QueryError { error: e.to_string() }),
+                }
+            }
+            Err(e) => serde_json::to_string(&QueryError { error: format!("Malformed request: {}", e) }),
+        };
+        let Ok(mut response) = response else {
+            continue;
+        };
+        response.push('\n');
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+    debug!("Query pipe client disconnected");
+}
+
+/// Searches for `query` the way the search box does. `limit` defaults to, and is capped
+/// at, the configured result limit.
+pub(crate) fn run_query(
+    indexer: &Indexer,
+    config: &RwLock<Config>,
+    query: &str,
+    limit: Option<usize>,
+    case_sensitive: bool,
+) -> Vec<QueryResult> {
+    let config = config.read().unwrap().clone();
+    let limit = limit.unwrap_or(config.result_limit).min(config.result_limit);
+    let found = indexer.search(&SearchQuery::from_config(query, case_sensitive, &config), limit);
+
+    found.ids.into_iter()
+        .filter_map(|id| {
+            let (name, size, modified, is_dir) = indexer.records.get(&id)
+                .map(|r| (r.name.clone(), r.size, r.modified, r.is_dir))?;
+            Some(QueryResult { name, path: indexer.get_full_path(id).path, size, modified, is_dir })
+        })
+        .collect()
+}
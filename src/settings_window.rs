@@ -0,0 +1,212 @@
+use crate::config::{Config, MIN_MONITOR_POLL_MS};
+use crate::gui::format_size;
+use crate::hotkey::Hotkey;
+use crate::volume_manager::{disk_info, indexable_volumes, DiskInfo, VolumeId, VolumeInfo};
+use chrono::format::{Item, StrftimeItems};
+use eframe::egui;
+
+pub enum SettingsAction {
+    None,
+    Save(Config),
+    Close,
+    // Throw away the volume's records and index it again; the window stays open
+    Rebuild(VolumeId),
+}
+
+/// Editable copy of the config shown in the Settings window; nothing is applied until Save.
+pub struct SettingsWindow {
+    draft: Config,
+    excluded_text: String,
+    // Mounted volumes with their label and capacity, to tell drives apart
+    available_volumes: Vec<(VolumeInfo, Option<DiskInfo>)>,
+    // Configured volumes that aren't mounted, kept listed so they can be disabled
+    unplugged_volumes: Vec<String>,
+    // Volumes being indexed, which can be rebuilt
+    indexed: Vec<VolumeId>,
+}
+
+impl SettingsWindow {
+    /// `indexed` are the volumes being indexed right now, which includes drives plugged in
+    /// while running that aren't in the config yet.
+    pub fn new(config: &Config, indexed: &[VolumeInfo]) -> Self {
+        let mut draft = config.clone();
+        // Listed as checked, and saved that way, so unchecking them is what turns them off
+        for volume in indexed {
+            if !draft.volumes.iter().any(|key| volume.matches(key)) {
+                draft.volumes.push(volume.config_key());
+            }
+        }
+        draft.volumes.sort_unstable();
+
+        let available_volumes: Vec<(VolumeInfo, Option<DiskInfo>)> = indexable_volumes().into_iter()
+            .map(|volume| {
+                let info = disk_info(&volume);
+                (volume, info)
+            })
+            .collect();
+        let unplugged_volumes = config.volumes.iter()
+            .filter(|key| !available_volumes.iter().any(|(volume, _)| volume.matches(key)))
+            .cloned()
+            .collect();
+
+        Self {
+            draft,
+            excluded_text: config.excluded.join("\n"),
+            available_volumes,
+            unplugged_volumes,
+            indexed: indexed.iter().map(|volume| volume.id).collect(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) -> SettingsAction {
+        let mut open = true;
+        let mut action = SettingsAction::None;
+
+        egui::Window::new("⚙ Settings")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(380.0)
+            .show(ctx, |ui| {
+                ui.heading("Volumes");
+                egui::Grid::new("settings_volumes").num_columns(4).show(ui, |ui| {
+                    for (volume, info) in &self.available_volumes {
+                        let mut enabled = self.draft.volumes.iter().any(|key| volume.matches(key));
+                        let checkbox = ui.checkbox(&mut enabled, volume.to_string());
+                        let checkbox = match &volume.guid_path {
+                            Some(guid_path) => checkbox.on_hover_text(guid_path),
+                            None => checkbox,
+                        };
+                        if checkbox.changed() {
+                            set_volume_enabled(&mut self.draft, volume, enabled);
+                        }
+                        match info {
+                            Some(info) => {
+                                ui.label(&info.label);
+                                ui.label(egui::RichText::new(format!("{} {}", format_size(info.total_bytes), info.file_system)).weak());
+                            }
+                            None => {
+                                ui.label("");
+                                ui.label("");
+                            }
+                        }
+                        if self.indexed.contains(&volume.id) {
+                            if ui.small_button("⟳").on_hover_text("Rebuild this volume's index").clicked() {
+                                action = SettingsAction::Rebuild(volume.id);
+                            }
+                        } else {
+                            ui.label("");
+                        }
+                        ui.end_row();
+                    }
+                    for key in &self.unplugged_volumes {
+                        let mut enabled = self.draft.volumes.contains(key);
+                        if ui.checkbox(&mut enabled, key.as_str()).changed() {
+                            if enabled {
+                                self.draft.volumes.push(key.clone());
+                                self.draft.volumes.sort_unstable();
+                            } else {
+                                self.draft.volumes.retain(|k| k != key);
+                            }
+                        }
+                        ui.label(egui::RichText::new("Not mounted").weak());
+                        ui.label("");
+                        ui.label("");
+                        ui.end_row();
+                    }
+                });
+                ui.checkbox(&mut self.draft.index_new_volumes, "Index drives plugged in while running");
+                ui.horizontal(|ui| {
+                    ui.label("Check for file changes every");
+                    ui.add(egui::DragValue::new(&mut self.draft.monitor_poll_ms).range(MIN_MONITOR_POLL_MS..=10_000).speed(10).suffix(" ms"));
+                }).response.on_hover_text("Lower shows changes sooner, higher saves power. Applies to drives indexed from now on.");
+
+                ui.separator();
+                ui.heading("Exclusions");
+                ui.label(egui::RichText::new("One folder (C:\\Windows) or name pattern (*.tmp) per line").weak());
+                ui.add(egui::TextEdit::multiline(&mut self.excluded_text).desired_rows(4).desired_width(f32::INFINITY));
+
+                ui.separator();
+                ui.heading("Results");
+                let date_format_valid = StrftimeItems::new(&self.draft.date_format).all(|item| !matches!(item, Item::Error));
+                egui::Grid::new("settings_results").num_columns(2).show(ui, |ui| {
+                    ui.label("Result limit");
+                    ui.add(egui::DragValue::new(&mut self.draft.result_limit).range(100..=1_000_000).speed(100));
+                    ui.end_row();
+                    ui.label("Date format");
+                    ui.text_edit_singleline(&mut self.draft.date_format);
+                    ui.end_row();
+                    ui.label("Content search size limit");
+                    ui.add(egui::DragValue::new(&mut self.draft.content_search_max_kb).range(1..=1_048_576).speed(64).suffix(" KB"));
+                    ui.end_row();
+                });
+                if !date_format_valid {
+                    ui.colored_label(ui.visuals().error_fg_color, "Invalid date format");
+                }
+                ui.checkbox(&mut self.draft.show_hidden, "Show hidden and system files");
+                ui.checkbox(&mut self.draft.natural_sort, "Sort numbers in names by value (file2 before file10)");
+
+                ui.separator();
+                ui.heading("Behavior");
+                ui.checkbox(&mut self.draft.fetch_sizes_eagerly, "Fetch file sizes right after indexing");
+                ui.checkbox(&mut self.draft.low_priority_indexing, "Index at low priority")
+                    .on_hover_text("Let other programs' disk access go first; indexing takes longer");
+                ui.checkbox(&mut self.draft.start_with_windows, "Start with Windows");
+                ui.checkbox(&mut self.draft.close_to_tray, "Close to tray")
+                    .on_hover_text("Keep running in the tray when the window is closed; exit from the tray menu");
+                let hotkey_valid = self.draft.hotkey.is_empty() || Hotkey::parse(&self.draft.hotkey).is_some();
+                ui.horizontal(|ui| {
+                    ui.label("Global hotkey");
+                    ui.add(egui::TextEdit::singleline(&mut self.draft.hotkey).hint_text("e.g. Ctrl+Shift+F"));
+                });
+                if !hotkey_valid {
+                    ui.colored_label(ui.visuals().error_fg_color, "Unrecognized hotkey");
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.draft.http_api, "Serve search over HTTP on 127.0.0.1, port");
+                    ui.add_enabled(self.draft.http_api, egui::DragValue::new(&mut self.draft.http_port).range(1024..=65535));
+                });
+                ui.label(egui::RichText::new("Takes effect after restarting Rivet").weak());
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(date_format_valid && hotkey_valid, egui::Button::new("Save")).clicked() {
+                        action = SettingsAction::Save(self.config());
+                    }
+                    if ui.button("Cancel").clicked() {
+                        action = SettingsAction::Close;
+                    }
+                });
+            });
+
+        if !open {
+            action = SettingsAction::Close;
+        }
+        action
+    }
+
+    fn config(&self) -> Config {
+        let mut config = self.draft.clone();
+        config.excluded = self.excluded_text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        config
+    }
+}
+
+/// Checks or unchecks `volume`. Unchecked volumes are also remembered as ignored, so
+/// plugging them in again doesn't index them anyway.
+fn set_volume_enabled(config: &mut Config, volume: &VolumeInfo, enabled: bool) {
+    if enabled {
+        config.volumes.push(volume.config_key());
+        config.volumes.sort_unstable();
+        config.ignored_volumes.retain(|key| !volume.matches(key));
+    } else {
+        config.volumes.retain(|key| !volume.matches(key));
+        if !config.ignored_volumes.iter().any(|key| volume.matches(key)) {
+            config.ignored_volumes.push(volume.config_key());
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use eframe::egui;
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use windows::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HANDLE, WAIT_OBJECT_0};
+use windows::Win32::System::Threading::{CreateEventW, CreateMutexW, SetEvent, WaitForSingleObject};
+use windows::core::w;
+
+// Object names are per session, so each logged-on user gets their own instance
+const MUTEX_NAME: windows::core::PCWSTR = w!("Local\\Rivet-7C1E4B52-9A3D-4F0B-B6E1-2D8F5A90C3E7");
+// Set by a second instance asking the running one to come to the front
+const ACTIVATE_EVENT_NAME: windows::core::PCWSTR = w!("Local\\Rivet-7C1E4B52-9A3D-4F0B-B6E1-2D8F5A90C3E7-activate");
+// Set by the running instance once its UI thread has handled the request
+const ACK_EVENT_NAME: windows::core::PCWSTR = w!("Local\\Rivet-7C1E4B52-9A3D-4F0B-B6E1-2D8F5A90C3E7-ack");
+
+/// How long a second instance waits for the running one before assuming it is hung.
+const ACTIVATE_TIMEOUT_MS: u32 = 5_000;
+const LISTEN_POLL_MS: u32 = 250;
+
+/// The named objects that mark this process as the running instance.
+pub struct SingleInstance {
+    mutex: HANDLE,
+    activate: HANDLE,
+    ack: HANDLE,
+}
+
+impl SingleInstance {
+    /// Claims the single-instance mutex. If another instance already holds it, asks that
+    /// one to show its window and returns `None` once it acknowledges. A running instance
+    /// that doesn't answer within the timeout is treated as hung and this one proceeds.
+    pub fn acquire() -> anyhow::Result<Option<Self>> {
+        unsafe {
+            let mutex = CreateMutexW(None, false, MUTEX_NAME)?;
+            let already_running = GetLastError() == ERROR_ALREADY_EXISTS;
+            let instance = Self {
+                mutex,
+                activate: CreateEventW(None, false, false, ACTIVATE_EVENT_NAME)?,
+                ack: CreateEventW(None, false, false, ACK_EVENT_NAME)?,
+            };
+            if !already_running {
+                return Ok(Some(instance));
+            }
+
+            SetEvent(instance.activate)?;
+            if WaitForSingleObject(instance.ack, ACTIVATE_TIMEOUT_MS) == WAIT_OBJECT_0 {
+                return Ok(None);
+            }
+            tracing::warn!("Running instance did not respond; starting anyway");
+            Ok(Some(instance))
+        }
+    }
+
+    /// Starts a thread that waits for activation requests from later instances and calls
+    /// `on_activate` for each. Requests are only acknowledged once `ActivationListener::
+    /// activated` sees them on the UI thread, so a hung UI doesn't block a new instance.
+    pub fn listen(self, ctx: &egui::Context, on_activate: impl Fn() + Send + 'static) -> ActivationListener {
+        let (tx, rx) = channel();
+        let token = CancellationToken::new();
+        let thread_token = token.clone();
+        let ctx = ctx.clone();
+        // Handles aren't Send, so hand the thread the raw value
+        let activate = self.activate.0 as isize;
+
+        let thread = std::thread::spawn(move || {
+            let activate = HANDLE(activate as *mut std::ffi::c_void);
+            while !thread_token.is_cancelled() {
+                if unsafe { WaitForSingleObject(activate, LISTEN_POLL_MS) } == WAIT_OBJECT_0 {
+                    on_activate();
+                    let _ = tx.send(());
+                    ctx.request_repaint();
+                }
+            }
+        });
+
+        ActivationListener {
+            instance: self,
+            token,
+            thread: Some(thread),
+            rx,
+        }
+    }
+}
+
+impl Drop for SingleInstance {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CloseHandle(self.ack);
+            let _ = CloseHandle(self.activate);
+            let _ = CloseHandle(self.mutex);
+        }
+    }
+}
+
+/// Keeps the instance claimed and relays activation requests to the UI.
+pub struct ActivationListener {
+    instance: SingleInstance,
+    token: CancellationToken,
+    thread: Option<JoinHandle<()>>,
+    rx: Receiver<()>,
+}
+
+impl ActivationListener {
+    /// Whether another instance asked to be brought forward since the last call.
+    /// Acknowledges the request so that instance can exit.
+    pub fn activated(&self) -> bool {
+        let activated = self.rx.try_iter().count() > 0;
+        if activated {
+            unsafe {
+                let _ = SetEvent(self.instance.ack);
+            }
+        }
+        activated
+    }
+}
+
+impl Drop for ActivationListener {
+    fn drop(&mut self) {
+        self.token.cancel();
+        // The thread notices within LISTEN_POLL_MS
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
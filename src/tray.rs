@@ -0,0 +1,129 @@
+use eframe::egui;
+use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use tray_icon::menu::{Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tray_icon::{Icon, MouseButton, MouseButtonState, TrayIcon, TrayIconBuilder, TrayIconEvent};
+use windows::Win32::Foundation::{COLORREF, HWND};
+use windows::Win32::UI::WindowsAndMessaging::{
+    GetWindowLongW, IsIconic, SetForegroundWindow, SetLayeredWindowAttributes, SetWindowLongW, ShowWindow,
+    GWL_EXSTYLE, LWA_ALPHA, SW_RESTORE, SW_SHOW, SW_SHOWNA, WS_EX_LAYERED, WS_EX_TOOLWINDOW,
+};
+
+pub enum TrayCommand {
+    Hide,
+    Show,
+    RebuildIndex,
+    Exit,
+}
+
+/// Returns the native handle of the main window, kept as an integer so it can be
+/// moved into the tray's event handlers.
+pub fn main_window_handle(cc: &eframe::CreationContext<'_>) -> Option<isize> {
+    match cc.window_handle().ok()?.as_raw() {
+        RawWindowHandle::Win32(handle) => Some(handle.hwnd.get()),
+        _ => None,
+    }
+}
+
+/// Shows, un-minimizes and focuses the main window from any thread. A hidden eframe
+/// window gets no frames, so this has to go through Win32 rather than a viewport command.
+pub fn restore_window(hwnd: isize, ctx: &egui::Context) {
+    unsafe {
+        let hwnd = HWND(hwnd as *mut std::ffi::c_void);
+        let cmd = if IsIconic(hwnd).as_bool() { SW_RESTORE } else { SW_SHOW };
+        let _ = ShowWindow(hwnd, cmd);
+        let _ = SetForegroundWindow(hwnd);
+    }
+    ctx.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+    ctx.request_repaint();
+}
+
+/// Lets a hidden window run the frame that handles Exit without showing up: Windows only
+/// sends paint messages to visible windows, so it's shown fully transparent, unfocused
+/// and without a taskbar button, the way winit hides its own message window.
+fn wake_unseen(hwnd: isize, ctx: &egui::Context) {
+    unsafe {
+        let hwnd = HWND(hwnd as *mut std::ffi::c_void);
+        let style = GetWindowLongW(hwnd, GWL_EXSTYLE);
+        SetWindowLongW(hwnd, GWL_EXSTYLE, style | (WS_EX_LAYERED | WS_EX_TOOLWINDOW).0 as i32);
+        let _ = SetLayeredWindowAttributes(hwnd, COLORREF(0), 0, LWA_ALPHA);
+        let _ = ShowWindow(hwnd, SW_SHOWNA);
+    }
+    ctx.request_repaint();
+}
+
+pub struct Tray {
+    _icon: TrayIcon,
+    rx: Receiver<TrayCommand>,
+    visible: Arc<AtomicBool>,
+}
+
+impl Tray {
+    pub fn new(ctx: &egui::Context, hwnd: isize, icon: &egui::IconData) -> anyhow::Result<Self> {
+        let show = MenuItem::new("Show Rivet", true, None);
+        let rebuild = MenuItem::new("Rebuild Index", true, None);
+        let exit = MenuItem::new("Exit", true, None);
+        let menu = Menu::new();
+        menu.append_items(&[&show, &rebuild, &PredefinedMenuItem::separator(), &exit])?;
+
+        let tray_icon = TrayIconBuilder::new()
+            .with_tooltip("Rivet")
+            .with_icon(Icon::from_rgba(icon.rgba.clone(), icon.width, icon.height)?)
+            .with_menu(Box::new(menu))
+            .with_menu_on_left_click(false)
+            .build()?;
+
+        let (tx, rx) = channel();
+        let visible = Arc::new(AtomicBool::new(true));
+
+        let (show_id, rebuild_id, exit_id) = (show.id().clone(), rebuild.id().clone(), exit.id().clone());
+        let menu_tx = tx.clone();
+        let menu_ctx = ctx.clone();
+        let menu_visible = Arc::clone(&visible);
+        MenuEvent::set_event_handler(Some(move |event: MenuEvent| {
+            let command = if event.id == show_id {
+                TrayCommand::Show
+            } else if event.id == rebuild_id {
+                TrayCommand::RebuildIndex
+            } else if event.id == exit_id {
+                // Quits straight from the tray, so the window isn't restored first
+                let _ = menu_tx.send(TrayCommand::Exit);
+                wake_unseen(hwnd, &menu_ctx);
+                return;
+            } else {
+                return;
+            };
+            restore_window(hwnd, &menu_ctx);
+            menu_visible.store(true, Ordering::Release);
+            let _ = menu_tx.send(command);
+        }));
+
+        // Left click toggles the window; the right-click menu is handled by tray-icon
+        let click_ctx = ctx.clone();
+        let click_visible = Arc::clone(&visible);
+        TrayIconEvent::set_event_handler(Some(move |event: TrayIconEvent| {
+            if let TrayIconEvent::Click { button: MouseButton::Left, button_state: MouseButtonState::Up, .. } = event {
+                if click_visible.load(Ordering::Acquire) {
+                    let _ = tx.send(TrayCommand::Hide);
+                    click_ctx.request_repaint();
+                } else {
+                    restore_window(hwnd, &click_ctx);
+                    click_visible.store(true, Ordering::Release);
+                }
+            }
+        }));
+
+        Ok(Self { _icon: tray_icon, rx, visible })
+    }
+
+    pub fn poll(&self) -> Vec<TrayCommand> {
+        self.rx.try_iter().collect()
+    }
+
+    pub fn set_visible(&self, visible: bool) {
+        self.visible.store(visible, Ordering::Release);
+    }
+}
@@ -1,64 +1,940 @@
-use crate::mft_indexer::{Indexer, FileRecord};
-use usn_journal_rs::journal::UsnJournal;
-use usn_journal_rs::volume::Volume;
+use crate::mft_indexer::{fid_sequence, record_key, Indexer, FileRecord, HardLink};
+use crate::ntfs::{
+    FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_REPARSE_POINT, USN_REASON_BASIC_INFO_CHANGE, USN_REASON_CLOSE,
+    USN_REASON_DATA_EXTEND, USN_REASON_DATA_OVERWRITE, USN_REASON_DATA_TRUNCATION, USN_REASON_FILE_CREATE,
+    USN_REASON_FILE_DELETE, USN_REASON_HARD_LINK_CHANGE, USN_REASON_RENAME_NEW_NAME, USN_REASON_RENAME_OLD_NAME,
+    USN_REASON_SECURITY_CHANGE,
+};
+use crate::volume::VolumeId;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio_util::sync::CancellationToken;
-use windows::Win32::Storage::FileSystem::{GetFileAttributesExW, GetFileExInfoStandard, WIN32_FILE_ATTRIBUTE_DATA};
-use windows::core::HSTRING;
+#[cfg(windows)]
+use {
+    crate::dir_walker::DirChangeSource,
+    std::os::windows::io::AsRawHandle,
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    std::thread::JoinHandle,
+    crate::mft_enumerator::parse_usn_record,
+    crate::volume::VolumeInfo,
+    crate::volume_manager::has_usn_journal,
+    windows::core::HSTRING,
+    windows::Win32::Foundation::{
+        CloseHandle, ERROR_JOURNAL_DELETE_IN_PROGRESS, ERROR_JOURNAL_ENTRY_DELETED, ERROR_JOURNAL_NOT_ACTIVE,
+        GENERIC_READ, HANDLE, WIN32_ERROR,
+    },
+    windows::Win32::Storage::FileSystem::{
+        CreateFileW, ExtendedFileIdType, OpenFileById, FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_BACKUP_SEMANTICS,
+        FILE_ID_128, FILE_ID_DESCRIPTOR, FILE_ID_DESCRIPTOR_0, FILE_READ_ATTRIBUTES, FILE_SHARE_DELETE,
+        FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+    },
+    windows::Win32::System::IO::{CancelSynchronousIo, DeviceIoControl},
+    windows::Win32::System::Ioctl::{
+        FSCTL_QUERY_USN_JOURNAL, FSCTL_READ_FILE_USN_DATA, FSCTL_READ_USN_JOURNAL, READ_FILE_USN_DATA,
+        READ_USN_JOURNAL_DATA_V1, USN_JOURNAL_DATA_V0,
+    },
+};
+
+const REASON_NAMES: &[(u32, &str)] = &[
+    (USN_REASON_FILE_CREATE, "create"),
+    (USN_REASON_FILE_DELETE, "delete"),
+    (USN_REASON_RENAME_OLD_NAME, "rename_old"),
+    (USN_REASON_RENAME_NEW_NAME, "rename_new"),
+    (USN_REASON_DATA_OVERWRITE, "overwrite"),
+    (USN_REASON_DATA_EXTEND, "extend"),
+    (USN_REASON_DATA_TRUNCATION, "truncate"),
+    (USN_REASON_BASIC_INFO_CHANGE, "basic_info"),
+    (USN_REASON_HARD_LINK_CHANGE, "hard_link"),
+    (USN_REASON_SECURITY_CHANGE, "security"),
+    (USN_REASON_CLOSE, "close"),
+];
+
+/// Readable form of a USN reason mask for logs, e.g. "create|close".
+fn reason_names(reason: u32) -> String {
+    let names: Vec<&str> = REASON_NAMES.iter()
+        .filter(|(flag, _)| reason & flag != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    if names.is_empty() { format!("0x{:x}", reason) } else { names.join("|") }
+}
+
+// Most missing parent folders looked up for one event, in case the chain doesn't end
+const MAX_PARENT_LOOKUPS: usize = 64;
+// While changes keep coming the poll interval shrinks down to the configured one
+// divided by this, and while none do it grows up to the configured one times this
+const POLL_BACKOFF_FACTOR: u32 = 4;
+// A file still open for writing is re-measured once it has gone this long without a
+// change; a close event re-measures it straight away
+const SIZE_SETTLE_DELAY: Duration = Duration::from_secs(2);
+// Longest the monitor waits on a source at a time, and so how long cancelling it takes
+const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+// Read failures meaning changes were lost and the index can't be trusted any more
+#[cfg(windows)]
+const JOURNAL_LOST_ERRORS: [WIN32_ERROR; 3] =
+    [ERROR_JOURNAL_ENTRY_DELETED, ERROR_JOURNAL_NOT_ACTIVE, ERROR_JOURNAL_DELETE_IN_PROGRESS];
+
+/// The journal no longer holds the changes since the last read: they were purged to make
+/// room, or the journal was deleted or recreated. Only a full reindex catches up.
+#[derive(Debug)]
+pub struct JournalLost(pub String);
+
+impl std::fmt::Display for JournalLost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "USN journal changes were lost: {}", self.0)
+    }
+}
+
+impl std::error::Error for JournalLost {}
+
+/// A file whose size may have changed, from `apply_changes`.
+pub struct SizeChange {
+    pub id: u128,
+    // Whether the handle was closed, so no more writes are coming from it
+    pub closed: bool,
+}
+
+/// One change to a file, as recorded in a volume's USN journal.
+#[derive(Debug, Clone)]
+pub struct ChangeEvent {
+    pub fid: u128,
+    pub parent_fid: u128,
+    // USN_REASON_* flags
+    pub reason: u32,
+    pub name: String,
+    // The exact name when `name` is a lossy copy, see `MftEntry::wide_name`
+    pub wide_name: Option<Vec<u16>>,
+    // FILETIME, 100ns intervals since 1601
+    pub modified: i64,
+    // Raw FILE_ATTRIBUTE_* flags
+    pub attributes: u32,
+}
+
+/// A feed of file changes for one volume. `UsnChangeSource` reads the USN journal;
+/// a `Vec` of events or a `MemoryChangeSource` stands in for a volume where there is none.
+pub trait ChangeSource {
+    /// Changes since the last call, empty when nothing happened.
+    fn poll(&mut self) -> anyhow::Result<Vec<ChangeEvent>>;
+
+    /// Blocks until `poll` may have changes to return, or `timeout` has passed, and
+    /// says which. Sources that can't be waited on just sleep.
+    fn wait(&mut self, timeout: Duration) -> bool {
+        std::thread::sleep(timeout);
+        false
+    }
+
+    /// The file's current name, parent and attributes, for a parent folder whose own
+    /// record never came through. None where files can't be looked up by ID.
+    fn lookup(&mut self, _fid: u128) -> Option<ChangeEvent> {
+        None
+    }
+}
+
+impl ChangeSource for Vec<ChangeEvent> {
+    fn poll(&mut self) -> anyhow::Result<Vec<ChangeEvent>> {
+        Ok(std::mem::take(self))
+    }
+}
+
+/// Changes made up in memory, e.g. for tests: each `push` is what one `poll` returns,
+/// and `lookup` finds the files given to `add_file`.
+#[derive(Default)]
+pub struct MemoryChangeSource {
+    batches: VecDeque<Vec<ChangeEvent>>,
+    files: HashMap<u128, ChangeEvent>,
+}
+
+impl MemoryChangeSource {
+    pub fn push(&mut self, events: Vec<ChangeEvent>) {
+        self.batches.push_back(events);
+    }
+
+    /// Makes `file` known to `lookup`, as if it existed on the volume.
+    pub fn add_file(&mut self, file: ChangeEvent) {
+        self.files.insert(file.fid, file);
+    }
+}
+
+impl ChangeSource for MemoryChangeSource {
+    fn poll(&mut self) -> anyhow::Result<Vec<ChangeEvent>> {
+        Ok(self.batches.pop_front().unwrap_or_default())
+    }
+
+    fn lookup(&mut self, fid: u128) -> Option<ChangeEvent> {
+        self.files.get(&fid).cloned()
+    }
+}
+
+// Journal bytes read per request
+#[cfg(windows)]
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+// How long a journal read waits for a record before checking whether to stop. The
+// kernel only takes whole seconds.
+#[cfg(windows)]
+const READ_TIMEOUT_SECS: u64 = 1;
+// Pause after a failed read before trying again, so a failing volume isn't hammered
+#[cfg(windows)]
+const READ_RETRY_DELAY: Duration = Duration::from_secs(1);
+// How often `drop` retries cancelling the reader thread's read until it has exited
+#[cfg(windows)]
+const CANCEL_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Reads a volume's USN journal from where it stood when opened. The reads block until
+/// a change arrives, so they run on a thread of their own and hand their records over
+/// a channel, where `wait` picks them up as soon as they're there.
+#[cfg(windows)]
+pub struct UsnChangeSource {
+    // For looking files up by ID; the reader thread has a handle of its own, since
+    // reads on one handle queue up behind each other
+    handle: HANDLE,
+    buffer: Vec<u8>,
+    rx: Receiver<anyhow::Result<Vec<ChangeEvent>>>,
+    // Taken off `rx` by `wait`, to be returned by the next `poll`
+    received: VecDeque<anyhow::Result<Vec<ChangeEvent>>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(windows)]
+fn open_volume(volume: &VolumeInfo) -> windows::core::Result<HANDLE> {
+    unsafe {
+        CreateFileW(
+            &HSTRING::from(volume.device_path()),
+            GENERIC_READ.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAGS_AND_ATTRIBUTES(0),
+            None,
+        )
+    }
+}
+
+#[cfg(windows)]
+impl UsnChangeSource {
+    pub fn open(volume: &VolumeInfo) -> anyhow::Result<Self> {
+        let handle = open_volume(volume)?;
+        let journal = match query_journal(handle) {
+            Ok(journal) => journal,
+            Err(e) => {
+                unsafe { let _ = CloseHandle(handle); }
+                anyhow::bail!("Failed to query USN journal for volume {}: {} (0x{:08X})", volume.root(), e, e.code().0);
+            }
+        };
+        let read_handle = match open_volume(volume) {
+            Ok(read_handle) => read_handle,
+            Err(e) => {
+                unsafe { let _ = CloseHandle(handle); }
+                return Err(e.into());
+            }
+        };
+
+        let (tx, rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let root = volume.root().to_string();
+        // HANDLE isn't Send; from here on only the thread uses it, and the reader closes it
+        let raw_handle = read_handle.0 as isize;
+        let thread = std::thread::spawn(move || {
+            let reader = JournalReader {
+                handle: HANDLE(raw_handle as *mut std::ffi::c_void),
+                journal_id: journal.UsnJournalID,
+                next_usn: journal.NextUsn,
+                buffer: vec![0u8; READ_BUFFER_SIZE],
+            };
+            read_journal(reader, &root, &tx, &thread_stop);
+        });
+
+        Ok(Self {
+            handle,
+            buffer: vec![0u8; READ_BUFFER_SIZE],
+            rx,
+            received: VecDeque::new(),
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+#[cfg(windows)]
+fn query_journal(handle: HANDLE) -> windows::core::Result<USN_JOURNAL_DATA_V0> {
+    let mut journal = USN_JOURNAL_DATA_V0::default();
+    unsafe {
+        DeviceIoControl(
+            handle,
+            FSCTL_QUERY_USN_JOURNAL,
+            None,
+            0,
+            Some(&mut journal as *mut _ as *mut std::ffi::c_void),
+            std::mem::size_of::<USN_JOURNAL_DATA_V0>() as u32,
+            None,
+            None,
+        )?;
+    }
+    Ok(journal)
+}
+
+/// Sends each batch `reader` reads until `stop` is set, the receiver goes away or
+/// changes were lost. Other failures are sent along too, then retried.
+#[cfg(windows)]
+fn read_journal(mut reader: JournalReader, root: &str, tx: &Sender<anyhow::Result<Vec<ChangeEvent>>>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read() {
+            Ok(events) if events.is_empty() => {}
+            Ok(events) => {
+                if tx.send(Ok(events)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                let lost = e.is::<JournalLost>();
+                if tx.send(Err(e)).is_err() || lost {
+                    break;
+                }
+                std::thread::sleep(READ_RETRY_DELAY);
+            }
+        }
+    }
+    tracing::debug!(%root, "USN journal reader stopped");
+}
+
+/// The reader thread's end of a `UsnChangeSource`.
+#[cfg(windows)]
+struct JournalReader {
+    handle: HANDLE,
+    journal_id: u64,
+    next_usn: i64,
+    buffer: Vec<u8>,
+}
+
+#[cfg(windows)]
+impl JournalReader {
+    /// Reads the next buffer of records. The kernel holds the read until a record
+    /// arrives, so changes are seen as soon as they happen, or `READ_TIMEOUT_SECS`
+    /// passes, which returns nothing.
+    fn read(&mut self) -> anyhow::Result<Vec<ChangeEvent>> {
+        // V1 of the request lets ReFS hand back V3 records with 128-bit file IDs
+        let request = READ_USN_JOURNAL_DATA_V1 {
+            StartUsn: self.next_usn,
+            ReasonMask: u32::MAX,
+            ReturnOnlyOnClose: 0,
+            // A timeout of 0 would wait forever, and stopping couldn't get through
+            Timeout: READ_TIMEOUT_SECS,
+            BytesToWaitFor: 1,
+            UsnJournalID: self.journal_id,
+            MinMajorVersion: 2,
+            MaxMajorVersion: 3,
+        };
+        let mut bytes_returned = 0u32;
+        let result = unsafe {
+            DeviceIoControl(
+                self.handle,
+                FSCTL_READ_USN_JOURNAL,
+                Some(&request as *const _ as _),
+                std::mem::size_of::<READ_USN_JOURNAL_DATA_V1>() as u32,
+                Some(self.buffer.as_mut_ptr() as _),
+                self.buffer.len() as u32,
+                Some(&mut bytes_returned),
+                None,
+            )
+        };
+        if let Err(e) = result {
+            return Err(self.read_error(e));
+        }
+
+        // The output starts with the USN to continue from
+        let bytes_returned = bytes_returned as usize;
+        if bytes_returned < 8 {
+            return Ok(Vec::new());
+        }
+        self.next_usn = i64::from_le_bytes(self.buffer[..8].try_into().unwrap());
+        let mut events = Vec::new();
+        let mut offset = 8;
+        while offset < bytes_returned {
+            let (record, length) = parse_usn_record(&self.buffer[offset..bytes_returned])?;
+            offset += length;
+            events.push(ChangeEvent {
+                fid: record.fid,
+                parent_fid: record.parent_fid,
+                reason: record.reason,
+                name: record.name,
+                wide_name: record.wide_name,
+                modified: record.timestamp,
+                attributes: record.attributes,
+            });
+        }
+        Ok(events)
+    }
+
+    /// Turns a failed read into `JournalLost` when changes were lost, which the error
+    /// code says directly or, for a recreated journal, the journal's new ID does.
+    fn read_error(&self, e: windows::core::Error) -> anyhow::Error {
+        if let Some(code) = JOURNAL_LOST_ERRORS.iter().find(|&&code| e.code() == code.into()) {
+            return JournalLost(format!("{} (0x{:08X})", e, code.0)).into();
+        }
+        match query_journal(self.handle) {
+            Ok(journal) if journal.UsnJournalID != self.journal_id => {
+                JournalLost("the journal was recreated".to_string()).into()
+            }
+            Ok(journal) if self.next_usn < journal.FirstUsn => {
+                JournalLost("the changes since the last read were purged".to_string()).into()
+            }
+            _ => e.into(),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for JournalReader {
+    fn drop(&mut self) {
+        unsafe { let _ = CloseHandle(self.handle); }
+    }
+}
+
+#[cfg(windows)]
+impl ChangeSource for UsnChangeSource {
+    fn poll(&mut self) -> anyhow::Result<Vec<ChangeEvent>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(read) => self.received.push_back(read),
+                Err(TryRecvError::Empty) => break,
+                // Only if it panicked; changes since then were never read
+                Err(TryRecvError::Disconnected) if self.received.is_empty() => {
+                    return Err(JournalLost("the journal reader stopped".to_string()).into());
+                }
+                Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        // Changes read before a failure are applied first; the failure comes next time
+        let mut events = Vec::new();
+        while let Some(read) = self.received.pop_front() {
+            match read {
+                Ok(read) => events.extend(read),
+                Err(e) if events.is_empty() => return Err(e),
+                Err(e) => {
+                    self.received.push_front(Err(e));
+                    break;
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    fn wait(&mut self, timeout: Duration) -> bool {
+        if !self.received.is_empty() {
+            return true;
+        }
+        match self.rx.recv_timeout(timeout) {
+            Ok(read) => {
+                self.received.push_back(read);
+                true
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            // `poll` reports it
+            Err(RecvTimeoutError::Disconnected) => true,
+        }
+    }
+
+    fn lookup(&mut self, fid: u128) -> Option<ChangeEvent> {
+        self.read_file_record(fid)
+            .map_err(|e| tracing::debug!(fid = format!("0x{:x}", fid), error = %e, "Failed to look up file by ID"))
+            .ok()
+    }
+}
+
+#[cfg(windows)]
+impl UsnChangeSource {
+    /// Opens the file by ID and asks NTFS for the USN record describing it.
+    fn read_file_record(&mut self, fid: u128) -> anyhow::Result<ChangeEvent> {
+        let descriptor = FILE_ID_DESCRIPTOR {
+            dwSize: std::mem::size_of::<FILE_ID_DESCRIPTOR>() as u32,
+            Type: ExtendedFileIdType,
+            Anonymous: FILE_ID_DESCRIPTOR_0 { ExtendedFileId: FILE_ID_128 { Identifier: fid.to_le_bytes() } },
+        };
+        let file = unsafe {
+            OpenFileById(
+                self.handle,
+                &descriptor,
+                FILE_READ_ATTRIBUTES.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                // Needed to open a directory
+                FILE_FLAG_BACKUP_SEMANTICS,
+            )?
+        };
+        let request = READ_FILE_USN_DATA { MinMajorVersion: 2, MaxMajorVersion: 3 };
+        let mut bytes_returned = 0u32;
+        let result = unsafe {
+            DeviceIoControl(
+                file,
+                FSCTL_READ_FILE_USN_DATA,
+                Some(&request as *const _ as _),
+                std::mem::size_of::<READ_FILE_USN_DATA>() as u32,
+                Some(self.buffer.as_mut_ptr() as _),
+                self.buffer.len() as u32,
+                Some(&mut bytes_returned),
+                None,
+            )
+        };
+        unsafe { let _ = CloseHandle(file); }
+        result?;
+
+        let (record, _) = parse_usn_record(&self.buffer[..bytes_returned as usize])?;
+        Ok(ChangeEvent {
+            fid: record.fid,
+            parent_fid: record.parent_fid,
+            reason: 0,
+            name: record.name,
+            wide_name: record.wide_name,
+            modified: record.timestamp,
+            attributes: record.attributes,
+        })
+    }
+}
+
+#[cfg(windows)]
+impl Drop for UsnChangeSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            // A cancel that lands before the thread starts its next read is lost, so
+            // keep cancelling until the thread sees `stop` and exits
+            while !thread.is_finished() {
+                unsafe {
+                    let _ = CancelSynchronousIo(HANDLE(thread.as_raw_handle()));
+                }
+                std::thread::sleep(CANCEL_RETRY_INTERVAL);
+            }
+            let _ = thread.join();
+        }
+        unsafe { let _ = CloseHandle(self.handle); }
+    }
+}
+
+/// Applies `events` from `volume` to the index: deletions remove the record and
+/// everything else inserts or replaces it, keeping the size already known for the file
+/// and folding the event's name in with its other links (see `merge_event_name`).
+/// Returns the files whose size needs re-measuring, since events don't carry one.
+pub fn apply_changes(indexer: &Indexer, volume: VolumeId, events: &[ChangeEvent]) -> Vec<SizeChange> {
+    let mut changed = Vec::new();
+    for event in events {
+        tracing::debug!(
+            %volume,
+            reason = %reason_names(event.reason),
+            name = %event.name,
+            "USN event"
+        );
+        let id = record_key(volume, event.fid);
+        // Before a delete or move, while the record still leads to its old folders
+        indexer.mark_dir_sizes_stale(id);
+        if event.reason & USN_REASON_FILE_DELETE != 0 {
+            // Walked volumes key records by path, so a removed folder takes its contents
+            // along; with a journal each file gets its own delete
+            if indexer.is_walked(volume) {
+                indexer.remove_subtree(id);
+            } else {
+                indexer.remove_record(id);
+            }
+            continue;
+        }
+
+        let is_dir = (event.attributes & FILE_ATTRIBUTE_DIRECTORY) != 0;
+        let sequence = fid_sequence(event.fid);
+        let mut record = FileRecord {
+            id,
+            parent_id: record_key(volume, event.parent_fid),
+            sequence,
+            name: event.name.clone(),
+            wide_name: event.wide_name.clone(),
+            size: 0,
+            modified: event.modified,
+            created: 0,
+            accessed: 0,
+            is_dir,
+            is_reparse_point: (event.attributes & FILE_ATTRIBUTE_REPARSE_POINT) != 0,
+            attributes: event.attributes,
+            hash: None,
+            links: Vec::new(),
+        };
+        // A reused MFT slot is a different file and starts from scratch
+        let existing = indexer.records.get(&id).filter(|r| r.sequence == sequence).map(|r| r.clone());
+        if let Some(existing) = existing {
+            record.size = existing.size;
+            record.created = existing.created;
+            record.accessed = existing.accessed;
+            record.parent_id = existing.parent_id;
+            record.name = existing.name;
+            record.wide_name = existing.wide_name;
+            record.links = existing.links;
+            merge_event_name(&mut record, volume, event);
+        }
+        indexer.insert_record(record);
+        // Its new folders too, if it was created or moved
+        indexer.mark_dir_sizes_stale(id);
+        if !is_dir {
+            changed.push(SizeChange { id, closed: event.reason & USN_REASON_CLOSE != 0 });
+        }
+    }
+    changed
+}
+
+/// Folds the name `event` came through into `record`'s names. An event names only the
+/// link the file was reached by, so a change made through a second hard link mustn't
+/// replace the primary name:
+/// - a known name leaves the names alone, except that the old name of a rename becomes
+///   the primary one, since the new name that follows replaces the primary name;
+/// - a name not seen before is a rename or move and replaces the primary name;
+/// - a hard link change adds the name as a link if it's new and removes it otherwise.
+///   Only the first record of the change counts, as the close record repeats the name.
+fn merge_event_name(record: &mut FileRecord, volume: VolumeId, event: &ChangeEvent) {
+    let link = HardLink { parent_id: record_key(volume, event.parent_fid), name: event.name.clone() };
+    let is_primary = record.parent_id == link.parent_id && record.name == link.name;
+    let link_pos = record.links.iter().position(|l| *l == link);
+
+    if event.reason & USN_REASON_HARD_LINK_CHANGE != 0 {
+        if event.reason & USN_REASON_CLOSE != 0 {
+            return;
+        }
+        if is_primary {
+            // The last link goes with a delete instead, so there's another one to promote
+            if !record.links.is_empty() {
+                let next = record.links.remove(0);
+                record.parent_id = next.parent_id;
+                record.name = next.name;
+                record.wide_name = None;
+            }
+        } else if let Some(pos) = link_pos {
+            record.links.remove(pos);
+        } else {
+            record.links.push(link);
+        }
+        return;
+    }
+
+    if is_primary {
+        return;
+    }
+    if let Some(pos) = link_pos {
+        if event.reason & USN_REASON_RENAME_OLD_NAME != 0 {
+            record.links[pos] = HardLink { parent_id: record.parent_id, name: std::mem::take(&mut record.name) };
+            record.parent_id = link.parent_id;
+            record.name = link.name;
+            record.wide_name = event.wide_name.clone();
+        }
+        return;
+    }
+    record.parent_id = link.parent_id;
+    record.name = link.name;
+    record.wide_name = event.wide_name.clone();
+}
+
+/// Looks up the parent folders of `events` that the index is missing, e.g. because
+/// they were created while the journal wasn't being read, so the paths of the changed
+/// files resolve all the way to the root.
+pub fn resolve_missing_parents(indexer: &Indexer, volume: VolumeId, source: &mut impl ChangeSource, events: &[ChangeEvent]) {
+    for event in events.iter().filter(|event| event.reason & USN_REASON_FILE_DELETE == 0) {
+        let mut fid = event.parent_fid;
+        for _ in 0..MAX_PARENT_LOOKUPS {
+            if !indexer.is_missing(record_key(volume, fid)) {
+                break;
+            }
+            let Some(parent) = source.lookup(fid) else {
+                break;
+            };
+            tracing::debug!(%volume, name = %parent.name, "Indexed missing parent folder");
+            fid = parent.parent_fid;
+            apply_changes(indexer, volume, &[parent]);
+        }
+    }
+}
 
 pub struct Monitor {
     indexer: Arc<Indexer>,
+    // How long to wait between checks for changes when there's no recent activity.
+    // Sources that can be waited on, like the USN journal, wake up early on a change.
+    poll_interval: Duration,
 }
 
 impl Monitor {
-    pub fn new(indexer: Arc<Indexer>) -> Self {
-        Self { indexer }
+    pub fn new(indexer: Arc<Indexer>, poll_interval: Duration) -> Self {
+        Self { indexer, poll_interval }
+    }
+
+    #[cfg(windows)]
+    pub fn start_monitoring(&self, volume: &VolumeInfo, token: &CancellationToken) -> anyhow::Result<()> {
+        if has_usn_journal(volume) {
+            self.run(volume.id, UsnChangeSource::open(volume)?, token)
+        } else {
+            self.run(volume.id, DirChangeSource::open(volume)?, token)
+        }
     }
 
-    pub fn start_monitoring(&self, drive_letter: char, token: &CancellationToken) -> anyhow::Result<()> {
-        let volume = Volume::from_drive_letter(drive_letter)?;
-        let journal = UsnJournal::new(&volume);
-        
+    /// Applies changes from `source` to the index until `token` is cancelled. Fails
+    /// with `JournalLost` if changes went missing; other read errors are retried.
+    pub fn run(&self, volume: VolumeId, mut source: impl ChangeSource, token: &CancellationToken) -> anyhow::Result<()> {
+        // Files being written -> when they last changed. A download or log fires a stream
+        // of events, so sizes are only measured once the writes settle.
+        let mut pending: HashMap<u128, Instant> = HashMap::new();
+        let (fastest, slowest) = (self.poll_interval / POLL_BACKOFF_FACTOR, self.poll_interval * POLL_BACKOFF_FACTOR);
+        let mut interval = self.poll_interval;
         loop {
             if token.is_cancelled() {
                 return Ok(());
             }
 
-            // Re-creating iterator to keep polling from "current"
-            if let Ok(iter) = journal.iter() {
-                for record in iter {
-                    if let Ok(entry) = record {
-                        let mut size = 0;
-                        if !entry.is_dir() {
-                            let path = self.indexer.get_full_path(entry.fid, drive_letter);
-                            let mut data = WIN32_FILE_ATTRIBUTE_DATA::default();
-                            unsafe {
-                                if GetFileAttributesExW(&HSTRING::from(path), GetFileExInfoStandard, &mut data as *mut _ as *mut _).is_ok() {
-                                    size = ((data.nFileSizeHigh as u64) << 32) | (data.nFileSizeLow as u64);
-                                }
-                            }
+            match source.poll() {
+                Ok(events) => {
+                    // Check again sooner while files are changing, and back off when idle
+                    if events.is_empty() {
+                        interval = (interval * 3 / 2).min(slowest);
+                    } else {
+                        interval = (interval / 2).max(fastest);
+                        self.indexer.request_repaint();
+                    }
+                    let now = Instant::now();
+                    let changes = apply_changes(&self.indexer, volume, &events);
+                    resolve_missing_parents(&self.indexer, volume, &mut source, &events);
+                    for change in changes {
+                        if change.closed {
+                            pending.remove(&change.id);
+                            self.indexer.refresh_size(change.id);
+                        } else {
+                            pending.insert(change.id, now);
                         }
-
-                        let modified = entry.time
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .map(|d| (d.as_secs() + 11_644_473_600) * 10_000_000)
-                                    .unwrap_or(0) as i64;
-
-                        let file_record = FileRecord {
-                            id: entry.fid,
-                            parent_id: entry.parent_fid,
-                            name: entry.file_name.to_string_lossy().into_owned(),
-                            size,
-                            modified,
-                            is_dir: entry.is_dir(),
-                        };
-
-                        self.indexer.records.insert(file_record.id, file_record);
                     }
                 }
+                Err(e) if e.is::<JournalLost>() => return Err(e),
+                Err(e) => tracing::debug!(%volume, error = %e, "Failed to read USN journal"),
+            }
+
+            let waiting = pending.len();
+            pending.retain(|&id, changed_at| {
+                if changed_at.elapsed() < SIZE_SETTLE_DELAY {
+                    return true;
+                }
+                self.indexer.refresh_size(id);
+                false
+            });
+            if pending.len() < waiting {
+                self.indexer.request_repaint();
             }
-            
-            std::thread::sleep(std::time::Duration::from_millis(500));
+
+            // Wakes up early for changes, and checks for cancellation in between
+            let deadline = Instant::now() + interval;
+            loop {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left.is_zero() || token.is_cancelled() || source.wait(left.min(WAIT_SLICE)) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: u128 = 5;
+
+    fn volume() -> VolumeId {
+        VolumeId::from_letter('C')
+    }
+
+    fn event(fid: u128, parent_fid: u128, name: &str, reason: u32, is_dir: bool) -> ChangeEvent {
+        ChangeEvent {
+            fid,
+            parent_fid,
+            reason,
+            name: name.to_string(),
+            wide_name: None,
+            modified: 0,
+            attributes: if is_dir { FILE_ATTRIBUTE_DIRECTORY } else { 0 },
         }
     }
+
+    fn path(indexer: &Indexer, fid: u128) -> String {
+        indexer.get_full_path(record_key(volume(), fid)).path
+    }
+
+    #[test]
+    fn created_files_are_indexed_under_their_folder() {
+        let indexer = Indexer::new();
+        let changes = apply_changes(&indexer, volume(), &[
+            event(100, ROOT, "docs", USN_REASON_FILE_CREATE, true),
+            event(101, 100, "a.txt", USN_REASON_FILE_CREATE | USN_REASON_CLOSE, false),
+        ]);
+        assert_eq!(path(&indexer, 101), "C:\\docs\\a.txt");
+        assert!(indexer.get_full_path(record_key(volume(), 101)).complete);
+        // Only files need measuring
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].id, record_key(volume(), 101));
+        assert!(changes[0].closed);
+    }
+
+    #[test]
+    fn renames_keep_the_known_size() {
+        let indexer = Indexer::new();
+        apply_changes(&indexer, volume(), &[event(101, ROOT, "a.txt", USN_REASON_FILE_CREATE, false)]);
+        let id = record_key(volume(), 101);
+        indexer.records.get_mut(&id).unwrap().size = 42;
+
+        apply_changes(&indexer, volume(), &[event(101, ROOT, "b.txt", USN_REASON_RENAME_NEW_NAME, false)]);
+        let record = indexer.records.get(&id).unwrap();
+        assert_eq!(record.name, "b.txt");
+        assert_eq!(record.size, 42);
+    }
+
+    #[test]
+    fn reused_slots_start_from_scratch() {
+        let indexer = Indexer::new();
+        let first = (1u128 << 48) | 101;
+        let second = (2u128 << 48) | 101;
+        apply_changes(&indexer, volume(), &[event(first, ROOT, "old.txt", USN_REASON_FILE_CREATE, false)]);
+        let id = record_key(volume(), first);
+        indexer.records.get_mut(&id).unwrap().size = 42;
+
+        apply_changes(&indexer, volume(), &[event(second, ROOT, "new.txt", USN_REASON_FILE_CREATE, false)]);
+        let record = indexer.records.get(&id).unwrap();
+        assert_eq!(record.name, "new.txt");
+        assert_eq!(record.sequence, 2);
+        assert_eq!(record.size, 0);
+    }
+
+    #[test]
+    fn deletes_remove_the_record() {
+        let indexer = Indexer::new();
+        apply_changes(&indexer, volume(), &[event(101, ROOT, "a.txt", USN_REASON_FILE_CREATE, false)]);
+        let changes = apply_changes(&indexer, volume(), &[event(101, ROOT, "a.txt", USN_REASON_FILE_DELETE, false)]);
+        assert!(changes.is_empty());
+        assert!(indexer.records.get(&record_key(volume(), 101)).is_none());
+    }
+
+    fn all_paths(indexer: &Indexer, fid: u128) -> Vec<String> {
+        let id = record_key(volume(), fid);
+        let mut paths: Vec<String> = std::iter::once(indexer.get_full_path(id))
+            .chain(indexer.link_paths(id))
+            .map(|full_path| full_path.path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    // C:\docs\a.txt, also linked as C:\b.txt
+    fn linked_file(source: &mut MemoryChangeSource) -> Indexer {
+        let indexer = Indexer::new();
+        source.push(vec![
+            event(100, ROOT, "docs", USN_REASON_FILE_CREATE | USN_REASON_CLOSE, true),
+            event(101, 100, "a.txt", USN_REASON_FILE_CREATE | USN_REASON_CLOSE, false),
+        ]);
+        source.push(vec![
+            event(101, ROOT, "b.txt", USN_REASON_HARD_LINK_CHANGE, false),
+            event(101, ROOT, "b.txt", USN_REASON_HARD_LINK_CHANGE | USN_REASON_CLOSE, false),
+        ]);
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        indexer
+    }
+
+    #[test]
+    fn added_hard_links_are_searchable() {
+        let mut source = MemoryChangeSource::default();
+        let indexer = linked_file(&mut source);
+        assert_eq!(all_paths(&indexer, 101), ["C:\\b.txt", "C:\\docs\\a.txt"]);
+    }
+
+    #[test]
+    fn changes_through_a_second_link_keep_both_names() {
+        let mut source = MemoryChangeSource::default();
+        let indexer = linked_file(&mut source);
+        source.push(vec![event(101, ROOT, "b.txt", USN_REASON_DATA_EXTEND | USN_REASON_CLOSE, false)]);
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        assert_eq!(all_paths(&indexer, 101), ["C:\\b.txt", "C:\\docs\\a.txt"]);
+    }
+
+    #[test]
+    fn renaming_a_second_link_keeps_the_first() {
+        let mut source = MemoryChangeSource::default();
+        let indexer = linked_file(&mut source);
+        source.push(vec![
+            event(101, ROOT, "b.txt", USN_REASON_RENAME_OLD_NAME, false),
+            event(101, 100, "c.txt", USN_REASON_RENAME_NEW_NAME, false),
+            event(101, 100, "c.txt", USN_REASON_RENAME_NEW_NAME | USN_REASON_CLOSE, false),
+        ]);
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        assert_eq!(all_paths(&indexer, 101), ["C:\\docs\\a.txt", "C:\\docs\\c.txt"]);
+    }
+
+    #[test]
+    fn removed_hard_links_are_dropped() {
+        let mut source = MemoryChangeSource::default();
+        let indexer = linked_file(&mut source);
+        // Removing the primary name promotes the other link
+        source.push(vec![
+            event(101, 100, "a.txt", USN_REASON_HARD_LINK_CHANGE, false),
+            event(101, 100, "a.txt", USN_REASON_HARD_LINK_CHANGE | USN_REASON_CLOSE, false),
+        ]);
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        assert_eq!(all_paths(&indexer, 101), ["C:\\b.txt"]);
+        assert!(indexer.records.get(&record_key(volume(), 101)).unwrap().links.is_empty());
+    }
+
+    #[test]
+    fn missing_parents_are_looked_up() {
+        let indexer = Indexer::new();
+        let mut source = MemoryChangeSource::default();
+        source.add_file(event(100, ROOT, "docs", 0, true));
+        source.add_file(event(200, 100, "2024", 0, true));
+        source.push(vec![event(201, 200, "report.pdf", USN_REASON_FILE_CREATE, false)]);
+
+        let events = source.poll().unwrap();
+        apply_changes(&indexer, volume(), &events);
+        assert!(!indexer.get_full_path(record_key(volume(), 201)).complete);
+
+        resolve_missing_parents(&indexer, volume(), &mut source, &events);
+        assert_eq!(path(&indexer, 201), "C:\\docs\\2024\\report.pdf");
+        assert!(source.poll().unwrap().is_empty());
+    }
+
+    #[test]
+    fn children_seen_before_their_parent_resolve_once_it_arrives() {
+        let indexer = Indexer::new();
+        let mut source = MemoryChangeSource::default();
+        source.push(vec![event(201, 200, "report.pdf", USN_REASON_FILE_CREATE, false)]);
+        source.push(vec![
+            event(100, ROOT, "docs", USN_REASON_FILE_CREATE, true),
+            event(200, 100, "2024", USN_REASON_FILE_CREATE, true),
+        ]);
+
+        // The parent isn't on the volume yet, so the lookup finds nothing either
+        let events = source.poll().unwrap();
+        apply_changes(&indexer, volume(), &events);
+        resolve_missing_parents(&indexer, volume(), &mut source, &events);
+        let early = indexer.get_full_path(record_key(volume(), 201));
+        assert!(!early.complete);
+        assert!(early.path.starts_with("…\\"));
+        assert!(early.path.ends_with("\\report.pdf"));
+
+        apply_changes(&indexer, volume(), &source.poll().unwrap());
+        let full_path = indexer.get_full_path(record_key(volume(), 201));
+        assert!(full_path.complete);
+        assert_eq!(full_path.path, "C:\\docs\\2024\\report.pdf");
+    }
+
+    #[test]
+    fn cancelling_stops_a_waiting_monitor_promptly() {
+        let monitor = Monitor::new(Arc::new(Indexer::new()), Duration::from_secs(60));
+        let token = CancellationToken::new();
+        let cancel = token.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            cancel.cancel();
+        });
+        let started = Instant::now();
+        monitor.run(volume(), MemoryChangeSource::default(), &token).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        canceller.join().unwrap();
+    }
 }
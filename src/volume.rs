@@ -0,0 +1,99 @@
+//! Volume identities, kept apart from `volume_manager` since the index needs them on
+//! every platform while finding and watching volumes needs Win32.
+
+use std::sync::Mutex;
+
+// Letterless volumes are numbered from here up, clear of the ASCII drive letters
+const FIRST_SLOT: u8 = 0x80;
+// GUID paths of letterless volumes, in the order they were first seen; a volume's
+// `VolumeId` is its position plus `FIRST_SLOT`, so it stays put while Rivet runs
+static SLOTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Identifies a volume within the index, whose record keys keep it in their top byte.
+/// Volumes with a drive letter use the letter; the rest get a slot of their own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VolumeId(pub u8);
+
+impl VolumeId {
+    pub fn from_letter(letter: char) -> Self {
+        Self(letter.to_ascii_uppercase() as u8)
+    }
+
+    pub fn letter(self) -> Option<char> {
+        self.0.is_ascii_uppercase().then_some(self.0 as char)
+    }
+
+    /// The slot for a volume without a drive letter, allocated the first time it's seen.
+    /// None once every slot is taken.
+    pub fn for_guid(guid_path: &str) -> Option<Self> {
+        let mut slots = SLOTS.lock().unwrap();
+        let index = match slots.iter().position(|slot| slot.eq_ignore_ascii_case(guid_path)) {
+            Some(index) => index,
+            None => {
+                slots.push(guid_path.to_string());
+                slots.len() - 1
+            }
+        };
+        u8::try_from(index).ok().and_then(|index| FIRST_SLOT.checked_add(index)).map(Self)
+    }
+}
+
+impl std::fmt::Display for VolumeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.letter() {
+            Some(letter) => write!(f, "{}:", letter),
+            None => write!(f, "volume #{}", self.0 - FIRST_SLOT + 1),
+        }
+    }
+}
+
+/// A mounted volume and the places it can be reached from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeInfo {
+    pub id: VolumeId,
+    // `\\?\Volume{GUID}\`; None for network drives, which only have their letter
+    pub guid_path: Option<String>,
+    // Drive roots and folders the volume is mounted at, e.g. `D:\` and `C:\Data\`,
+    // drive roots first. Empty for a volume that isn't mounted anywhere.
+    pub mount_points: Vec<String>,
+}
+
+impl VolumeInfo {
+    /// The folder paths on the volume start from, with a trailing backslash: its drive
+    /// root, else the first folder it's mounted at, else its GUID path.
+    pub fn root(&self) -> &str {
+        self.mount_points.first().or(self.guid_path.as_ref()).map(String::as_str).unwrap_or_default()
+    }
+
+    /// The path to open the volume itself with, e.g. for FSCTL_ENUM_USN_DATA.
+    pub fn device_path(&self) -> String {
+        match &self.guid_path {
+            Some(guid_path) => guid_path.trim_end_matches('\\').to_string(),
+            None => format!("\\\\.\\{}", self.root().trim_end_matches('\\')),
+        }
+    }
+
+    /// How the volume is stored in `Config::volumes`: its drive letter if it has one,
+    /// else its GUID path, which unlike a mount folder can't be reassigned.
+    pub fn config_key(&self) -> String {
+        match (self.id.letter(), &self.guid_path) {
+            (Some(letter), _) => letter.to_string(),
+            (None, Some(guid_path)) => guid_path.clone(),
+            (None, None) => self.root().to_string(),
+        }
+    }
+
+    /// Whether a `Config::volumes` entry names this volume: a drive letter (`D`, `D:`
+    /// or `D:\`), a folder it's mounted at or its GUID path, in any case.
+    pub fn matches(&self, key: &str) -> bool {
+        let key = key.trim_end_matches(['\\', ':']);
+        let same = |path: &String| path.trim_end_matches(['\\', ':']).eq_ignore_ascii_case(key);
+        !key.is_empty() && (self.mount_points.iter().any(same) || self.guid_path.as_ref().is_some_and(same))
+    }
+}
+
+impl std::fmt::Display for VolumeInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.root().trim_end_matches('\\'))
+    }
+}
@@ -0,0 +1,556 @@
+use crate::config::Config;
+use crate::mft_indexer::{begin_background_priority, Indexer};
+use crate::usn_monitor::{JournalLost, Monitor};
+pub use crate::volume::{VolumeId, VolumeInfo};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+use windows::Win32::Foundation::{ERROR_MORE_DATA, HWND, LPARAM, LRESULT, MAX_PATH, WPARAM};
+use windows::Win32::Storage::FileSystem::{
+    FindFirstVolumeW, FindNextVolumeW, FindVolumeClose, GetDiskFreeSpaceExW, GetLogicalDrives, GetVolumeInformationW,
+    GetVolumePathNamesForVolumeNameW,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::System::Threading::GetCurrentThreadId;
+use windows::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetMessageW, PostThreadMessageW, RegisterClassW,
+    DBT_DEVICEARRIVAL, DBT_DEVICEREMOVECOMPLETE, MSG, WINDOW_EX_STYLE, WM_DEVICECHANGE, WM_QUIT, WNDCLASSW, WS_OVERLAPPED,
+};
+use windows::core::{w, HSTRING};
+
+/// Every mounted volume that can be indexed: anything with a file system, including
+/// mapped network drives and volumes mounted in a folder or not at all, but not empty
+/// card readers or CD drives.
+pub fn indexable_volumes() -> Vec<VolumeInfo> {
+    mounted_volumes().into_iter().filter(is_indexable).collect()
+}
+
+/// Every volume the system knows of, whether or not it holds a file system, sorted by
+/// ID. Cheap enough to poll, as it doesn't touch the volumes themselves.
+fn mounted_volumes() -> Vec<VolumeInfo> {
+    let mut volumes: Vec<VolumeInfo> = volume_guid_paths().into_iter()
+        .filter_map(|guid_path| {
+            let mut mount_points = volume_mount_points(&guid_path);
+            mount_points.sort_by_key(|path| (!is_drive_root(path), path.to_ascii_uppercase()));
+            let id = match mount_points.first().filter(|path| is_drive_root(path)) {
+                Some(root) => VolumeId::from_letter(root.chars().next()?),
+                None => VolumeId::for_guid(&guid_path)?,
+            };
+            Some(VolumeInfo { id, guid_path: Some(guid_path), mount_points })
+        })
+        .collect();
+
+    // Letters with no local volume behind them, i.e. mapped network drives
+    for letter in drive_letters(unsafe { GetLogicalDrives() }) {
+        let id = VolumeId::from_letter(letter);
+        if !volumes.iter().any(|volume| volume.id == id) {
+            volumes.push(VolumeInfo { id, guid_path: None, mount_points: vec![format!("{}:\\", letter)] });
+        }
+    }
+    volumes.sort_unstable_by_key(|volume| volume.id);
+    volumes
+}
+
+fn drive_letters(mask: u32) -> impl Iterator<Item = char> {
+    (0..26u8)
+        .filter(move |i| mask & (1 << i) != 0)
+        .map(|i| (b'A' + i) as char)
+}
+
+fn is_drive_root(path: &str) -> bool {
+    path.len() == 3 && path.ends_with(":\\")
+}
+
+fn wide_to_string(wide: &[u16]) -> String {
+    let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+    String::from_utf16_lossy(&wide[..len])
+}
+
+/// `\\?\Volume{GUID}\` paths of every volume, lettered or not.
+fn volume_guid_paths() -> Vec<String> {
+    let mut paths = Vec::new();
+    let mut name = [0u16; MAX_PATH as usize];
+    let Ok(find) = (unsafe { FindFirstVolumeW(&mut name) }) else {
+        return paths;
+    };
+    loop {
+        paths.push(wide_to_string(&name));
+        if unsafe { FindNextVolumeW(find, &mut name) }.is_err() {
+            break;
+        }
+    }
+    unsafe {
+        let _ = FindVolumeClose(find);
+    }
+    paths
+}
+
+/// Drive roots and folders the volume is mounted at, each with a trailing backslash.
+fn volume_mount_points(guid_path: &str) -> Vec<String> {
+    let guid_path = HSTRING::from(guid_path);
+    let mut buffer = vec![0u16; MAX_PATH as usize];
+    let mut len = 0u32;
+    loop {
+        match unsafe { GetVolumePathNamesForVolumeNameW(&guid_path, Some(&mut buffer), &mut len) } {
+            Ok(()) => break,
+            Err(e) if e.code() == ERROR_MORE_DATA.to_hresult() => buffer.resize(len as usize, 0),
+            Err(_) => return Vec::new(),
+        }
+    }
+    // A list of NUL-terminated paths, ended by an empty one
+    buffer[..(len as usize).min(buffer.len())]
+        .split(|&c| c == 0)
+        .filter(|path| !path.is_empty())
+        .map(String::from_utf16_lossy)
+        .collect()
+}
+
+fn is_indexable(volume: &VolumeInfo) -> bool {
+    file_system_name(volume.root()).is_some()
+}
+
+/// Whether the volume has a USN journal to enumerate and monitor. Others (FAT32, exFAT,
+/// network shares) are indexed by walking their folders instead.
+pub fn has_usn_journal(volume: &VolumeInfo) -> bool {
+    matches!(file_system_name(volume.root()).as_deref(), Some("NTFS" | "ReFS"))
+}
+
+fn file_system_name(root: &str) -> Option<String> {
+    let mut fs_name = [0u16; 32];
+    unsafe {
+        GetVolumeInformationW(
+            &HSTRING::from(root),
+            None,
+            None,
+            None,
+            None,
+            Some(&mut fs_name),
+        )
+        .ok()?;
+    }
+    Some(wide_to_string(&fs_name))
+}
+
+/// Label and capacity of a mounted volume.
+pub struct DiskInfo {
+    pub label: String,
+    // e.g. "NTFS" or "exFAT"
+    pub file_system: String,
+    // Free space available to the current user, which quotas may make less than the volume's
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// None if the volume can't be queried, e.g. it was just removed.
+pub fn disk_info(volume: &VolumeInfo) -> Option<DiskInfo> {
+    let root = HSTRING::from(volume.root());
+    let mut label = [0u16; 261];
+    let mut file_system = [0u16; 32];
+    let mut free_bytes = 0u64;
+    let mut total_bytes = 0u64;
+    unsafe {
+        GetVolumeInformationW(&root, Some(&mut label), None, None, None, Some(&mut file_system)).ok()?;
+        GetDiskFreeSpaceExW(&root, Some(&mut free_bytes as *mut u64), Some(&mut total_bytes as *mut u64), None).ok()?;
+    }
+    Some(DiskInfo { label: wide_to_string(&label), file_system: wide_to_string(&file_system), free_bytes, total_bytes })
+}
+
+// Arrival and removal of lettered drives is announced by WM_DEVICECHANGE; volumes
+// mounted in folders or without a letter aren't, so those are polled for
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+// How often the device watcher checks for shutdown while waiting
+const DEVICE_WAIT_SLICE: Duration = Duration::from_millis(100);
+const MONITOR_RESTART_MIN: Duration = Duration::from_secs(1);
+const MONITOR_RESTART_MAX: Duration = Duration::from_secs(60);
+
+/// Sleeps for `duration` unless cancelled first. Returns false if cancelled.
+fn wait_or_cancel(token: &CancellationToken, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if token.is_cancelled() {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    !token.is_cancelled()
+}
+
+/// Runs the USN monitor for one volume, restarting it with exponential backoff whenever
+/// it fails (e.g. the drive was briefly removed) until `token` is cancelled.
+fn supervise_monitor(indexer: Arc<Indexer>, volume: VolumeInfo, poll_interval: Duration, token: CancellationToken) {
+    let mut backoff = MONITOR_RESTART_MIN;
+    loop {
+        let started = Instant::now();
+        let monitor = Monitor::new(Arc::clone(&indexer), poll_interval);
+        match monitor.start_monitoring(&volume, &token) {
+            Ok(()) => return,
+            // Restarting would only pick up from now; the rebuild starts a fresh monitor
+            Err(e) if e.is::<JournalLost>() => {
+                warn!(%volume, error = %e, "Reindexing to catch up");
+                indexer.log_error(format!("{}: {}; reindexing", volume, e));
+                indexer.report_lost_journal(volume.id);
+                return;
+            }
+            Err(e) => {
+                error!(%volume, error = %e, "USN monitor failed");
+                indexer.log_error(format!("{}: USN monitor failed: {}", volume, e));
+            }
+        }
+
+        // A monitor that ran for a while before failing starts over with a short delay
+        if started.elapsed() > MONITOR_RESTART_MAX {
+            backoff = MONITOR_RESTART_MIN;
+        }
+        warn!(%volume, backoff_ms = backoff.as_millis() as u64, "Restarting USN monitor");
+        if !wait_or_cancel(&token, backoff) {
+            return;
+        }
+        backoff = (backoff * 2).min(MONITOR_RESTART_MAX);
+    }
+}
+
+thread_local! {
+    // Wakes `VolumeManager::watch_devices` from the device window's procedure
+    static DEVICE_EVENTS: RefCell<Option<Sender<()>>> = const { RefCell::new(None) };
+}
+
+unsafe extern "system" fn device_window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if msg == WM_DEVICECHANGE && matches!(wparam.0 as u32, DBT_DEVICEARRIVAL | DBT_DEVICEREMOVECOMPLETE) {
+        DEVICE_EVENTS.with(|events| {
+            if let Some(events) = &*events.borrow() {
+                let _ = events.send(());
+            }
+        });
+        return LRESULT(1);
+    }
+    DefWindowProcW(hwnd, msg, wparam, lparam)
+}
+
+/// Creates a hidden window for WM_DEVICECHANGE, which is only broadcast to top-level
+/// windows, and pumps its messages until WM_QUIT. Reports the thread's ID through
+/// `ready` once the window exists, or None if it couldn't be created.
+fn run_device_window(events: Sender<()>, ready: Sender<Option<u32>>) {
+    DEVICE_EVENTS.with(|slot| *slot.borrow_mut() = Some(events));
+    unsafe {
+        let instance = GetModuleHandleW(None).unwrap_or_default();
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(device_window_proc),
+            hInstance: instance.into(),
+            lpszClassName: w!("RivetDeviceWatcher"),
+            ..Default::default()
+        };
+        RegisterClassW(&class);
+        let hwnd = match CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            w!("RivetDeviceWatcher"),
+            w!("Rivet device watcher"),
+            WS_OVERLAPPED,
+            0,
+            0,
+            0,
+            0,
+            None,
+            None,
+            instance,
+            None,
+        ) {
+            Ok(hwnd) => hwnd,
+            Err(e) => {
+                warn!(error = %e, "Failed to create device notification window");
+                let _ = ready.send(None);
+                return;
+            }
+        };
+        let _ = ready.send(Some(GetCurrentThreadId()));
+
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+            DispatchMessageW(&msg);
+        }
+        let _ = DestroyWindow(hwnd);
+    }
+}
+
+struct VolumeWorkers {
+    token: CancellationToken,
+    // Runs the volume's monitor and index passes; exits once `token` is cancelled
+    thread: JoinHandle<()>,
+}
+
+/// Owns the indexing thread and supervised USN monitor for each configured volume.
+/// All volumes share one `Indexer`; each has a child token of the app's shutdown token.
+/// Only volumes that are actually mounted are worked on, so drives plugged in or pulled
+/// out while running are picked up by `refresh_devices`.
+pub struct VolumeManager {
+    indexer: Arc<Indexer>,
+    config: Arc<RwLock<Config>>,
+    shutdown: CancellationToken,
+    active: HashMap<VolumeId, VolumeWorkers>,
+    // Volumes unplugged while indexed, whose records are hidden until they come back.
+    // Their workers are cancelled but kept so a return can wait for them to exit.
+    offline: HashMap<VolumeId, (VolumeInfo, VolumeWorkers)>,
+    // Threads purging the records of stopped volumes once their workers exit. A volume
+    // started again waits for its purge first, so the purge can't remove the new records.
+    stopping: HashMap<VolumeId, JoinHandle<()>>,
+    // Every volume as of the last device check, indexable or not
+    known: Vec<VolumeInfo>,
+    // Mounted indexable volumes as of the last device check
+    mounted: Vec<VolumeInfo>,
+    // Indexable volumes that appeared while running and aren't in the config
+    arrived: Vec<VolumeId>,
+}
+
+impl VolumeManager {
+    pub fn new(indexer: Arc<Indexer>, config: Arc<RwLock<Config>>, shutdown: CancellationToken) -> Self {
+        let known = mounted_volumes();
+        Self {
+            indexer,
+            config,
+            shutdown,
+            active: HashMap::new(),
+            offline: HashMap::new(),
+            stopping: HashMap::new(),
+            mounted: known.iter().filter(|volume| is_indexable(volume)).cloned().collect(),
+            known,
+            arrived: Vec::new(),
+        }
+    }
+
+    /// Refreshes on drive arrival and removal until `token` is cancelled, and polls every
+    /// few seconds for the changes Windows doesn't announce.
+    pub fn watch_devices(manager: Arc<Mutex<Self>>, token: CancellationToken) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let (events_tx, events) = channel();
+            let (ready_tx, ready) = channel();
+            let window = std::thread::spawn(move || run_device_window(events_tx, ready_tx));
+            let window_thread_id = ready.recv().ok().flatten();
+
+            let mut last_refresh = Instant::now();
+            while !token.is_cancelled() {
+                let notified = match events.recv_timeout(DEVICE_WAIT_SLICE) {
+                    Ok(()) => true,
+                    Err(RecvTimeoutError::Timeout) => false,
+                    // No window, so polling is all there is
+                    Err(RecvTimeoutError::Disconnected) => {
+                        std::thread::sleep(DEVICE_WAIT_SLICE);
+                        false
+                    }
+                };
+                if notified || last_refresh.elapsed() >= DEVICE_POLL_INTERVAL {
+                    // One drive sends a burst of notifications; a single refresh covers them
+                    while events.try_recv().is_ok() {}
+                    manager.lock().unwrap().refresh_devices();
+                    last_refresh = Instant::now();
+                }
+                manager.lock().unwrap().rebuild_lost_journals();
+            }
+
+            if let Some(thread_id) = window_thread_id {
+                unsafe {
+                    let _ = PostThreadMessageW(thread_id, WM_QUIT, WPARAM(0), LPARAM(0));
+                }
+            }
+            let _ = window.join();
+        })
+    }
+
+    /// Rechecks which indexable volumes are mounted and syncs if anything changed. Only
+    /// volumes that came, went or moved are queried, so slow network drives aren't hit
+    /// each poll.
+    pub fn refresh_devices(&mut self) {
+        let known = mounted_volumes();
+        if known == self.known {
+            return;
+        }
+
+        for volume in &self.mounted {
+            if known.iter().any(|other| other.id == volume.id) {
+                continue;
+            }
+            info!(%volume, "Volume removed");
+            // Kept rather than purged, so a drive that's briefly unplugged doesn't lose
+            // its place in results for good
+            if let Some(workers) = self.active.remove(&volume.id) {
+                workers.token.cancel();
+                self.indexer.set_offline(volume.id);
+                self.offline.insert(volume.id, (volume.clone(), workers));
+            }
+        }
+        let mut mounted = Vec::new();
+        // Volumes still mounted under the same ID but at different places
+        let mut moved = Vec::new();
+        for volume in &known {
+            if self.mounted.contains(volume) {
+                mounted.push(volume.clone());
+            } else if !self.known.contains(volume) && is_indexable(volume) {
+                if self.mounted.iter().any(|other| other.id == volume.id) {
+                    info!(%volume, "Volume mount points changed");
+                    moved.push(volume.id);
+                } else {
+                    info!(%volume, "Volume arrived");
+                    if !self.arrived.contains(&volume.id) {
+                        self.arrived.push(volume.id);
+                    }
+                }
+                mounted.push(volume.clone());
+            }
+        }
+        self.known = known;
+        self.mounted = mounted;
+        // Paths start from the volume's mount point, so those indexed under the old one are stale
+        for id in moved {
+            self.rebuild(id);
+        }
+        self.sync();
+    }
+
+    /// Starts work for enabled volumes that are mounted, and stops and purges any that
+    /// were disabled or removed. Volumes that come back from being offline are purged
+    /// and indexed afresh.
+    pub fn sync(&mut self) {
+        let (wanted, dropped) = {
+            let config = self.config.read().unwrap();
+            let wanted: Vec<VolumeId> = self.mounted.iter()
+                .filter(|volume| self.is_wanted(&config, volume))
+                .map(|volume| volume.id)
+                .collect();
+            let dropped: Vec<VolumeId> = self.offline.values()
+                .filter(|(volume, _)| !self.is_wanted(&config, volume))
+                .map(|(volume, _)| volume.id)
+                .collect();
+            (wanted, dropped)
+        };
+
+        self.stopping.retain(|_, purge| !purge.is_finished());
+        let removed: Vec<VolumeId> = self.active.keys().copied().filter(|id| !wanted.contains(id)).collect();
+        for id in removed {
+            self.stop(id);
+        }
+        for id in dropped {
+            if let Some((_, workers)) = self.offline.remove(&id) {
+                self.purge(id, workers);
+            }
+        }
+        for id in wanted {
+            if !self.active.contains_key(&id) {
+                let previous = self.offline.remove(&id).map(|(_, workers)| workers);
+                self.start(id, previous);
+            }
+        }
+    }
+
+    fn is_wanted(&self, config: &Config, volume: &VolumeInfo) -> bool {
+        let ignored = config.ignored_volumes.iter().any(|key| volume.matches(key));
+        config.volumes.iter().any(|key| volume.matches(key))
+            || (config.index_new_volumes && !ignored && self.arrived.contains(&volume.id))
+    }
+
+    /// The volumes being indexed, sorted by ID.
+    pub fn volumes(&self) -> Vec<VolumeInfo> {
+        self.mounted.iter().filter(|volume| self.active.contains_key(&volume.id)).cloned().collect()
+    }
+
+    /// Throws away a volume's records and indexes it from scratch.
+    pub fn rebuild(&mut self, id: VolumeId) {
+        if let Some(previous) = self.active.remove(&id) {
+            previous.token.cancel();
+            self.start(id, Some(previous));
+        }
+    }
+
+    /// Rebuilds the volumes whose monitor found changes missing from the journal.
+    fn rebuild_lost_journals(&mut self) {
+        for id in self.indexer.take_lost_journals() {
+            info!(volume = %id, "Rebuilding after USN journal loss");
+            self.rebuild(id);
+        }
+    }
+
+    pub fn rebuild_all(&mut self) {
+        for volume in self.volumes() {
+            self.rebuild(volume.id);
+        }
+    }
+
+    /// Spawns the volume's worker. When replacing `previous`, the new worker first waits
+    /// for it to exit and purges its records, so nothing stale races the fresh index. It
+    /// also waits for a purge still pending from `stop`.
+    fn start(&mut self, id: VolumeId, previous: Option<VolumeWorkers>) {
+        let Some(volume) = self.mounted.iter().find(|volume| volume.id == id).cloned() else {
+            return;
+        };
+        let purging = self.stopping.remove(&id);
+        let token = self.shutdown.child_token();
+        let (buffer_size, threads, fetch_sizes, poll_interval) = {
+            let config = self.config.read().unwrap();
+            self.indexer.set_low_priority(config.low_priority_indexing);
+            (config.mft_buffer_size(), config.size_fetch_threads, config.fetch_sizes_eagerly, config.monitor_poll_interval())
+        };
+
+        let indexer = Arc::clone(&self.indexer);
+        let worker_token = token.clone();
+        let thread = std::thread::spawn(move || {
+            if let Some(purging) = purging {
+                let _ = purging.join();
+            }
+            if let Some(previous) = previous {
+                let _ = previous.thread.join();
+                indexer.remove_volume(id);
+            }
+            if worker_token.is_cancelled() {
+                return;
+            }
+
+            std::thread::scope(|scope| {
+                scope.spawn(|| supervise_monitor(Arc::clone(&indexer), volume.clone(), poll_interval, worker_token.clone()));
+                // The monitor above keeps normal priority so changes still show up promptly
+                if indexer.is_low_priority() {
+                    begin_background_priority();
+                }
+
+                // index_volume logs its own outcome
+                if indexer.index_volume(&volume, buffer_size, &worker_token).is_err() {
+                    return;
+                }
+                let started = Instant::now();
+                indexer.build_name_index();
+                info!(%volume, elapsed_ms = started.elapsed().as_millis() as u64, "Name index built");
+                if fetch_sizes {
+                    let started = Instant::now();
+                    info!(%volume, "Size fetch started");
+                    indexer.fetch_sizes(id, threads, &worker_token);
+                    info!(%volume, elapsed_ms = started.elapsed().as_millis() as u64, "Size fetch complete");
+                    if !worker_token.is_cancelled() {
+                        indexer.compute_dir_sizes(id);
+                    }
+                }
+            });
+        });
+
+        self.active.insert(id, VolumeWorkers { token, thread });
+    }
+
+    fn stop(&mut self, id: VolumeId) {
+        let Some(workers) = self.active.remove(&id) else {
+            return;
+        };
+        workers.token.cancel();
+        self.purge(id, workers);
+    }
+
+    /// Drops the volume's records once its cancelled `workers` exit, so a late insert
+    /// can't resurrect them. The purge is kept in `stopping` until it's done.
+    fn purge(&mut self, id: VolumeId, workers: VolumeWorkers) {
+        let indexer = Arc::clone(&self.indexer);
+        let purge = std::thread::spawn(move || {
+            let _ = workers.thread.join();
+            indexer.remove_volume(id);
+        });
+        self.stopping.insert(id, purge);
+    }
+}